@@ -9,11 +9,14 @@ use flux::semantic::types::{
 };
 use flux::semantic::walk::Visitor as SemanticVisitor;
 use lspower::lsp;
+use serde::{Deserialize, Serialize};
 
 use crate::lang;
+use crate::visitors::ast::NodeFinderNode;
 use crate::visitors::semantic::{
-    FunctionFinderVisitor, Import, ImportFinderVisitor,
-    ObjectFunctionFinderVisitor,
+    BucketLiteralVisitor, FunctionFinderVisitor, Import,
+    ImportFinderVisitor, ObjectFunctionFinderVisitor,
+    PackageNodeFinderVisitor,
 };
 
 pub fn get_imports(
@@ -64,6 +67,7 @@ pub(crate) fn walk_package(
                 MonoType::Fun(f) => {
                     list.push(Box::new(FunctionResult {
                         name: head.k.clone().to_string(),
+                        package: package.into(),
                         signature: create_function_signature(f),
                     }));
                 }
@@ -114,6 +118,85 @@ impl Completable for FunctionResult {
             sort_text: Some(self.name.clone()),
             text_edit: None,
             command: None,
+            data: CompletionItemData::member(
+                &self.package,
+                &self.name,
+            ),
+            insert_text_mode: None,
+            tags: None,
+        }
+    }
+}
+
+/// The data attached to completion items for stdlib packages and their members.
+///
+/// Documentation is only looked up when the client resolves an item, which
+/// keeps completion responses small.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct CompletionItemData {
+    pub package: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member: Option<String>,
+}
+
+impl CompletionItemData {
+    pub(crate) fn package(path: &str) -> Option<serde_json::Value> {
+        serde_json::to_value(Self {
+            package: path.into(),
+            member: None,
+        })
+        .ok()
+    }
+
+    pub(crate) fn member(
+        path: &str,
+        name: &str,
+    ) -> Option<serde_json::Value> {
+        serde_json::to_value(Self {
+            package: path.into(),
+            member: Some(name.into()),
+        })
+        .ok()
+    }
+}
+
+/// Get the markdown documentation for a completion item from its data.
+pub(crate) fn resolve_documentation(
+    data: &serde_json::Value,
+) -> Option<String> {
+    let data: CompletionItemData =
+        serde_json::from_value(data.clone()).ok()?;
+    let package = lang::package_doc(&data.package)?;
+    match &data.member {
+        Some(member) => Some(package.member(member)?.markdown()),
+        None => Some(package.markdown()),
+    }
+}
+
+impl Completable for CompletionVarResult {
+    fn completion_item(
+        &self,
+        _imports: &[Import],
+    ) -> lsp::CompletionItem {
+        lsp::CompletionItem {
+            label: format!("{} (self)", self.name),
+            additional_text_edits: None,
+            commit_characters: None,
+            deprecated: None,
+            detail: Some(self.detail()),
+            documentation: Some(lsp::Documentation::String(
+                "from self".to_string(),
+            )),
+            filter_text: Some(self.name.clone()),
+            insert_text: Some(self.name.clone()),
+            insert_text_format: Some(
+                lsp::InsertTextFormat::PLAIN_TEXT,
+            ),
+            kind: Some(lsp::CompletionItemKind::VARIABLE),
+            preselect: None,
+            sort_text: Some(self.name.clone()),
+            text_edit: None,
+            command: None,
             data: None,
             insert_text_mode: None,
             tags: None,
@@ -121,35 +204,1986 @@ impl Completable for FunctionResult {
     }
 }
 
-impl Completable for CompletionVarResult {
-    fn completion_item(
-        &self,
-        _imports: &[Import],
-    ) -> lsp::CompletionItem {
-        lsp::CompletionItem {
-            label: format!("{} (self)", self.name),
-            additional_text_edits: None,
-            commit_characters: None,
-            deprecated: None,
-            detail: Some(self.detail()),
-            documentation: Some(lsp::Documentation::String(
-                "from self".to_string(),
-            )),
-            filter_text: Some(self.name.clone()),
-            insert_text: Some(self.name.clone()),
-            insert_text_format: Some(
-                lsp::InsertTextFormat::PLAIN_TEXT,
-            ),
-            kind: Some(lsp::CompletionItemKind::VARIABLE),
-            preselect: None,
-            sort_text: Some(self.name.clone()),
-            text_edit: None,
-            command: None,
-            data: None,
-            insert_text_mode: None,
-            tags: None,
+/// Find the type of the expression at a location in the ast.
+struct ExpressionTypeFinderVisitor<'a> {
+    location: &'a flux::ast::SourceLocation,
+    typ: Option<MonoType>,
+}
+
+impl<'a> SemanticVisitor<'a> for ExpressionTypeFinderVisitor<'_> {
+    fn visit(
+        &mut self,
+        node: flux::semantic::walk::Node<'a>,
+    ) -> bool {
+        let loc = node.loc();
+        if loc.file == self.location.file
+            && loc.start == self.location.start
+            && loc.end == self.location.end
+        {
+            // Keep the innermost node, e.g. the expression rather than the
+            // statement wrapping it.
+            if let Some(typ) = node.type_of() {
+                self.typ = Some(typ);
+            }
+        }
+        true
+    }
+}
+
+/// The inferred type of an expression in the ast.
+pub(crate) fn expression_type(
+    pkg: &flux::semantic::nodes::Package,
+    expr: &Expression,
+) -> Option<MonoType> {
+    crate::walk_semantic_package!(
+        ExpressionTypeFinderVisitor {
+            location: &expr.base().location,
+            typ: None,
+        },
+        pkg
+    )
+    .typ
+}
+
+/// The file containing a node, found through its ancestors.
+fn node_file<'a>(
+    node: &NodeFinderNode<'a>,
+) -> Option<&'a flux::ast::File> {
+    let mut ancestor = Some(node);
+    while let Some(current) = ancestor {
+        if let flux::ast::walk::Node::File(file) = &current.node {
+            return Some(file);
+        }
+        ancestor = current.parent.as_deref();
+    }
+    None
+}
+
+/// Whether a node is where a new top-level statement can be written: between
+/// statements, or an identifier that is a statement of the file on its own,
+/// e.g. `fr` while typing `from`.
+pub(crate) fn is_statement_position(
+    node: Option<&NodeFinderNode>,
+) -> bool {
+    let node = match node {
+        Some(node) => node,
+        None => return true,
+    };
+    match &node.node {
+        flux::ast::walk::Node::File(_) => true,
+        flux::ast::walk::Node::Identifier(_) => {
+            match node.parent.as_deref() {
+                Some(NodeFinderNode {
+                    node: flux::ast::walk::Node::ExprStmt(_),
+                    parent: Some(statement_parent),
+                }) => matches!(
+                    statement_parent.node,
+                    flux::ast::walk::Node::File(_)
+                ),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// The input of the innermost pipe stage whose call contains a node, e.g.
+/// `from(bucket: "b")` for a node in `from(bucket: "b") |> keep(columns: [""])`.
+fn stage_input<'a>(
+    node: &NodeFinderNode<'a>,
+) -> Option<&'a Expression> {
+    let start = node.node.base().location.start;
+    let mut ancestor = node.parent.as_deref();
+    while let Some(current) = ancestor {
+        if let flux::ast::walk::Node::PipeExpr(pipe) = &current.node {
+            let call = &pipe.call.base.location;
+            if call.start <= start && start <= call.end {
+                return Some(&pipe.argument);
+            }
+        }
+        ancestor = current.parent.as_deref();
+    }
+    None
+}
+
+fn column_item(index: usize, column: &str) -> lsp::CompletionItem {
+    lsp::CompletionItem {
+        label: column.into(),
+        detail: Some("Column".into()),
+        filter_text: Some(column.into()),
+        insert_text: Some(column.into()),
+        insert_text_format: Some(lsp::InsertTextFormat::PLAIN_TEXT),
+        kind: Some(lsp::CompletionItemKind::FIELD),
+        // Keep the order the columns were inferred in, e.g. `_time` first.
+        sort_text: Some(format!("{:03}", index)),
+        ..lsp::CompletionItem::default()
+    }
+}
+
+/// Complete the columns of the tables read by a pipe stage, inferred statically
+/// from its pipeline: the column names in the `columns` array of a stage, e.g.
+/// `keep(columns: ["_time", ""])`, and the fields of a record parameter, e.g.
+/// `r.` in `filter(fn: (r) => r.)`.
+pub(crate) fn complete_pipeline_columns(
+    pkg: &flux::semantic::nodes::Package,
+    node: &NodeFinderNode,
+) -> Option<Vec<lsp::CompletionItem>> {
+    let parent = node.parent.as_deref();
+    let pipeline =
+        crate::pipeline::Pipeline::new(pkg, node_file(node));
+
+    // The strings of an array that is the `columns` argument of a call.
+    let array = match (&node.node, parent.map(|parent| &parent.node))
+    {
+        (
+            flux::ast::walk::Node::StringLit(lit),
+            Some(flux::ast::walk::Node::ArrayExpr(array)),
+        ) => Some((*array, Some(*lit), parent?.parent.as_deref())),
+        (flux::ast::walk::Node::ArrayExpr(array), _) => {
+            Some((*array, None, parent))
+        }
+        _ => None,
+    };
+    if let Some((array, lit, property)) = array {
+        match property.map(|property| &property.node) {
+            Some(flux::ast::walk::Node::Property(property))
+                if property_key_name(&property.key) == "columns"
+                    && matches!(
+                        &property.value,
+                        Some(Expression::Array(value))
+                            if std::ptr::eq(value.as_ref(), array)
+                    ) => {}
+            _ => return None,
+        }
+        let columns = pipeline.schema(stage_input(node)?)?.columns;
+        let listed: Vec<&str> = array
+            .elements
+            .iter()
+            .filter_map(|element| match &element.expression {
+                Expression::StringLit(element)
+                    if !lit.map_or(false, |lit| {
+                        std::ptr::eq(element, lit)
+                    }) =>
+                {
+                    Some(element.value.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+        return Some(
+            columns
+                .iter()
+                .filter(|column| !listed.contains(&column.as_str()))
+                .enumerate()
+                .map(|(index, column)| {
+                    let mut item = column_item(index, column);
+                    match lit {
+                        // Replace the contents of the string, leaving the quotes
+                        // in place.
+                        Some(lit) => {
+                            let mut range: lsp::Range =
+                                lit.base.location.clone().into();
+                            range.start.character += 1;
+                            range.end.character =
+                                range.end.character.saturating_sub(1);
+                            item.insert_text = None;
+                            item.text_edit =
+                                Some(lsp::CompletionTextEdit::Edit(
+                                    lsp::TextEdit {
+                                        range,
+                                        new_text: column.clone(),
+                                    },
+                                ));
+                        }
+                        None => {
+                            item.insert_text =
+                                Some(format!(r#""{}""#, column));
+                        }
+                    }
+                    item
+                })
+                .collect(),
+        );
+    }
+
+    // The fields of a function parameter, e.g. `r.` in `filter(fn: (r) => r.)`.
+    let member = match (&node.node, parent.map(|parent| &parent.node))
+    {
+        (
+            flux::ast::walk::Node::Identifier(_),
+            Some(flux::ast::walk::Node::MemberExpr(member)),
+        )
+        | (flux::ast::walk::Node::MemberExpr(member), _) => member,
+        _ => return None,
+    };
+    match &member.object {
+        Expression::Identifier(object)
+            if is_function_parameter(node, &object.name) => {}
+        _ => return None,
+    }
+    let columns = pipeline.schema(stage_input(node)?)?.columns;
+    let mut items: Vec<lsp::CompletionItem> = columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| column_item(index, column))
+        .collect();
+    // Fields that are used, or added by the stage itself, are known from the
+    // inferred type of the parameter.
+    for item in complete_record_members(pkg, &member.object) {
+        if !columns.contains(&item.label) {
+            items.push(item);
+        }
+    }
+    Some(items)
+}
+
+/// A postfix template: the member typed after an expression, and the snippet
+/// that replaces the expression, where `{}` is the expression.
+struct PostfixTemplate {
+    name: &'static str,
+    description: &'static str,
+    applies: fn(&PostfixReceiver) -> bool,
+    snippet: fn(&str) -> String,
+}
+
+/// The expression a postfix template is written after.
+struct PostfixReceiver {
+    typ: Option<MonoType>,
+    /// Whether the expression is a pipeline, e.g. `from(bucket: "b") |> range(start: -1h)`.
+    pipeline: bool,
+    /// Whether the expression is a statement on its own.
+    statement: bool,
+}
+
+impl PostfixReceiver {
+    fn is_stream(&self) -> bool {
+        match &self.typ {
+            Some(MonoType::Collection(collection)) => {
+                collection.collection == CollectionType::Stream
+            }
+            Some(_) => self.pipeline,
+            None => true,
+        }
+    }
+}
+
+/// Add a pipe stage to an expression, on a new line when the expression spans
+/// several lines already.
+fn pipe_stage(receiver: &str, stage: &str) -> String {
+    if receiver.contains('\n') {
+        format!("{}\n  |> {}", receiver, stage)
+    } else {
+        format!("{} |> {}", receiver, stage)
+    }
+}
+
+/// Escape the characters of a snippet that would otherwise be read as tabstops,
+/// variables or escapes, e.g. in the interpolated string `"${r.host}"`.
+fn escape_snippet(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '$' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+const POSTFIX_TEMPLATES: &[PostfixTemplate] = &[
+    PostfixTemplate {
+        name: "yield",
+        description: "Yield the tables of the expression",
+        applies: PostfixReceiver::is_stream,
+        snippet: |receiver| {
+            pipe_stage(receiver, "yield(name: \"$1\")")
+        },
+    },
+    PostfixTemplate {
+        name: "filter",
+        description: "Filter the rows of the expression",
+        applies: PostfixReceiver::is_stream,
+        snippet: |receiver| {
+            pipe_stage(receiver, "filter(fn: (r) => $1)")
+        },
+    },
+    PostfixTemplate {
+        name: "var",
+        description: "Assign the expression to a variable",
+        applies: |receiver| receiver.statement,
+        snippet: |receiver| format!("${{1:name}} = {}", receiver),
+    },
+    PostfixTemplate {
+        name: "if",
+        description: "Use the expression as a condition",
+        applies: |receiver| {
+            matches!(
+                receiver.typ,
+                Some(MonoType::Builtin(BuiltinType::Bool))
+            )
+        },
+        snippet: |receiver| {
+            format!("if {} then $1 else $2", receiver)
+        },
+    },
+];
+
+/// Complete postfix templates after an expression, e.g. `.yield` after a pipeline,
+/// with an edit that replaces the whole expression. Templates with the name of a
+/// member of the expression aren't completed.
+pub(crate) fn complete_postfix_templates(
+    pkg: &flux::semantic::nodes::Package,
+    contents: &str,
+    node: &NodeFinderNode,
+    member: &flux::ast::MemberExpr,
+    members: &[lsp::CompletionItem],
+) -> Vec<lsp::CompletionItem> {
+    if let Expression::Identifier(identifier) = &member.object {
+        if get_imports(pkg)
+            .iter()
+            .any(|import| import.name == identifier.name)
+        {
+            return vec![];
+        }
+    }
+    let mut member_node = Some(node);
+    while let Some(current) = member_node {
+        if let flux::ast::walk::Node::MemberExpr(_) = current.node {
+            break;
+        }
+        member_node = current.parent.as_deref();
+    }
+    let member_node = match member_node {
+        Some(member_node) => member_node,
+        None => return vec![],
+    };
+
+    let object: lsp::Range =
+        member.object.base().location.clone().into();
+    let end: lsp::Range = member.base.location.clone().into();
+    // A member after the last stage of a pipeline, e.g. `a |> b().yield`, is parsed
+    // as the callee of that stage, so the receiver is the whole pipeline.
+    let mut start = object.start;
+    let mut receiver_node = member_node;
+    let mut pipeline =
+        matches!(member.object, Expression::PipeExpr(_));
+    if let Some(call_node) = member_node.parent.as_deref() {
+        if let (
+            flux::ast::walk::Node::CallExpr(call),
+            Some(pipe_node),
+        ) = (&call_node.node, call_node.parent.as_deref())
+        {
+            if let flux::ast::walk::Node::PipeExpr(pipe) =
+                &pipe_node.node
+            {
+                if call.callee.base().location == member.base.location
+                    && pipe.call.base.location == call.base.location
+                {
+                    let range: lsp::Range =
+                        pipe.base.location.clone().into();
+                    start = range.start;
+                    receiver_node = pipe_node;
+                    pipeline = true;
+                }
+            }
+        }
+    }
+    let receiver_text = match crate::lsp::text_in_range(
+        contents,
+        &lsp::Range {
+            start,
+            end: object.end,
+        },
+    ) {
+        Some(text) => text,
+        None => return vec![],
+    };
+    let receiver = PostfixReceiver {
+        typ: if pipeline && start != object.start {
+            None
+        } else {
+            expression_type(pkg, &member.object)
+        },
+        pipeline,
+        statement: matches!(
+            receiver_node
+                .parent
+                .as_deref()
+                .map(|parent| &parent.node),
+            Some(flux::ast::walk::Node::ExprStmt(_))
+        ),
+    };
+    let range = lsp::Range {
+        start,
+        end: end.end,
+    };
+    let snippet_receiver = escape_snippet(&receiver_text);
+
+    POSTFIX_TEMPLATES
+        .iter()
+        .filter(|template| (template.applies)(&receiver))
+        .filter(|template| {
+            !members.iter().any(|item| {
+                item.filter_text.as_ref().unwrap_or(&item.label)
+                    == template.name
+            })
+        })
+        .map(|template| lsp::CompletionItem {
+            label: template.name.into(),
+            detail: Some(template.description.into()),
+            // Clients filter with the text of the edit range, which starts at
+            // the receiver.
+            filter_text: Some(format!(
+                "{}.{}",
+                receiver_text, template.name
+            )),
+            insert_text_format: Some(lsp::InsertTextFormat::SNIPPET),
+            kind: Some(lsp::CompletionItemKind::SNIPPET),
+            sort_text: Some(format!("~{}", template.name)),
+            text_edit: Some(lsp::CompletionTextEdit::Edit(
+                lsp::TextEdit {
+                    range,
+                    new_text: (template.snippet)(&snippet_receiver),
+                },
+            )),
+            ..lsp::CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Complete the members of an expression from its inferred record type, e.g. the
+/// fields of `r` in `map(fn: (r) => r.)` or of a call result in `f().`.
+pub(crate) fn complete_record_members(
+    pkg: &flux::semantic::nodes::Package,
+    object: &Expression,
+) -> Vec<lsp::CompletionItem> {
+    let record = match expression_type(pkg, object) {
+        Some(MonoType::Record(record)) => record,
+        _ => return vec![],
+    };
+    record
+        .fields()
+        .map(|field| {
+            let name = field.k.to_string();
+            let (kind, detail) = match &field.v {
+                MonoType::Fun(function) => (
+                    lsp::CompletionItemKind::FUNCTION,
+                    create_function_signature(function),
+                ),
+                typ => {
+                    (lsp::CompletionItemKind::FIELD, typ.to_string())
+                }
+            };
+            lsp::CompletionItem {
+                label: name.clone(),
+                detail: Some(detail),
+                filter_text: Some(name.clone()),
+                insert_text: Some(name.clone()),
+                insert_text_format: Some(
+                    lsp::InsertTextFormat::PLAIN_TEXT,
+                ),
+                kind: Some(kind),
+                sort_text: Some(name),
+                ..lsp::CompletionItem::default()
+            }
+        })
+        .collect()
+}
+
+/// The kind of schema information that completes a node.
+pub(crate) enum SchemaQueryKind {
+    Measurements,
+    Fields,
+    TagKeys,
+    TagValues(String),
+}
+
+/// A query for the schema information that completes a node, e.g. the
+/// measurements of the bucket read by the pipeline in
+/// `from(bucket: "telegraf") |> filter(fn: (r) => r._measurement == "")`.
+pub(crate) struct SchemaQuery {
+    pub bucket: String,
+    pub measurement: Option<String>,
+    pub kind: SchemaQueryKind,
+    /// The contents of the string literal being completed, if any.
+    range: Option<lsp::Range>,
+}
+
+/// The name of the property accessed by a member expression, e.g. `host` in both
+/// `r.host` and `r["host"]`.
+fn member_property(expr: &Expression) -> Option<&str> {
+    match expr {
+        Expression::Member(member) => match &member.property {
+            PropertyKey::Identifier(ident) => Some(&ident.name),
+            PropertyKey::StringLit(lit) => Some(&lit.value),
+        },
+        _ => None,
+    }
+}
+
+/// The string compared with a column in `r.column == "value"`, along with the column.
+fn column_comparison(
+    binary: &flux::ast::BinaryExpr,
+) -> Option<(&str, &flux::ast::StringLit)> {
+    if !matches!(
+        binary.operator,
+        flux::ast::Operator::EqualOperator
+            | flux::ast::Operator::NotEqualOperator
+    ) {
+        return None;
+    }
+    match (&binary.left, &binary.right) {
+        (member, Expression::StringLit(lit))
+        | (Expression::StringLit(lit), member) => {
+            Some((member_property(member)?, lit))
+        }
+        _ => None,
+    }
+}
+
+/// Find the measurement selected by a `r._measurement == "..."` predicate.
+#[derive(Default)]
+struct MeasurementFinderVisitor<'a> {
+    measurement: Option<&'a str>,
+}
+
+impl<'a> flux::ast::walk::Visitor<'a>
+    for MeasurementFinderVisitor<'a>
+{
+    fn visit(&mut self, node: flux::ast::walk::Node<'a>) -> bool {
+        if let flux::ast::walk::Node::BinaryExpr(binary) = node {
+            if let Some(("_measurement", lit)) =
+                column_comparison(binary)
+            {
+                self.measurement.get_or_insert(&lit.value);
+            }
+        }
+        self.measurement.is_none()
+    }
+}
+
+/// The bucket and measurement read by a pipeline, e.g. `telegraf` and `cpu` in
+/// `from(bucket: "telegraf") |> filter(fn: (r) => r._measurement == "cpu")`.
+fn pipeline_source(
+    mut expr: &Expression,
+) -> Option<(String, Option<String>)> {
+    let mut measurement = None;
+    loop {
+        match expr {
+            Expression::PipeExpr(pipe) => {
+                let mut visitor = MeasurementFinderVisitor::default();
+                flux::ast::walk::walk(
+                    &mut visitor,
+                    flux::ast::walk::Node::CallExpr(&pipe.call),
+                );
+                if let Some(found) = visitor.measurement {
+                    measurement = Some(found.to_string());
+                }
+                expr = &pipe.argument;
+            }
+            Expression::Call(call) => {
+                match &call.callee {
+                    Expression::Identifier(ident)
+                        if ident.name == "from" => {}
+                    _ => return None,
+                }
+                let bucket =
+                    call.arguments.iter().find_map(|argument| {
+                        match argument {
+                            Expression::Object(object) => {
+                                object.properties.iter().find_map(
+                                    |property| match (
+                                        &property.key,
+                                        &property.value,
+                                    ) {
+                                        (
+                                            PropertyKey::Identifier(
+                                                key,
+                                            ),
+                                            Some(
+                                                Expression::StringLit(
+                                                    lit,
+                                                ),
+                                            ),
+                                        ) if key.name == "bucket" => {
+                                            Some(lit.value.clone())
+                                        }
+                                        _ => None,
+                                    },
+                                )
+                            }
+                            _ => None,
+                        }
+                    })?;
+                return Some((bucket, measurement));
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Return true if `name` is a parameter of a function containing `node`.
+fn is_function_parameter(node: &NodeFinderNode, name: &str) -> bool {
+    let mut parent = node.parent.as_deref();
+    while let Some(ancestor) = parent {
+        if let flux::ast::walk::Node::FunctionExpr(func) =
+            &ancestor.node
+        {
+            if func.params.iter().any(|param| {
+                matches!(&param.key, PropertyKey::Identifier(key) if key.name == name)
+            }) {
+                return true;
+            }
+        }
+        parent = ancestor.parent.as_deref();
+    }
+    false
+}
+
+/// The schema query that completes a node: measurements, fields or tag values
+/// in a string compared with `r._measurement`, `r._field` or a tag column, and
+/// tag keys in a member expression of a function parameter, e.g. `r.`.
+pub(crate) fn schema_query(
+    node: &NodeFinderNode,
+) -> Option<SchemaQuery> {
+    let parent = node.parent.as_deref();
+    let (kind, range) =
+        match (&node.node, parent.map(|parent| &parent.node)) {
+            (
+                flux::ast::walk::Node::StringLit(lit),
+                Some(flux::ast::walk::Node::BinaryExpr(binary)),
+            ) => {
+                let (column, compared) = column_comparison(binary)?;
+                if !std::ptr::eq(*lit, compared) {
+                    return None;
+                }
+                let kind = match column {
+                    "_measurement" => SchemaQueryKind::Measurements,
+                    "_field" => SchemaQueryKind::Fields,
+                    tag => SchemaQueryKind::TagValues(tag.into()),
+                };
+                // Replace the contents of the string, leaving the quotes in place.
+                let mut range: lsp::Range =
+                    lit.base.location.clone().into();
+                range.start.character += 1;
+                range.end.character =
+                    range.end.character.saturating_sub(1);
+                (kind, Some(range))
+            }
+            (
+                flux::ast::walk::Node::Identifier(_),
+                Some(flux::ast::walk::Node::MemberExpr(member)),
+            )
+            | (flux::ast::walk::Node::MemberExpr(member), _) => {
+                match &member.object {
+                    Expression::Identifier(object)
+                        if is_function_parameter(
+                            node,
+                            &object.name,
+                        ) => {}
+                    _ => return None,
+                }
+                (SchemaQueryKind::TagKeys, None)
+            }
+            _ => return None,
+        };
+
+    // The innermost pipe stage containing the node reads from the stages before it.
+    let mut ancestor = parent;
+    while let Some(current) = ancestor {
+        if let flux::ast::walk::Node::PipeExpr(pipe) = &current.node {
+            let (bucket, measurement) =
+                pipeline_source(&pipe.argument)?;
+            return Some(SchemaQuery {
+                bucket,
+                measurement,
+                kind,
+                range,
+            });
+        }
+        ancestor = current.parent.as_deref();
+    }
+    None
+}
+
+/// Completion items for the values found by a schema query.
+pub(crate) fn schema_completion_items(
+    query: &SchemaQuery,
+    values: Vec<String>,
+) -> Vec<lsp::CompletionItem> {
+    let (detail, kind) = match query.kind {
+        SchemaQueryKind::Measurements => {
+            ("Measurement", lsp::CompletionItemKind::VALUE)
+        }
+        SchemaQueryKind::Fields => {
+            ("Field", lsp::CompletionItemKind::VALUE)
+        }
+        SchemaQueryKind::TagKeys => {
+            ("Tag", lsp::CompletionItemKind::FIELD)
+        }
+        SchemaQueryKind::TagValues(_) => {
+            ("Tag value", lsp::CompletionItemKind::VALUE)
+        }
+    };
+    values
+        .into_iter()
+        .map(|value| lsp::CompletionItem {
+            label: value.clone(),
+            detail: Some(detail.into()),
+            filter_text: Some(value.clone()),
+            kind: Some(kind),
+            sort_text: Some(value.clone()),
+            text_edit: query.range.map(|range| {
+                lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+                    range,
+                    new_text: value.clone(),
+                })
+            }),
+            ..lsp::CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Complete the configured bucket names inside a string literal that names a
+/// bucket, e.g. `from(bucket: "")`.
+pub(crate) fn complete_buckets(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+    buckets: &[String],
+) -> Vec<lsp::CompletionItem> {
+    let visitor = crate::walk_semantic_package!(
+        BucketLiteralVisitor::default(),
+        pkg
+    );
+    let lit = match visitor.literals.into_iter().find(|lit| {
+        crate::lsp::position_in_range(
+            &position,
+            &lit.loc.clone().into(),
+        )
+    }) {
+        Some(lit) => lit,
+        None => return vec![],
+    };
+
+    // Replace the contents of the string, leaving the quotes in place.
+    let mut range: lsp::Range = lit.loc.clone().into();
+    range.start.character += 1;
+    range.end.character = range.end.character.saturating_sub(1);
+
+    buckets
+        .iter()
+        .map(|bucket| lsp::CompletionItem {
+            label: bucket.clone(),
+            detail: Some("Bucket".into()),
+            filter_text: Some(bucket.clone()),
+            kind: Some(lsp::CompletionItemKind::VALUE),
+            sort_text: Some(bucket.clone()),
+            text_edit: Some(lsp::CompletionTextEdit::Edit(
+                lsp::TextEdit {
+                    range,
+                    new_text: bucket.clone(),
+                },
+            )),
+            ..lsp::CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Collect the identifiers in scope at a position: the variables assigned before
+/// it, and the parameters of the functions that contain it.
+struct ScopeVisitor {
+    position: lsp::Position,
+    identifiers: BTreeMap<String, Option<MonoType>>,
+}
+
+impl<'a> SemanticVisitor<'a> for ScopeVisitor {
+    fn visit(
+        &mut self,
+        node: flux::semantic::walk::Node<'a>,
+    ) -> bool {
+        match node {
+            flux::semantic::walk::Node::FunctionExpr(func) => {
+                // The parameters and variables of other functions are not in scope.
+                if !crate::lsp::position_in_range(
+                    &self.position,
+                    &func.loc.clone().into(),
+                ) {
+                    return false;
+                }
+                for param in &func.params {
+                    self.identifiers
+                        .insert(param.key.name.to_string(), None);
+                }
+            }
+            flux::semantic::walk::Node::VariableAssgn(assign) => {
+                let end: lsp::Position = assign.loc.end.into();
+                if end <= self.position {
+                    self.identifiers.insert(
+                        assign.id.name.to_string(),
+                        Some(assign.init.type_of()),
+                    );
+                }
+            }
+            _ => (),
+        }
+        true
+    }
+}
+
+fn identifiers_in_scope(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+) -> BTreeMap<String, Option<MonoType>> {
+    let visitor = crate::walk_semantic_package!(
+        ScopeVisitor {
+            position,
+            identifiers: BTreeMap::new(),
+        },
+        pkg
+    );
+    visitor.identifiers
+}
+
+fn binding_item(
+    name: &str,
+    typ: &Option<MonoType>,
+) -> lsp::CompletionItem {
+    lsp::CompletionItem {
+        label: name.into(),
+        detail: Some(match typ {
+            Some(typ) => typ.to_string(),
+            None => "Parameter".into(),
+        }),
+        filter_text: Some(name.into()),
+        insert_text: Some(name.into()),
+        insert_text_format: Some(lsp::InsertTextFormat::PLAIN_TEXT),
+        kind: Some(match typ {
+            Some(MonoType::Fun(_)) => {
+                lsp::CompletionItemKind::FUNCTION
+            }
+            _ => lsp::CompletionItemKind::VARIABLE,
+        }),
+        sort_text: Some(name.into()),
+        ..lsp::CompletionItem::default()
+    }
+}
+
+/// Complete the identifiers that can be used as a value at a position, e.g. the
+/// expression of a `${}` string interpolation.
+pub(crate) fn complete_identifiers_in_scope(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+) -> Vec<lsp::CompletionItem> {
+    identifiers_in_scope(pkg, position)
+        .iter()
+        .filter(|(_, typ)| !matches!(typ, Some(MonoType::Fun(_))))
+        .map(|(name, typ)| binding_item(name, typ))
+        .collect()
+}
+
+/// The variables, functions and parameters in scope at a position, to be ranked
+/// with the prelude and stdlib.
+pub(crate) fn complete_bindings_in_scope(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+) -> Vec<Candidate> {
+    identifiers_in_scope(pkg, position)
+        .into_iter()
+        .map(|(name, typ)| Candidate {
+            item: binding_item(&name, &typ),
+            source: CompletionSource::Local,
+            typ,
+        })
+        .collect()
+}
+
+/// Whether a name is bound at a position, e.g. to a variable or function
+/// parameter, rather than referring to the prelude.
+pub(crate) fn is_bound_in_scope(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+    name: &str,
+) -> bool {
+    identifiers_in_scope(pkg, position).contains_key(name)
+}
+
+/// Where a completion comes from. Bindings closer to the user's code rank higher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CompletionSource {
+    Stdlib,
+    Prelude,
+    Local,
+    /// A literal value written for the completion position, e.g. a duration.
+    Literal,
+    /// A snippet template of a query skeleton.
+    Snippet,
+}
+
+impl CompletionSource {
+    fn weight(self) -> i32 {
+        match self {
+            CompletionSource::Stdlib => 0,
+            CompletionSource::Prelude => 24,
+            CompletionSource::Local => 48,
+            CompletionSource::Literal => 72,
+            CompletionSource::Snippet => 0,
+        }
+    }
+}
+
+/// A completion item to be ranked against what has been typed.
+pub(crate) struct Candidate {
+    pub item: lsp::CompletionItem,
+    pub source: CompletionSource,
+    /// The type of the completed value, if it's known.
+    pub typ: Option<MonoType>,
+}
+
+/// The bonus for candidates with the type expected at the completion position.
+const BONUS_EXPECTED_TYPE: i32 = 32;
+
+/// Scores are subtracted from this in `sort_text`, so that higher scores sort first.
+const SORT_TEXT_BASE: i32 = 10_000;
+
+/// Whether a value of one type can probably be used where the other is expected.
+///
+/// This only compares the kinds of types, as the expected type is often generic,
+/// e.g. the `fn` parameter of `filter`.
+fn is_compatible(typ: &MonoType, expected: &MonoType) -> bool {
+    match (typ, expected) {
+        (MonoType::Builtin(typ), MonoType::Builtin(expected)) => {
+            typ == expected
+        }
+        (
+            MonoType::Collection(typ),
+            MonoType::Collection(expected),
+        ) => typ.collection == expected.collection,
+        (MonoType::Record(_), MonoType::Record(_))
+        | (MonoType::Fun(_), MonoType::Fun(_)) => true,
+        _ => false,
+    }
+}
+
+/// How a completed function would be called.
+pub(crate) enum CallPosition {
+    /// Not called, e.g. a value passed as an argument in `fn: mean`.
+    Value,
+    /// The callee of a call anywhere other than after `|>`.
+    Plain,
+    /// After `|>`, with the type of the piped value when it's known.
+    Pipe(Option<MonoType>),
+}
+
+/// Find how the completed node would be called: it is in pipe position when it is
+/// the callee of the call in `x |> f()`, or the member in `x |> pkg.f()`, and in
+/// value position when it isn't the callee of a call at all.
+pub(crate) fn call_position(
+    pkg: &flux::semantic::nodes::Package,
+    node: &NodeFinderNode,
+) -> CallPosition {
+    let mut callee = node;
+    if let (
+        flux::ast::walk::Node::Identifier(identifier),
+        Some(parent),
+    ) = (&node.node, node.parent.as_deref())
+    {
+        if let flux::ast::walk::Node::MemberExpr(member) =
+            &parent.node
+        {
+            if member.property.base().location.start
+                == identifier.base.location.start
+            {
+                callee = parent;
+            }
+        }
+    }
+
+    let call = match callee.parent.as_deref() {
+        Some(call) => call,
+        None => return CallPosition::Value,
+    };
+    let expr = match &call.node {
+        flux::ast::walk::Node::CallExpr(expr)
+            if expr.callee.base().location.start
+                == callee.node.base().location.start =>
+        {
+            expr
+        }
+        _ => return CallPosition::Value,
+    };
+    match call.parent.as_deref() {
+        Some(NodeFinderNode {
+            node: flux::ast::walk::Node::PipeExpr(pipe),
+            ..
+        }) if std::ptr::eq(&pipe.call, *expr) => {
+            CallPosition::Pipe(expression_type(pkg, &pipe.argument))
+        }
+        _ => CallPosition::Plain,
+    }
+}
+
+/// Whether a value of a type can be completed in a call position. Only functions
+/// with a pipe parameter that accepts the piped value can be called after `|>`,
+/// and functions with a pipe parameter can't be called anywhere else. Any value
+/// can be completed where it isn't called.
+pub(crate) fn accepts_call_position(
+    typ: &MonoType,
+    position: &CallPosition,
+) -> bool {
+    let pipe = match typ {
+        MonoType::Fun(function) => function.pipe.as_ref(),
+        _ => return !matches!(position, CallPosition::Pipe(_)),
+    };
+    match (pipe, position) {
+        (_, CallPosition::Value) => true,
+        (None, CallPosition::Plain) => true,
+        (Some(pipe), CallPosition::Pipe(Some(piped))) => {
+            matches!(pipe.v, MonoType::Var(_) | MonoType::BoundVar(_))
+                || matches!(
+                    piped,
+                    MonoType::Var(_) | MonoType::BoundVar(_)
+                )
+                || is_compatible(piped, &pipe.v)
+        }
+        (Some(_), CallPosition::Pipe(None)) => true,
+        _ => false,
+    }
+}
+
+/// Drop the candidates that can't be completed in a call position. Candidates
+/// with an unknown type, e.g. packages, are kept.
+pub(crate) fn retain_for_call_position(
+    candidates: &mut Vec<Candidate>,
+    position: &CallPosition,
+) {
+    candidates.retain(|candidate| {
+        candidate
+            .typ
+            .as_ref()
+            .map_or(true, |typ| accepts_call_position(typ, position))
+    });
+}
+
+/// The type of the object of a member expression, falling back to the exports of
+/// the package it names when its type can't be inferred.
+fn member_object_type(
+    pkg: &flux::semantic::nodes::Package,
+    object: &Expression,
+) -> Option<MonoType> {
+    if let Some(typ) = expression_type(pkg, object) {
+        return Some(typ);
+    }
+    let name = match object {
+        Expression::Identifier(identifier) => &identifier.name,
+        _ => return None,
+    };
+    let path = match get_imports(pkg)
+        .into_iter()
+        .find(|import| &import.name == name)
+    {
+        Some(import) => import.path,
+        None => name.clone(),
+    };
+    lang::STDLIB
+        .package(&path)
+        .map(|package| package.exports.typ().expr)
+}
+
+/// Drop the members that can't be completed in a call position, e.g. package
+/// functions without a pipe parameter after `|>`.
+pub(crate) fn retain_members_for_call_position(
+    pkg: &flux::semantic::nodes::Package,
+    object: &Expression,
+    items: &mut Vec<lsp::CompletionItem>,
+    position: &CallPosition,
+) {
+    let record = match member_object_type(pkg, object) {
+        Some(MonoType::Record(record)) => record,
+        _ => return,
+    };
+    items.retain(|item| {
+        let name = item.filter_text.as_ref().unwrap_or(&item.label);
+        record
+            .fields()
+            .find(|field| &field.k.to_string() == name)
+            .map_or(true, |field| {
+                accepts_call_position(&field.v, position)
+            })
+    });
+}
+
+/// Rank candidates by how well they match the needle. Candidates that don't match
+/// are dropped, and the rest are sorted, best first, with a `sort_text` that keeps
+/// that order in the client.
+pub(crate) fn rank_candidates(
+    candidates: Vec<Candidate>,
+    needle: &str,
+    expected: Option<&MonoType>,
+) -> Vec<lsp::CompletionItem> {
+    let mut items: Vec<lsp::CompletionItem> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let text = candidate
+                .item
+                .filter_text
+                .as_deref()
+                .unwrap_or(&candidate.item.label);
+            let mut score = crate::fuzzy::score(text, needle)?
+                + candidate.source.weight();
+            if let (Some(typ), Some(expected)) =
+                (&candidate.typ, expected)
+            {
+                if is_compatible(typ, expected) {
+                    score += BONUS_EXPECTED_TYPE;
+                }
+            }
+
+            let mut item = candidate.item;
+            item.sort_text = Some(format!(
+                "{:05} {}",
+                (SORT_TEXT_BASE - score).max(0),
+                item.sort_text.as_ref().unwrap_or(&item.label)
+            ));
+            Some(item)
+        })
+        .collect();
+    items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    items
+}
+
+/// Find the type expected of the call argument at a position.
+struct ArgumentTypeVisitor {
+    position: lsp::Position,
+    expected: Option<MonoType>,
+}
+
+impl<'a> SemanticVisitor<'a> for ArgumentTypeVisitor {
+    fn visit(
+        &mut self,
+        node: flux::semantic::walk::Node<'a>,
+    ) -> bool {
+        if let flux::semantic::walk::Node::CallExpr(call) = node {
+            if let MonoType::Fun(function) = call.callee.type_of() {
+                // Calls nested in the argument are visited later, so the
+                // innermost argument wins.
+                for argument in &call.arguments {
+                    if !crate::lsp::position_in_range(
+                        &self.position,
+                        &argument.value.loc().clone().into(),
+                    ) {
+                        continue;
+                    }
+                    if let Some((_, typ)) = function
+                        .req
+                        .iter()
+                        .chain(
+                            function
+                                .opt
+                                .iter()
+                                .map(|(k, v)| (k, &v.typ)),
+                        )
+                        .find(|(k, _)| {
+                            argument.key.name == k.as_str()
+                        })
+                    {
+                        self.expected = Some(typ.clone());
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// The type expected at a position, when it's the value of a call argument.
+pub(crate) fn expected_argument_type(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+) -> Option<MonoType> {
+    crate::walk_semantic_package!(
+        ArgumentTypeVisitor {
+            position,
+            expected: None,
+        },
+        pkg
+    )
+    .expected
+}
+
+/// Durations offered as the value of duration parameters, e.g. `every`.
+const DURATION_VALUES: &[&str] =
+    &["10s", "1m", "5m", "15m", "1h", "6h", "1d", "1w"];
+
+/// The name of a property key.
+fn property_key_name(key: &PropertyKey) -> &str {
+    match key {
+        PropertyKey::Identifier(identifier) => &identifier.name,
+        PropertyKey::StringLit(literal) => &literal.value,
+    }
+}
+
+/// The location of a property key.
+pub(crate) fn property_key_location(
+    key: &PropertyKey,
+) -> &flux::ast::SourceLocation {
+    match key {
+        PropertyKey::Identifier(identifier) => {
+            &identifier.base.location
+        }
+        PropertyKey::StringLit(literal) => &literal.base.location,
+    }
+}
+
+/// The package path and name of a called function, with the import alias
+/// resolved. Identifiers are functions of the prelude, i.e. `universe`.
+pub(crate) fn callee_path(
+    pkg: &flux::semantic::nodes::Package,
+    callee: &Expression,
+) -> Option<(String, String)> {
+    match callee {
+        Expression::Identifier(identifier) => {
+            Some(("universe".into(), identifier.name.clone()))
+        }
+        Expression::Member(member) => {
+            let object = match &member.object {
+                Expression::Identifier(identifier) => {
+                    &identifier.name
+                }
+                _ => return None,
+            };
+            let path = match get_imports(pkg)
+                .into_iter()
+                .find(|import| &import.name == object)
+            {
+                Some(import) => import.path,
+                None => object.clone(),
+            };
+            Some((path, property_key_name(&member.property).into()))
+        }
+        _ => None,
+    }
+}
+
+/// The type of a parameter of a called function, from the stdlib or, for user
+/// functions, from the inferred type of the callee.
+fn parameter_type(
+    pkg: &flux::semantic::nodes::Package,
+    callee: &Expression,
+    parameter: &str,
+) -> Option<MonoType> {
+    let stdlib = callee_path(pkg, callee).and_then(|(path, name)| {
+        let package = if path == "universe" {
+            Some(lang::UNIVERSE.clone())
+        } else {
+            lang::STDLIB.package(&path)
+        };
+        package?.function(&name)
+    });
+    if let Some(function) = stdlib {
+        return function
+            .parameters()
+            .into_iter()
+            .find(|(k, _)| k == parameter)
+            .map(|(_, v)| v);
+    }
+    match expression_type(pkg, callee)? {
+        MonoType::Fun(function) => function
+            .req
+            .get(parameter)
+            .or_else(|| function.opt.get(parameter).map(|p| &p.typ))
+            .cloned(),
+        _ => None,
+    }
+}
+
+/// Whether a function can be passed where a function of the expected type is
+/// expected: both must take a pipe or not, the candidate must accept every
+/// argument it will be called with, and require no others.
+fn function_fits(
+    candidate: &flux::semantic::types::Function,
+    expected: &flux::semantic::types::Function,
+) -> bool {
+    candidate.pipe.is_some() == expected.pipe.is_some()
+        && expected.req.keys().all(|k| {
+            candidate.req.contains_key(k)
+                || candidate.opt.contains_key(k)
+        })
+        && candidate.req.keys().all(|k| {
+            expected.req.contains_key(k)
+                || expected.opt.contains_key(k)
+        })
+}
+
+/// Whether a value of a type can be passed where the other is expected.
+fn fits_argument(typ: &MonoType, expected: &MonoType) -> bool {
+    match (typ, expected) {
+        (MonoType::Fun(candidate), MonoType::Fun(expected)) => {
+            function_fits(candidate, expected)
+        }
+        _ => is_compatible(typ, expected),
+    }
+}
+
+/// A function literal with the parameters a function of a type is called with,
+/// e.g. `(r) => $0` for the `fn` of `filter`.
+fn function_literal(
+    function: &flux::semantic::types::Function,
+) -> String {
+    let params: Vec<String> = function
+        .pipe
+        .iter()
+        .map(|pipe| {
+            if pipe.k == "<-" {
+                "tables=<-".into()
+            } else {
+                format!("{}=<-", pipe.k)
+            }
+        })
+        .chain(function.req.keys().map(String::from))
+        .collect();
+    format!("({}) => $0", params.join(", "))
+}
+
+/// The literal values that can be written for a parameter, in the order they are
+/// offered.
+fn literal_candidates(
+    values: &[&str],
+    typ: &MonoType,
+    quote: bool,
+) -> Vec<Candidate> {
+    let literal =
+        |index: usize,
+         label: String,
+         kind: lsp::CompletionItemKind| Candidate {
+            item: lsp::CompletionItem {
+                label: label.clone(),
+                detail: Some(typ.to_string()),
+                filter_text: Some(label.clone()),
+                insert_text: Some(label),
+                insert_text_format: Some(
+                    lsp::InsertTextFormat::PLAIN_TEXT,
+                ),
+                kind: Some(kind),
+                // Keep the order of the values among those with the same score.
+                sort_text: Some(format!("{:02}", index)),
+                ..lsp::CompletionItem::default()
+            },
+            source: CompletionSource::Literal,
+            typ: Some(typ.clone()),
+        };
+
+    if !values.is_empty() {
+        return values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                let label = if quote {
+                    format!(r#""{}""#, value)
+                } else {
+                    (*value).into()
+                };
+                literal(
+                    index,
+                    label,
+                    lsp::CompletionItemKind::ENUM_MEMBER,
+                )
+            })
+            .collect();
+    }
+    match typ {
+        MonoType::Builtin(BuiltinType::Bool) => ["true", "false"]
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                literal(
+                    index,
+                    (*value).into(),
+                    lsp::CompletionItemKind::KEYWORD,
+                )
+            })
+            .collect(),
+        MonoType::Builtin(BuiltinType::Duration) => DURATION_VALUES
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                literal(
+                    index,
+                    (*value).into(),
+                    lsp::CompletionItemKind::VALUE,
+                )
+            })
+            .collect(),
+        MonoType::Fun(function) => {
+            let text = function_literal(function);
+            let mut candidate = literal(
+                0,
+                text.replace("$0", "..."),
+                lsp::CompletionItemKind::SNIPPET,
+            );
+            candidate.item.insert_text = Some(text);
+            candidate.item.insert_text_format =
+                Some(lsp::InsertTextFormat::SNIPPET);
+            vec![candidate]
+        }
+        _ => vec![],
+    }
+}
+
+/// The variables and prelude values that can be passed where a value of a type is
+/// expected, e.g. the aggregates that can be the `fn` of `aggregateWindow`.
+fn binding_candidates(
+    pkg: &flux::semantic::nodes::Package,
+    position: lsp::Position,
+    expected: &MonoType,
+) -> Vec<Candidate> {
+    let locals = complete_bindings_in_scope(pkg, position);
+    let prelude = lang::UNIVERSE
+        .exports
+        .iter()
+        .filter(|(key, _)| !key.starts_with('_'))
+        .map(|(key, val)| Candidate {
+            item: lsp::CompletionItem {
+                data: CompletionItemData::member("universe", key),
+                ..binding_item(key, &Some(val.expr.clone()))
+            },
+            source: CompletionSource::Prelude,
+            typ: Some(val.expr.clone()),
+        });
+    locals
+        .into_iter()
+        .chain(prelude)
+        .filter(|candidate| {
+            candidate
+                .typ
+                .as_ref()
+                .map_or(false, |typ| fits_argument(typ, expected))
+        })
+        .collect()
+}
+
+/// Complete the value of a call argument, e.g. after `every: ` in
+/// `aggregateWindow(every: )`, or inside the string of `group(mode: "")`.
+pub(crate) fn complete_argument_value(
+    pkg: &flux::semantic::nodes::Package,
+    contents: &str,
+    node: &NodeFinderNode,
+    position: lsp::Position,
+) -> Option<Vec<lsp::CompletionItem>> {
+    // Inside a string argument, only the known values of the parameter are
+    // completed, replacing the contents of the string.
+    if let flux::ast::walk::Node::StringLit(lit) = &node.node {
+        let property = node.parent.as_deref()?;
+        let call = property.parent.as_deref()?.parent.as_deref()?;
+        let (property, call) = match (&property.node, &call.node) {
+            (
+                flux::ast::walk::Node::Property(property),
+                flux::ast::walk::Node::CallExpr(call),
+            ) => (property, call),
+            _ => return None,
+        };
+        let (path, name) = callee_path(pkg, &call.callee)?;
+        let values = lang::parameter_values(
+            &path,
+            &name,
+            property_key_name(&property.key),
+        );
+        if values.is_empty() {
+            return None;
+        }
+        let mut range: lsp::Range = lit.base.location.clone().into();
+        range.start.character += 1;
+        range.end.character = range.end.character.saturating_sub(1);
+        return Some(
+            literal_candidates(values, &MonoType::STRING, false)
+                .into_iter()
+                .map(|candidate| {
+                    let mut item = candidate.item;
+                    item.text_edit =
+                        item.insert_text.take().map(|new_text| {
+                            lsp::CompletionTextEdit::Edit(
+                                lsp::TextEdit { range, new_text },
+                            )
+                        });
+                    item
+                })
+                .collect(),
+        );
+    }
+
+    // A value that is partly typed, e.g. `me` in `fn: me` or `1` in `every: 1`,
+    // is completed like an empty one, replacing what's typed so far.
+    if matches!(
+        node.node,
+        flux::ast::walk::Node::Identifier(_)
+            | flux::ast::walk::Node::IntegerLit(_)
+            | flux::ast::walk::Node::FloatLit(_)
+            | flux::ast::walk::Node::DurationLit(_)
+    ) {
+        if let Some(NodeFinderNode {
+            node: flux::ast::walk::Node::Property(property),
+            parent: Some(object),
+        }) = node.parent.as_deref()
+        {
+            let value = property.value.as_ref()?;
+            if value.base().location.start
+                != node.node.base().location.start
+            {
+                return None;
+            }
+            let call = match object.parent.as_deref() {
+                Some(NodeFinderNode {
+                    node: flux::ast::walk::Node::CallExpr(call),
+                    ..
+                }) => call,
+                _ => return None,
+            };
+            let start = crate::lsp::offset_of(
+                contents,
+                &value.base().location.start.into(),
+            )?;
+            let offset = crate::lsp::offset_of(contents, &position)?;
+            let needle = contents.get(start..offset)?;
+            let range = lsp::Range {
+                start: value.base().location.start.into(),
+                end: position,
+            };
+            let mut items = argument_value_items(
+                pkg, call, property, position, needle,
+            )?;
+            for item in items.iter_mut() {
+                item.text_edit =
+                    item.insert_text.take().map(|new_text| {
+                        lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+                            range,
+                            new_text,
+                        })
+                    });
+            }
+            return Some(items);
+        }
+    }
+
+    let mut call = node;
+    let call = loop {
+        match &call.node {
+            flux::ast::walk::Node::CallExpr(expr) => break expr,
+            flux::ast::walk::Node::ObjectExpr(_)
+            | flux::ast::walk::Node::Property(_) => {
+                call = call.parent.as_deref()?
+            }
+            _ => return None,
+        }
+    };
+    let object = match call.arguments.first() {
+        Some(Expression::Object(object)) => object,
+        _ => return None,
+    };
+    // A property without a value yet, whose key and colon are all that's
+    // between the start of the argument and the position.
+    let offset = crate::lsp::offset_of(contents, &position)?;
+    let (property, separator) =
+        object.properties.iter().rev().find_map(|property| {
+            if property.value.is_some() {
+                return None;
+            }
+            let end = crate::lsp::offset_of(
+                contents,
+                &property_key_location(&property.key).end.into(),
+            )?;
+            let separator = contents.get(end..offset)?;
+            if separator.trim_start().starts_with(':')
+                && separator.trim_start()[1..].trim().is_empty()
+            {
+                Some((property, separator))
+            } else {
+                None
+            }
+        })?;
+
+    let mut items =
+        argument_value_items(pkg, call, property, position, "")?;
+    if separator.ends_with(':') {
+        for item in items.iter_mut() {
+            item.insert_text = item
+                .insert_text
+                .as_ref()
+                .map(|text| format!(" {}", text));
+        }
+    }
+    Some(items)
+}
+
+/// The values that can be passed to the parameter of a property of a call,
+/// ranked against what's typed of the value.
+fn argument_value_items(
+    pkg: &flux::semantic::nodes::Package,
+    call: &flux::ast::CallExpr,
+    property: &flux::ast::Property,
+    position: lsp::Position,
+    needle: &str,
+) -> Option<Vec<lsp::CompletionItem>> {
+    let parameter = property_key_name(&property.key);
+    let typ = parameter_type(pkg, &call.callee, parameter)?;
+    let values = callee_path(pkg, &call.callee)
+        .map(|(path, name)| {
+            lang::parameter_values(&path, &name, parameter)
+        })
+        .unwrap_or_default();
+
+    let mut candidates = literal_candidates(values, &typ, true);
+    if values.is_empty() {
+        candidates.extend(binding_candidates(pkg, position, &typ));
+    }
+    let items = rank_candidates(candidates, needle, Some(&typ));
+    if items.is_empty() {
+        None
+    } else {
+        Some(items)
+    }
+}
+
+/// The position where new `import` statements are inserted: the line after the
+/// package clause, or the start of the file.
+pub(crate) fn import_position(
+    pkg: &flux::semantic::nodes::Package,
+) -> lsp::Position {
+    let visitor = crate::walk_semantic_package!(
+        PackageNodeFinderVisitor::default(),
+        pkg
+    );
+    match visitor.location {
+        Some(location) => lsp::Position {
+            line: location.start.line + 1,
+            character: 0,
+        },
+        None => lsp::Position::default(),
+    }
+}
+
+/// The shortest identifier that package functions are completed for. Shorter
+/// identifiers match too much of the stdlib to be useful.
+const PACKAGE_FUNCTION_MIN_NEEDLE: usize = 4;
+
+/// Complete the functions of stdlib packages that start with the needle, qualified
+/// with the name the package is imported as.
+///
+/// Packages that aren't imported yet have their `import` added when the item is
+/// accepted, unless another import already uses the package name.
+pub(crate) fn complete_package_functions(
+    pkg: &flux::semantic::nodes::Package,
+    needle: &str,
+) -> Vec<Candidate> {
+    if needle.chars().count() < PACKAGE_FUNCTION_MIN_NEEDLE {
+        return vec![];
+    }
+    let imports = get_imports(pkg);
+    let position = import_position(pkg);
+
+    lang::STDLIB
+        .packages()
+        .filter(|package| package.path != "universe")
+        .filter_map(|package| {
+            match imports
+                .iter()
+                .find(|import| import.path == package.path)
+            {
+                Some(import) => {
+                    Some((package, import.name.clone(), None))
+                }
+                None if imports
+                    .iter()
+                    .any(|import| import.name == package.name) =>
+                {
+                    None
+                }
+                None => {
+                    let edit = lsp::TextEdit {
+                        range: lsp::Range {
+                            start: position,
+                            end: position,
+                        },
+                        new_text: format!(
+                            "import \"{}\"\n",
+                            package.path
+                        ),
+                    };
+                    let name = package.name.clone();
+                    Some((package, name, Some(vec![edit])))
+                }
+            }
+        })
+        .flat_map(|(package, name, additional_text_edits)| {
+            package
+                .exports
+                .iter()
+                .filter(|(key, _)| {
+                    !key.starts_with('_')
+                        && key.to_lowercase().starts_with(
+                            needle.to_lowercase().as_str(),
+                        )
+                })
+                .filter_map(|(key, val)| match &val.expr {
+                    MonoType::Fun(function) => Some(Candidate {
+                        item: lsp::CompletionItem {
+                            label: format!("{}.{}", name, key),
+                            additional_text_edits:
+                                additional_text_edits.clone(),
+                            detail: Some(create_function_signature(
+                                function,
+                            )),
+                            filter_text: Some(key.to_string()),
+                            insert_text: Some(format!(
+                                "{}.{}",
+                                name, key
+                            )),
+                            insert_text_format: Some(
+                                lsp::InsertTextFormat::PLAIN_TEXT,
+                            ),
+                            kind: Some(
+                                lsp::CompletionItemKind::FUNCTION,
+                            ),
+                            sort_text: Some(format!(
+                                "{} {}",
+                                key, name
+                            )),
+                            data: CompletionItemData::member(
+                                &package.path,
+                                key,
+                            ),
+                            ..lsp::CompletionItem::default()
+                        },
+                        source: CompletionSource::Stdlib,
+                        typ: Some(val.expr.clone()),
+                    }),
+                    _ => None,
+                })
+                .collect::<Vec<Candidate>>()
+        })
+        .collect()
+}
+
+/// Complete the name of the option set by an `option` statement, e.g. `option ta`,
+/// with the option and a snippet of its value.
+pub(crate) fn complete_option_statement(
+    pkg: &flux::semantic::nodes::Package,
+    contents: &str,
+    position: lsp::Position,
+) -> Option<Vec<lsp::CompletionItem>> {
+    // The statement can't be parsed until the value is written, so look for
+    // `option` at the start of the line.
+    let offset = crate::lsp::offset_of(contents, &position)?;
+    let line = contents.get(..offset)?.rsplit('\n').next()?;
+    let rest = line.trim_start().strip_prefix("option")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let typed = rest.trim_start();
+    if !typed
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+    {
+        return None;
+    }
+    let range = lsp::Range {
+        start: lsp::Position {
+            line: position.line,
+            character: position.character
+                - typed.chars().count() as u32,
+        },
+        end: position,
+    };
+
+    let imports = get_imports(pkg);
+    let items = lang::RUNTIME_OPTIONS
+        .iter()
+        .filter_map(|option| {
+            let (name, additional_text_edits) = match option.package {
+                None => (option.name.to_string(), None),
+                Some(path) => {
+                    match imports
+                        .iter()
+                        .find(|import| import.path == path)
+                    {
+                        Some(import) => (
+                            format!(
+                                "{}.{}",
+                                import.name, option.name
+                            ),
+                            None,
+                        ),
+                        None => {
+                            let package = path.rsplit('/').next()?;
+                            if imports
+                                .iter()
+                                .any(|import| import.name == package)
+                            {
+                                return None;
+                            }
+                            let position = import_position(pkg);
+                            (
+                                format!(
+                                    "{}.{}",
+                                    package, option.name
+                                ),
+                                Some(vec![lsp::TextEdit {
+                                    range: lsp::Range {
+                                        start: position,
+                                        end: position,
+                                    },
+                                    new_text: format!(
+                                        "import \"{}\"\n",
+                                        path
+                                    ),
+                                }]),
+                            )
+                        }
+                    }
+                }
+            };
+            Some(lsp::CompletionItem {
+                label: name.clone(),
+                additional_text_edits,
+                detail: Some("Option".into()),
+                documentation: Some(lsp::Documentation::String(
+                    option.description.into(),
+                )),
+                filter_text: Some(name.clone()),
+                insert_text_format: Some(
+                    lsp::InsertTextFormat::SNIPPET,
+                ),
+                kind: Some(lsp::CompletionItemKind::VARIABLE),
+                sort_text: Some(name.clone()),
+                text_edit: Some(lsp::CompletionTextEdit::Edit(
+                    lsp::TextEdit {
+                        range,
+                        new_text: format!(
+                            "{} = {}",
+                            name, option.snippet
+                        ),
+                    },
+                )),
+                ..lsp::CompletionItem::default()
+            })
+        })
+        .collect();
+    Some(items)
+}
+
+/// The package path and name of the option set by an `option` statement, with the
+/// import alias of package options resolved.
+pub(crate) fn option_name(
+    pkg: &flux::semantic::nodes::Package,
+    statement: &flux::ast::OptionStmt,
+) -> Option<(Option<String>, String)> {
+    match &statement.assignment {
+        flux::ast::Assignment::Variable(assign) => {
+            Some((None, assign.id.name.clone()))
+        }
+        flux::ast::Assignment::Member(assign) => {
+            let object = match &assign.member.object {
+                Expression::Identifier(identifier) => {
+                    &identifier.name
+                }
+                _ => return None,
+            };
+            let path = match get_imports(pkg)
+                .into_iter()
+                .find(|import| &import.name == object)
+            {
+                Some(import) => import.path,
+                None => return None,
+            };
+            Some((
+                Some(path),
+                property_key_name(&assign.member.property).into(),
+            ))
+        }
+    }
+}
+
+/// Complete the fields of the record assigned to an option, e.g. `every` in
+/// `option task = {name: "downsample", }`.
+pub(crate) fn complete_option_record(
+    pkg: &flux::semantic::nodes::Package,
+    node: &NodeFinderNode,
+) -> Option<Vec<lsp::CompletionItem>> {
+    // The node is the record, or a key being typed in it.
+    if let (
+        flux::ast::walk::Node::Identifier(identifier),
+        Some(flux::ast::walk::Node::Property(property)),
+    ) = (
+        &node.node,
+        node.parent.as_deref().map(|parent| &parent.node),
+    ) {
+        if property_key_location(&property.key).start
+            != identifier.base.location.start
+        {
+            return None;
         }
     }
+    let mut current = node;
+    let object = loop {
+        match &current.node {
+            flux::ast::walk::Node::ObjectExpr(object) => {
+                break *object
+            }
+            flux::ast::walk::Node::Identifier(_)
+            | flux::ast::walk::Node::Property(_) => {
+                current = current.parent.as_deref()?
+            }
+            _ => return None,
+        }
+    };
+    let assignment = current.parent.as_deref()?;
+    let statement = match (
+        &assignment.node,
+        assignment.parent.as_deref().map(|parent| &parent.node),
+    ) {
+        (
+            flux::ast::walk::Node::VariableAssgn(_)
+            | flux::ast::walk::Node::MemberAssgn(_),
+            Some(flux::ast::walk::Node::OptionStmt(statement)),
+        ) => *statement,
+        _ => return None,
+    };
+    let (package, name) = option_name(pkg, statement)?;
+    let option = lang::runtime_option(package.as_deref(), &name)?;
+
+    // The key being typed is replaced, so it isn't a field that's set already.
+    let typed = match &node.node {
+        flux::ast::walk::Node::Identifier(identifier) => {
+            Some(identifier.name.as_str())
+        }
+        _ => None,
+    };
+    let set: Vec<&str> = object
+        .properties
+        .iter()
+        .map(|property| property_key_name(&property.key))
+        .filter(|key| Some(*key) != typed)
+        .collect();
+    let items = option
+        .fields
+        .iter()
+        .enumerate()
+        .filter(|(_, (field, _))| !set.contains(field))
+        .map(|(index, (field, typ))| lsp::CompletionItem {
+            label: field.to_string(),
+            detail: Some(typ.to_string()),
+            filter_text: Some(field.to_string()),
+            insert_text: Some(format!(
+                "{}: {}",
+                field,
+                lang::type_placeholder(typ)
+            )),
+            insert_text_format: Some(lsp::InsertTextFormat::SNIPPET),
+            kind: Some(lsp::CompletionItemKind::FIELD),
+            // Keep the order of the fields, e.g. `name` first.
+            sort_text: Some(format!("{:02}", index)),
+            ..lsp::CompletionItem::default()
+        })
+        .collect();
+    Some(items)
 }
 
 pub fn get_var_type(
@@ -435,7 +2469,10 @@ impl Completable for VarResult {
             )),
             text_edit: None,
             command: None,
-            data: None,
+            data: CompletionItemData::member(
+                &self.package,
+                &self.name,
+            ),
             insert_text_mode: None,
             tags: None,
         }
@@ -465,6 +2502,7 @@ impl CompletionVarResult {
 #[derive(Clone)]
 struct FunctionResult {
     name: String,
+    package: String,
     signature: String,
 }
 #[derive(Clone)]
@@ -645,10 +2683,19 @@ pub fn complete_call_expr(
                         PropertyKey::StringLit(l) => &l.value,
                     };
 
+                    // The package of an aliased import, e.g. `s` in
+                    // `import s "strings"`, is found by its path.
+                    let path = match get_imports(sem_pkg)
+                        .into_iter()
+                        .find(|import| import.name == ident.name)
+                    {
+                        Some(import) => import.path,
+                        None => ident.name.clone(),
+                    };
                     let initial_params: Vec<(
                         String,
                         Option<MonoType>,
-                    )> = match lang::STDLIB.package(&ident.name) {
+                    )> = match lang::STDLIB.package(&path) {
                         Some(package) => {
                             match package.function(key) {
                                 Some(function) => function
@@ -816,3 +2863,146 @@ pub fn create_function_signature(
         f.retn
     )
 }
+
+/// Signature information for a user function, with the documentation of its
+/// doc comment, as plain text for clients that can't render markdown.
+pub(crate) fn user_function_signature(
+    name: &str,
+    f: &flux::semantic::types::Function,
+    doc: Option<&crate::visitors::ast::DocComment>,
+    supports_markdown: bool,
+) -> lsp::SignatureInformation {
+    let pipe = f.pipe.as_ref().map(|pipe| {
+        if pipe.k == "<-" {
+            (pipe.k.clone(), pipe.v.to_string())
+        } else {
+            (format!("<-{}", pipe.k), pipe.v.to_string())
+        }
+    });
+    let parameters: Vec<(String, String)> = pipe
+        .into_iter()
+        .chain(f.req.iter().map(|(k, v)| (k.clone(), v.to_string())))
+        .chain(
+            f.opt
+                .iter()
+                .map(|(k, v)| (format!("?{}", k), v.typ.to_string())),
+        )
+        .collect();
+
+    let markdown = |value: String| {
+        if supports_markdown {
+            lsp::Documentation::MarkupContent(lsp::MarkupContent {
+                kind: lsp::MarkupKind::Markdown,
+                value,
+            })
+        } else {
+            lsp::Documentation::String(value)
+        }
+    };
+    lsp::SignatureInformation {
+        label: format!(
+            "{}({}) => {}",
+            name,
+            parameters
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect::<Vec<_>>()
+                .join(", "),
+            f.retn
+        ),
+        parameters: Some(
+            parameters
+                .iter()
+                .map(|(k, v)| {
+                    let parameter =
+                        k.trim_start_matches(&['<', '-', '?'][..]);
+                    lsp::ParameterInformation {
+                        label: lsp::ParameterLabel::Simple(format!(
+                            "{}: {}",
+                            k, v
+                        )),
+                        documentation: doc
+                            .and_then(|doc| doc.parameter(parameter))
+                            .map(|description| {
+                                markdown(description.into())
+                            }),
+                    }
+                })
+                .collect(),
+        ),
+        documentation: doc.map(|doc| markdown(doc.markdown())),
+        active_parameter: None,
+    }
+}
+
+/// The parameter name of a signature parameter label, e.g. `bucket` for
+/// `$bucket` or `c` for `c: float`. Piped parameters, e.g. `<-tables`, aren't
+/// written as arguments, so they have no name.
+fn signature_parameter_name(
+    label: &lsp::ParameterLabel,
+) -> Option<&str> {
+    match label {
+        lsp::ParameterLabel::Simple(label)
+            if !label.starts_with("<-") =>
+        {
+            label.split(':').next().map(|name| {
+                name.trim_start_matches(&['$', '?'][..]).trim()
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The active signature and parameter of a call, from the named arguments
+/// already written and the argument the cursor is in, if any. The active
+/// signature is the first one with every written argument and, when the cursor
+/// isn't in an argument, another argument to write.
+pub(crate) fn active_signature(
+    signatures: &[lsp::SignatureInformation],
+    written: &[String],
+    current: Option<&str>,
+) -> (Option<u32>, Option<u32>) {
+    if written.is_empty() && current.is_none() {
+        return (None, None);
+    }
+    let found = signatures.iter().enumerate().find_map(
+        |(index, signature)| {
+            let names: Vec<Option<&str>> = signature
+                .parameters
+                .iter()
+                .flatten()
+                .map(|parameter| {
+                    signature_parameter_name(&parameter.label)
+                })
+                .collect();
+            let has_written = written
+                .iter()
+                .all(|name| names.contains(&Some(name.as_str())));
+            let has_next = current.is_some()
+                || names.iter().flatten().count() > written.len();
+            if has_written && has_next {
+                Some((index, names))
+            } else {
+                None
+            }
+        },
+    );
+    let (index, names) = match found {
+        Some(found) => found,
+        None => return (None, None),
+    };
+    let parameter = match current {
+        Some(current) => {
+            names.iter().position(|name| *name == Some(current))
+        }
+        None => names.iter().position(|name| {
+            name.map_or(false, |name| {
+                !written.iter().any(|written| written == name)
+            })
+        }),
+    };
+    (
+        Some(index as u32),
+        parameter.map(|parameter| parameter as u32),
+    )
+}
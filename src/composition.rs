@@ -104,39 +104,46 @@ macro_rules! binary_eq_expr {
 /// * `key` - the string used in all of the predicates. key = value
 /// * `values` - pointer to vector slice of strings, to be used as each value in the predicates.
 ///
-/// logical_expr() is a recursive function, with an unknown length (at compile time) of the values vector.
-/// As such, the filter! macro can be compile time (since it only pass the pointer to values).
-/// Then this logical_expr() cannot be a macro, because has an unknown runtime recursive depth.
-/// Yet the lower binary_eq_expr! can still be a macro.
+/// The predicates themselves are built with the binary_eq_expr! macro, and then joined
+/// by chained_logical_expr().
 fn chained_binary_eq_expr(
     operator: ast::LogicalOperator,
     keys: &[String],
     values: &[String],
 ) -> Result<ast::Expression, ()> {
-    match (keys, values) {
-        ([key], [value]) => {
-            Ok(binary_eq_expr!(key.to_owned(), value.to_owned()))
-        }
-        ([key, ..], [value, ..]) => {
-            if let Ok(right) = chained_binary_eq_expr(
-                operator.clone(),
-                &keys[1..],
-                &values[1..],
-            ) {
-                Ok(ast::Expression::Logical(Box::new(
-                    ast::LogicalExpr {
-                        base: ast::BaseNode::default(),
-                        left: binary_eq_expr!(
-                            key.to_owned(),
-                            value.to_owned()
-                        ),
-                        right,
-                        operator,
-                    },
-                )))
-            } else {
-                Err(())
-            }
+    if keys.len() != values.len() {
+        return Err(());
+    }
+    chained_logical_expr(
+        operator,
+        &keys
+            .iter()
+            .zip(values.iter())
+            .map(|(key, value)| {
+                binary_eq_expr!(key.to_owned(), value.to_owned())
+            })
+            .collect::<Vec<ast::Expression>>(),
+    )
+}
+
+/// Returns the logical expr, which are the expressions joined by the operator.
+///
+/// The expressions are nested to the right, e.g. `a or (b or c)`. chained_logical_expr()
+/// is a recursive function, with an unknown length (at compile time) of the expressions,
+/// so it cannot be a macro.
+pub(crate) fn chained_logical_expr(
+    operator: ast::LogicalOperator,
+    exprs: &[ast::Expression],
+) -> Result<ast::Expression, ()> {
+    match exprs {
+        [expr] => Ok(expr.clone()),
+        [expr, rest @ ..] if !rest.is_empty() => {
+            Ok(ast::Expression::Logical(Box::new(ast::LogicalExpr {
+                base: ast::BaseNode::default(),
+                left: expr.clone(),
+                right: chained_logical_expr(operator.clone(), rest)?,
+                operator,
+            })))
         }
         _ => Err(()),
     }
@@ -144,9 +151,9 @@ fn chained_binary_eq_expr(
 
 macro_rules! filter {
     ($key:expr, $values:expr, $operator:expr) => {
-        filter!($key, $values, $operator, chained_binary_eq_expr($operator, $key, $values).expect("chained_binary_eq_expr failed"))
+        filter!("r", chained_binary_eq_expr($operator, $key, $values).expect("chained_binary_eq_expr failed"))
     };
-    ($key:expr, $values:expr, $operator:expr, $funBody:expr) => {
+    ($param:expr, $funBody:expr) => {
         ast::CallExpr {
             arguments: vec![ast::Expression::Object(
                 Box::new(ast::ObjectExpr {
@@ -171,7 +178,7 @@ macro_rules! filter {
                                     base: ast::BaseNode::default(),
                                     key: ast::PropertyKey::Identifier(ast::Identifier {
                                         base: ast::BaseNode::default(),
-                                        name: "r".into(),
+                                        name: $param.into(),
                                     }),
                                     comma: vec![],
                                     separator: vec![],
@@ -200,6 +207,14 @@ macro_rules! filter {
     };
 }
 
+/// Returns a `filter` call, with a predicate function taking a single `param`.
+pub(crate) fn filter_call(
+    param: &str,
+    predicate: ast::Expression,
+) -> ast::CallExpr {
+    filter!(param, predicate)
+}
+
 macro_rules! pipe {
     ($a:expr, $b:expr) => {
         ast::PipeExpr {
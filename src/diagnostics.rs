@@ -1,16 +1,27 @@
 /// Diagnostics for flux code
 ///
 /// These diagnostics can range from informational lints to warnings and errors.
+use flux::ast;
 use flux::semantic::nodes::Package;
 use flux::semantic::walk::Node as WalkNode;
 use inflector::Inflector;
 use lspower::lsp;
+use serde::{Deserialize, Serialize};
 
 use super::visitors::semantic::{
-    ContribDiagnosticVisitor, ExperimentalDiagnosticVisitor,
+    BucketLiteralVisitor, ContribDiagnosticVisitor,
+    ExperimentalDiagnosticVisitor,
     InfluxDBIdentifierDiagnosticVisitor,
 };
 
+/// The diagnostic code for a bucket that isn't in the configured buckets. The
+/// diagnostic data is the list of suggested bucket names.
+pub(crate) const UNKNOWN_BUCKET: &str = "unknown-bucket";
+
+/// The diagnostic code for a column that isn't in the tables of a pipe stage. The
+/// diagnostic data is the list of `ColumnReplacement`s for the known columns.
+pub(crate) const UNKNOWN_COLUMN: &str = "unknown-column";
+
 /// Provide info about the nature of experimental.
 ///
 /// While we want to encourage people to use the experimental package, we should
@@ -89,6 +100,372 @@ pub(crate) fn prefer_camel_case(
     visitor.diagnostics
 }
 
+/// The number of single character edits needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution =
+                previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(
+                substitution
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The names that `name` is plausibly a misspelling of, closest first.
+fn suggestions(name: &str, candidates: &[String]) -> Vec<String> {
+    let threshold = (name.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, &String)> = candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    close.sort();
+    close
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+/// Warn about bucket names that aren't in the buckets configured by the client.
+///
+/// Nothing is reported until the client has configured some buckets, as an empty
+/// list more likely means the client doesn't know the buckets than that there
+/// are none.
+pub(crate) fn unknown_buckets(
+    pkg: &Package,
+    buckets: &[String],
+) -> Vec<(Option<String>, lsp::Diagnostic)> {
+    if buckets.is_empty() {
+        return vec![];
+    }
+    let visitor = crate::walk_semantic_package!(
+        BucketLiteralVisitor::default(),
+        pkg
+    );
+    visitor
+        .literals
+        .into_iter()
+        .filter(|lit| !buckets.contains(&lit.value))
+        .map(|lit| {
+            let suggestions = suggestions(&lit.value, buckets);
+            let message = match suggestions.first() {
+                Some(suggestion) => format!(
+                    "Bucket `{}` is not a known bucket. Did you mean `{}`?",
+                    lit.value, suggestion
+                ),
+                None => {
+                    format!("Bucket `{}` is not a known bucket.", lit.value)
+                }
+            };
+            (
+                lit.loc.file.clone(),
+                lsp::Diagnostic {
+                    range: lit.loc.clone().into(),
+                    severity: Some(lsp::DiagnosticSeverity::WARNING),
+                    code: Some(lsp::NumberOrString::String(
+                        UNKNOWN_BUCKET.into(),
+                    )),
+                    message,
+                    data: Some(serde_json::json!(suggestions)),
+                    ..lsp::Diagnostic::default()
+                },
+            )
+        })
+        .collect()
+}
+
+/// A replacement of an unknown column reference with a known column.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ColumnReplacement {
+    pub column: String,
+    pub new_text: String,
+}
+
+/// A reference to a column by a pipe stage, and how to write a reference to
+/// another column in its place.
+struct ColumnReference<'a> {
+    column: &'a str,
+    location: &'a ast::SourceLocation,
+    replace: Box<dyn Fn(&str) -> String + 'a>,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Collect the columns read from a record parameter, e.g. `r.host` and `r["host"]`,
+/// except where they are tested with `exists`.
+struct RecordMemberVisitor<'a> {
+    parameter: &'a str,
+    references: Vec<ColumnReference<'a>>,
+}
+
+impl<'a> ast::walk::Visitor<'a> for RecordMemberVisitor<'a> {
+    fn visit(&mut self, node: ast::walk::Node<'a>) -> bool {
+        match node {
+            ast::walk::Node::UnaryExpr(unary)
+                if matches!(
+                    unary.operator,
+                    ast::Operator::ExistsOperator
+                ) =>
+            {
+                false
+            }
+            ast::walk::Node::MemberExpr(member) => {
+                match &member.object {
+                    ast::Expression::Identifier(object)
+                        if object.name == self.parameter => {}
+                    _ => return true,
+                }
+                let column = match &member.property {
+                    ast::PropertyKey::Identifier(property) => {
+                        &property.name
+                    }
+                    ast::PropertyKey::StringLit(property) => {
+                        &property.value
+                    }
+                };
+                let parameter = self.parameter;
+                self.references.push(ColumnReference {
+                    column,
+                    location: &member.base.location,
+                    replace: Box::new(move |column| {
+                        if is_identifier(column) {
+                            format!("{}.{}", parameter, column)
+                        } else {
+                            format!("{}[\"{}\"]", parameter, column)
+                        }
+                    }),
+                });
+                true
+            }
+            _ => true,
+        }
+    }
+}
+
+/// The value of a named argument of a call.
+fn call_argument<'a>(
+    call: &'a ast::CallExpr,
+    name: &str,
+) -> Option<&'a ast::Expression> {
+    match call.arguments.first()? {
+        ast::Expression::Object(object) => {
+            object.properties.iter().find_map(|property| {
+                match (&property.key, &property.value) {
+                    (
+                        ast::PropertyKey::Identifier(key),
+                        Some(value),
+                    ) if key.name == name => Some(value),
+                    _ => None,
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+fn quoted(column: &str) -> String {
+    format!("\"{}\"", column)
+}
+
+/// The columns of its input referenced by a pipe stage: the fields of the record
+/// of `filter`, `map` and `reduce` functions, the keys of `rename` and the strings
+/// of `columns` arrays.
+fn column_references(
+    call: &ast::CallExpr,
+) -> Vec<ColumnReference<'_>> {
+    let name = match &call.callee {
+        ast::Expression::Identifier(identifier) => {
+            identifier.name.as_str()
+        }
+        _ => return vec![],
+    };
+    match (
+        name,
+        call_argument(call, "fn"),
+        call_argument(call, "columns"),
+    ) {
+        (
+            "filter" | "map" | "reduce",
+            Some(ast::Expression::Function(func)),
+            _,
+        ) => {
+            let parameter =
+                match func.params.first().map(|param| &param.key) {
+                    Some(ast::PropertyKey::Identifier(parameter)) => {
+                        parameter
+                    }
+                    _ => return vec![],
+                };
+            let mut visitor = RecordMemberVisitor {
+                parameter: &parameter.name,
+                references: vec![],
+            };
+            ast::walk::walk(
+                &mut visitor,
+                ast::walk::Node::FunctionExpr(func),
+            );
+            visitor.references
+        }
+        ("rename", _, Some(ast::Expression::Object(object))) => {
+            object
+                .properties
+                .iter()
+                .map(|property| match &property.key {
+                    ast::PropertyKey::Identifier(key) => {
+                        ColumnReference {
+                            column: &key.name,
+                            location: &key.base.location,
+                            replace: Box::new(|column: &str| {
+                                if is_identifier(column) {
+                                    column.into()
+                                } else {
+                                    quoted(column)
+                                }
+                            }),
+                        }
+                    }
+                    ast::PropertyKey::StringLit(key) => {
+                        ColumnReference {
+                            column: &key.value,
+                            location: &key.base.location,
+                            replace: Box::new(quoted),
+                        }
+                    }
+                })
+                .collect()
+        }
+        (
+            "keep" | "drop" | "group" | "sort",
+            _,
+            Some(ast::Expression::Array(array)),
+        ) => array
+            .elements
+            .iter()
+            .filter_map(|element| match &element.expression {
+                ast::Expression::StringLit(lit) => {
+                    Some(ColumnReference {
+                        column: &lit.value,
+                        location: &lit.base.location,
+                        replace: Box::new(quoted),
+                    })
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Collect the pipe expressions of a file.
+#[derive(Default)]
+struct PipeExprVisitor<'a> {
+    pipes: Vec<&'a ast::PipeExpr>,
+}
+
+impl<'a> ast::walk::Visitor<'a> for PipeExprVisitor<'a> {
+    fn visit(&mut self, node: ast::walk::Node<'a>) -> bool {
+        if let ast::walk::Node::PipeExpr(pipe) = node {
+            self.pipes.push(pipe);
+        }
+        true
+    }
+}
+
+/// Warn about columns referenced by a pipe stage that an earlier stage has
+/// definitely removed, e.g. `r.host` after `keep(columns: ["_time", "_value"])`,
+/// using the columns inferred statically from the pipeline.
+pub(crate) fn unknown_columns(
+    ast_pkg: &ast::Package,
+    pkg: &Package,
+) -> Vec<(Option<String>, lsp::Diagnostic)> {
+    let mut diagnostics = vec![];
+    for file in &ast_pkg.files {
+        let pipeline =
+            crate::pipeline::Pipeline::new(pkg, Some(file));
+        let mut visitor = PipeExprVisitor::default();
+        ast::walk::walk(&mut visitor, ast::walk::Node::File(file));
+        for pipe in visitor.pipes {
+            let schema = match pipeline.schema(&pipe.argument) {
+                Some(schema) => schema,
+                None => continue,
+            };
+            for reference in column_references(&pipe.call) {
+                // An empty column is still being typed.
+                if reference.column.is_empty()
+                    || !schema.is_missing(reference.column)
+                {
+                    continue;
+                }
+                let suggested =
+                    suggestions(reference.column, &schema.columns);
+                let mut message = if schema
+                    .is_removed(reference.column)
+                {
+                    format!(
+                        "Column `{}` was removed by an earlier stage.",
+                        reference.column
+                    )
+                } else {
+                    format!(
+                        "Column `{}` is not a column of the tables at this stage.",
+                        reference.column
+                    )
+                };
+                if let Some(suggestion) = suggested.first() {
+                    message.push_str(&format!(
+                        " Did you mean `{}`?",
+                        suggestion
+                    ));
+                }
+                // The closest columns are offered first, then the rest in order.
+                let mut columns = suggested;
+                for column in &schema.columns {
+                    if !columns.contains(column) {
+                        columns.push(column.clone());
+                    }
+                }
+                let replacements: Vec<ColumnReplacement> = columns
+                    .into_iter()
+                    .map(|column| ColumnReplacement {
+                        new_text: (reference.replace)(&column),
+                        column,
+                    })
+                    .collect();
+                diagnostics.push((
+                    reference.location.file.clone(),
+                    lsp::Diagnostic {
+                        range: reference.location.clone().into(),
+                        severity: Some(
+                            lsp::DiagnosticSeverity::WARNING,
+                        ),
+                        code: Some(lsp::NumberOrString::String(
+                            UNKNOWN_COLUMN.into(),
+                        )),
+                        message,
+                        data: Some(serde_json::json!(replacements)),
+                        ..lsp::Diagnostic::default()
+                    },
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -330,4 +707,154 @@ params = "abc123"
             ..lsp::Diagnostic::default()
         })], diagnostics);
     }
+
+    #[test]
+    fn unknown_buckets_suggests_close_names() {
+        let fluxscript = r#"from(bucket: "my-buckt")
+    |> range(start: -1h)
+    |> to(bucket: "my-bucket")
+"#;
+        let package = get_package(&fluxscript);
+        let buckets =
+            vec!["my-bucket".to_string(), "other".to_string()];
+
+        let diagnostics = unknown_buckets(&package, &buckets);
+
+        assert_eq!(vec![(Some("script.flux".to_string()), lsp::Diagnostic {
+            range: lsp::Range {
+                start: lsp::Position {
+                    line: 0, character: 13,
+                },
+                end : lsp::Position {
+                    line: 0, character: 23,
+                },
+            },
+            severity: Some(lsp::DiagnosticSeverity::WARNING),
+            code: Some(lsp::NumberOrString::String("unknown-bucket".into())),
+            message: "Bucket `my-buckt` is not a known bucket. Did you mean `my-bucket`?".into(),
+            data: Some(serde_json::json!(["my-bucket"])),
+            ..lsp::Diagnostic::default()
+        })], diagnostics);
+    }
+
+    #[test]
+    fn unknown_buckets_in_buckets_results() {
+        let fluxscript = r#"buckets()
+    |> filter(fn: (r) => r.name == "telegraf")
+"#;
+        let package = get_package(&fluxscript);
+        let buckets = vec!["my-bucket".to_string()];
+
+        let diagnostics = unknown_buckets(&package, &buckets);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "Bucket `telegraf` is not a known bucket.",
+            diagnostics[0].1.message
+        );
+    }
+
+    fn get_ast_package(source: &str) -> ast::Package {
+        flux::parser::parse_string("script.flux".into(), source)
+            .into()
+    }
+
+    #[test]
+    fn unknown_columns_after_keep() {
+        let fluxscript = r#"from(bucket: "telegraf")
+    |> keep(columns: ["_time", "_value"])
+    |> filter(fn: (r) => r.host == "a")
+"#;
+        let package = get_package(&fluxscript);
+
+        let diagnostics =
+            unknown_columns(&get_ast_package(fluxscript), &package);
+
+        assert_eq!(vec![(Some("script.flux".to_string()), lsp::Diagnostic {
+            range: lsp::Range {
+                start: lsp::Position {
+                    line: 2, character: 25,
+                },
+                end : lsp::Position {
+                    line: 2, character: 31,
+                },
+            },
+            severity: Some(lsp::DiagnosticSeverity::WARNING),
+            code: Some(lsp::NumberOrString::String("unknown-column".into())),
+            message: "Column `host` is not a column of the tables at this stage.".into(),
+            data: Some(serde_json::json!([
+                {"column": "_time", "new_text": "r._time"},
+                {"column": "_value", "new_text": "r._value"},
+            ])),
+            ..lsp::Diagnostic::default()
+        })], diagnostics);
+    }
+
+    #[test]
+    fn unknown_columns_renamed() {
+        let fluxscript = r#"import "array"
+
+array.from(rows: [{host: "a", _value: 1}])
+    |> rename(columns: {hots: "server"})
+"#;
+        let package = get_package(&fluxscript);
+
+        let diagnostics =
+            unknown_columns(&get_ast_package(fluxscript), &package);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            lsp::Range {
+                start: lsp::Position::new(3, 24),
+                end: lsp::Position::new(3, 28),
+            },
+            diagnostics[0].1.range
+        );
+        assert_eq!(
+            "Column `hots` is not a column of the tables at this stage. Did you mean `host`?",
+            diagnostics[0].1.message
+        );
+    }
+
+    #[test]
+    fn unknown_columns_only_when_definitely_removed() {
+        let fluxscript = r#"from(bucket: "telegraf")
+    |> filter(fn: (r) => r.host == "a")
+    |> drop(columns: ["host"])
+    |> filter(fn: (r) => r.region == "b" and r.host == "a")
+"#;
+        let package = get_package(&fluxscript);
+
+        let diagnostics =
+            unknown_columns(&get_ast_package(fluxscript), &package);
+
+        assert_eq!(1, diagnostics.len());
+        assert_eq!(
+            "Column `host` was removed by an earlier stage.",
+            diagnostics[0].1.message
+        );
+    }
+
+    #[test]
+    fn unknown_columns_not_after_unknown_stage() {
+        let fluxscript = r#"from(bucket: "telegraf")
+    |> keep(columns: ["_time", "_value"])
+    |> stateCount(fn: (r) => r._value > 0)
+    |> filter(fn: (r) => r.stateCount > 3)
+"#;
+        let package = get_package(&fluxscript);
+
+        let diagnostics =
+            unknown_columns(&get_ast_package(fluxscript), &package);
+
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn unknown_buckets_without_configured_buckets() {
+        let fluxscript = r#"from(bucket: "my-bucket")"#;
+        let package = get_package(&fluxscript);
+
+        assert!(unknown_buckets(&package, &[]).is_empty());
+    }
 }
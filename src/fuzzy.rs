@@ -0,0 +1,151 @@
+/// Fuzzy matching of identifiers, for filtering and ranking completions.
+///
+/// A needle matches a candidate when its characters appear in the candidate in
+/// order, ignoring case, e.g. `aggW` matches `aggregateWindow`. Matches score
+/// higher when they start words, are consecutive, or are a prefix of the candidate.
+
+const SCORE_MATCH: i32 = 16;
+const BONUS_FIRST_CHARACTER: i32 = 16;
+const BONUS_BOUNDARY: i32 = 12;
+const BONUS_CONSECUTIVE: i32 = 8;
+const BONUS_CASE: i32 = 1;
+const BONUS_PREFIX: i32 = 32;
+const BONUS_EXACT: i32 = 64;
+const PENALTY_GAP: i32 = 3;
+const PENALTY_UNMATCHED: i32 = 1;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '_' | '/' | '.' | '-' | ' ')
+}
+
+/// The bonus for matching the character at `index`, when it starts a word.
+fn boundary_bonus(candidate: &[char], index: usize) -> i32 {
+    if index == 0 {
+        return BONUS_FIRST_CHARACTER;
+    }
+    let previous = candidate[index - 1];
+    let current = candidate[index];
+    if is_separator(previous)
+        || (previous.is_lowercase() && current.is_uppercase())
+        || (!previous.is_ascii_digit() && current.is_ascii_digit())
+    {
+        BONUS_BOUNDARY
+    } else {
+        0
+    }
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Score how well a candidate matches a needle, or None if it doesn't match.
+///
+/// Every candidate matches with a score of zero when the needle is empty.
+pub fn score(candidate: &str, needle: &str) -> Option<i32> {
+    let candidate: Vec<char> = candidate.chars().collect();
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() {
+        return Some(0);
+    }
+    if needle.len() > candidate.len() {
+        return None;
+    }
+
+    // `scores[j]` is the best score for the needle so far, with its last
+    // character matched at `candidate[j]`. Keeping the best score for each
+    // position, rather than matching greedily, finds word boundaries later in
+    // the candidate, e.g. `gR` in `getRecord` rather than `getrecord`.
+    let mut scores: Vec<Option<i32>> = vec![None; candidate.len()];
+    for (i, n) in needle.iter().enumerate() {
+        let mut next: Vec<Option<i32>> = vec![None; candidate.len()];
+        for (j, c) in candidate.iter().enumerate() {
+            if !eq_ignore_case(*c, *n) {
+                continue;
+            }
+            let previous = if i == 0 {
+                Some(0)
+            } else {
+                (0..j)
+                    .filter_map(|k| {
+                        scores[k].map(|score| {
+                            if k + 1 == j {
+                                score + BONUS_CONSECUTIVE
+                            } else {
+                                score - PENALTY_GAP
+                            }
+                        })
+                    })
+                    .max()
+            };
+            if let Some(previous) = previous {
+                let case = if c == n { BONUS_CASE } else { 0 };
+                next[j] = Some(
+                    previous
+                        + SCORE_MATCH
+                        + boundary_bonus(&candidate, j)
+                        + case,
+                );
+            }
+        }
+        scores = next;
+    }
+    let mut best = scores.into_iter().flatten().max()?;
+
+    let prefix = candidate
+        .iter()
+        .zip(needle.iter())
+        .all(|(c, n)| eq_ignore_case(*c, *n));
+    if prefix {
+        best += BONUS_PREFIX;
+        if candidate.len() == needle.len() {
+            best += BONUS_EXACT;
+        }
+    }
+    Some(
+        best - PENALTY_UNMATCHED
+            * (candidate.len() - needle.len()) as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subsequence_matches() {
+        assert!(score("aggregateWindow", "aggW").is_some());
+        assert!(score("aggregateWindow", "agw").is_some());
+        assert!(score("aggregateWindow", "").is_some());
+        assert!(score("aggregateWindow", "wa").is_none());
+        assert!(score("map", "mapp").is_none());
+    }
+
+    #[test]
+    fn prefix_ranks_above_subsequence() {
+        assert!(score("count", "co") > score("bucketOffset", "co"));
+        assert!(
+            score("filter", "fil")
+                > score("findFirstIndexLast", "fil")
+        );
+    }
+
+    #[test]
+    fn exact_ranks_above_prefix() {
+        assert!(score("map", "map") > score("mapValues", "map"));
+        assert!(score("sql", "sql") > score("sqlite", "sql"));
+    }
+
+    #[test]
+    fn camel_case_boundaries_rank_above_inner_matches() {
+        assert!(score("getRecord", "gr") > score("genre", "gr"));
+        assert!(
+            score("aggregateWindow", "aw") > score("drawn", "aw")
+        );
+    }
+
+    #[test]
+    fn matching_case_ranks_above_other_case() {
+        assert!(score("toUpper", "toU") > score("toUpper", "tou"));
+    }
+}
@@ -14,6 +14,7 @@ use std::iter::Iterator;
 lazy_static::lazy_static! {
     pub static ref STDLIB: Stdlib = Stdlib(flux::imports().expect("Could not initialize stdlib."));
     pub static ref UNIVERSE: Package = Package::new("builtin", Arc::new(flux::prelude().expect("Could not initialize prelude")));
+    pub static ref STDLIB_DOCS: Vec<PackageDoc> = serde_json::from_str(include_str!("../data/stdlib-docs.json")).expect("Could not parse stdlib docs.");
 }
 
 /// Stdlib serves as the API for querying the flux stdlib.
@@ -35,8 +36,8 @@ impl Stdlib {
         self.packages().find(|package| package.path == path)
     }
 
-    /// Get all packages that fuzzy match on the needle.
-    pub fn fuzzy_matches<'a>(
+    /// Get all packages whose name contains the needle, ignoring case.
+    pub fn matches<'a>(
         &'a self,
         needle: &'a str,
     ) -> impl Iterator<Item = Package> + '_ {
@@ -47,6 +48,23 @@ impl Stdlib {
                 .contains(needle.to_lowercase().as_str())
         })
     }
+
+    /// Get all packages that fuzzy match on the needle, best matches first.
+    pub fn fuzzy_matches<'a>(
+        &'a self,
+        needle: &'a str,
+    ) -> impl Iterator<Item = Package> + '_ {
+        let mut matches: Vec<(i32, Package)> = self
+            .packages()
+            .filter_map(|package| {
+                crate::fuzzy::score(&package.name, needle)
+                    .map(|score| (score, package))
+            })
+            .collect();
+        // The sort is stable, so packages with the same score stay in path order.
+        matches.sort_by(|a, b| b.0.cmp(&a.0));
+        matches.into_iter().map(|(_, package)| package)
+    }
 }
 
 /// Package represents a flux package.
@@ -87,6 +105,7 @@ impl Package {
                 .map(|property| match &property.v {
                     MonoType::Fun(f) => Function {
                         name: property.k.to_string(),
+                        package: self.doc_path().into(),
                         expr: f.as_ref().clone(),
                     },
                     _ => unreachable!(
@@ -113,6 +132,71 @@ impl Package {
             .find(|function| function.name == name)
             .cloned()
     }
+
+    /// Get the type of a member of the package, which may not be a function.
+    pub fn member(&self, name: &str) -> Option<MonoType> {
+        if let MonoType::Record(record) = self.exports.typ().expr {
+            record
+                .fields()
+                .find(|property| property.k.to_string() == name)
+                .map(|property| property.v.clone())
+        } else {
+            None
+        }
+    }
+
+    /// The path the package is documented under. The prelude is documented as
+    /// the `universe` package.
+    fn doc_path(&self) -> &str {
+        if self.path == "builtin" {
+            "universe"
+        } else {
+            &self.path
+        }
+    }
+
+    /// Get the bundled documentation of the package.
+    pub fn doc(&self) -> Option<&'static PackageDoc> {
+        package_doc(self.doc_path())
+    }
+
+    /// Render the package overview and its exported members as markdown.
+    pub fn markdown(&self) -> String {
+        let doc = self.doc();
+        let mut markdown = match doc {
+            Some(doc) => doc.markdown(),
+            None => format!("```flux\nimport \"{}\"\n```", self.path),
+        };
+        if let MonoType::Record(record) = self.exports.typ().expr {
+            let members: Vec<String> = record
+                .fields()
+                .filter(|property| {
+                    !property.k.to_string().starts_with('_')
+                })
+                .map(|property| {
+                    let name = property.k.to_string();
+                    match doc.and_then(|doc| doc.member(&name)) {
+                        Some(member) => {
+                            format!(
+                                "- `{}`: {}",
+                                name, member.headline
+                            )
+                        }
+                        None => {
+                            format!("- `{}`: `{}`", name, property.v)
+                        }
+                    }
+                })
+                .collect();
+            if !members.is_empty() {
+                markdown.push_str(&format!(
+                    "\n\n**Members**\n\n{}",
+                    members.join("\n")
+                ));
+            }
+        }
+        markdown
+    }
 }
 
 /// A flux function struct
@@ -126,6 +210,8 @@ impl Package {
 #[derive(Clone, Debug)]
 pub struct Function {
     pub name: String,
+    /// The path the package of the function is documented under.
+    package: String,
     expr: flux::semantic::types::Function,
 }
 
@@ -207,6 +293,115 @@ impl Function {
             .collect()
     }
 
+    /// Get the bundled documentation of the function.
+    pub fn doc(&self) -> Option<&'static MemberDoc> {
+        package_doc(&self.package)?.member(&self.name)
+    }
+
+    /// The link to the documentation of the function. Undocumented functions
+    /// link to where the stdlib documentation would have them.
+    pub fn link(&self) -> String {
+        match self.doc() {
+            Some(doc) => doc.link.clone(),
+            None => format!(
+                "https://docs.influxdata.com/flux/v0.x/stdlib/{}/{}/",
+                self.package,
+                self.name.to_lowercase()
+            ),
+        }
+    }
+
+    /// Render the function documentation as markdown: its signature, a table of
+    /// its parameters, its return type, an example and a link to its docs. Only
+    /// the signature and parameter types are known for undocumented functions.
+    pub fn markdown(&self) -> String {
+        let doc = self.doc();
+        let qualified = if self.package == "universe" {
+            self.name.clone()
+        } else {
+            format!(
+                "{}.{}",
+                self.package
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(&self.package),
+                self.name
+            )
+        };
+        let mut markdown = format!(
+            "```flux\n{}: {}\n```",
+            qualified,
+            doc.map(|doc| doc.flux_type.clone())
+                .unwrap_or_else(|| self.expr.to_string())
+        );
+        if let Some(doc) = doc {
+            markdown.push_str(&format!("\n\n{}", doc.headline));
+            if let Some(description) = &doc.description {
+                markdown.push_str(&format!("\n\n{}", description));
+            }
+        }
+
+        let mut parameters = self.parameters();
+        parameters.sort_by(|a, b| a.0.cmp(&b.0));
+        if !parameters.is_empty() {
+            markdown.push_str(
+                "\n\n| Parameter | Type | Description |\n| --- | --- | --- |",
+            );
+            for (name, typ) in parameters {
+                let parameter_doc = doc.and_then(|doc| {
+                    doc.parameters
+                        .iter()
+                        .find(|parameter| parameter.name == name)
+                });
+                let optional = if self.expr.opt.contains_key(&name) {
+                    "(optional) "
+                } else {
+                    ""
+                };
+                let description = match parameter_doc {
+                    Some(parameter) => match &parameter.description {
+                        Some(description) => {
+                            format!(
+                                "{} {}",
+                                parameter.headline, description
+                            )
+                        }
+                        None => parameter.headline.clone(),
+                    },
+                    None => String::new(),
+                };
+                markdown.push_str(&format!(
+                    "\n| `{}` | `{}` | {}{} |",
+                    name,
+                    typ,
+                    optional,
+                    description
+                        .replace('\n', " ")
+                        .replace('|', "\\|")
+                ));
+            }
+        }
+        markdown.push_str(&format!(
+            "\n\n**Returns**: `{}`",
+            self.expr.retn
+        ));
+
+        if let Some(doc) = doc {
+            // A single example is enough to show the function in use.
+            if let Some(example) = doc.examples.first() {
+                markdown.push_str(&format!(
+                    "\n\n**Example**: {}\n\n```flux\n{}\n```",
+                    example.title, example.content
+                ));
+            }
+            markdown.push_str(&format!(
+                "\n\n[Documentation]({})",
+                doc.link
+            ));
+        }
+        markdown
+    }
+
     pub fn parameters(&self) -> Vec<(String, MonoType)> {
         self.expr
             .req
@@ -218,6 +413,324 @@ impl Function {
     }
 }
 
+/// Parameters of stdlib functions that only accept a fixed set of string values,
+/// by package path, function name and parameter name. The prelude is the
+/// `universe` package.
+const PARAMETER_VALUES: &[(&str, &str, &str, &[&str])] = &[
+    (
+        "universe",
+        "aggregateWindow",
+        "timeSrc",
+        &["_start", "_stop", "_time"],
+    ),
+    (
+        "universe",
+        "aggregateWindow",
+        "timeDst",
+        &["_time", "_start", "_stop"],
+    ),
+    ("universe", "filter", "onEmpty", &["drop", "keep"]),
+    ("universe", "group", "mode", &["by", "except"]),
+    (
+        "universe",
+        "median",
+        "method",
+        &["estimate_tdigest", "exact_mean", "exact_selector"],
+    ),
+    (
+        "universe",
+        "quantile",
+        "method",
+        &["estimate_tdigest", "exact_mean", "exact_selector"],
+    ),
+    ("csv", "from", "mode", &["annotations", "raw"]),
+    (
+        "http/requests",
+        "do",
+        "method",
+        &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD"],
+    ),
+    (
+        "join",
+        "tables",
+        "method",
+        &["inner", "left", "right", "full"],
+    ),
+];
+
+/// Get the valid values of a stdlib function parameter that only accepts a fixed
+/// set of strings, or an empty slice if it accepts any value.
+pub fn parameter_values(
+    package: &str,
+    function: &str,
+    parameter: &str,
+) -> &'static [&'static str] {
+    PARAMETER_VALUES
+        .iter()
+        .find(|(p, f, name, _)| {
+            *p == package && *f == function && *name == parameter
+        })
+        .map_or(&[], |(_, _, _, values)| values)
+}
+
+/// Documentation for a stdlib package, in the format produced by `fluxdoc`.
+///
+/// The docs are bundled into the binary from `data/stdlib-docs.json`.
+/// Regenerate it with `stdlib-docs.sh`, which runs `fluxdoc` on the flux
+/// version pinned in `Cargo.toml`. Members without docs in the file have no
+/// documentation in hover and completion.
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct PackageDoc {
+    pub path: String,
+    pub name: String,
+    pub headline: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub link: String,
+    #[serde(default)]
+    pub members: std::collections::BTreeMap<String, MemberDoc>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct MemberDoc {
+    pub kind: String,
+    pub name: String,
+    pub headline: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub parameters: Vec<ParameterDoc>,
+    pub flux_type: String,
+    pub link: String,
+    #[serde(default)]
+    pub examples: Vec<ExampleDoc>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ParameterDoc {
+    pub name: String,
+    pub headline: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub required: bool,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ExampleDoc {
+    pub title: String,
+    pub content: String,
+}
+
+/// Get the documentation for a stdlib package by path. The prelude is
+/// documented as the `universe` package.
+pub fn package_doc(path: &str) -> Option<&'static PackageDoc> {
+    STDLIB_DOCS.iter().find(|doc| doc.path == path)
+}
+
+impl PackageDoc {
+    /// Get the documentation for a member of the package.
+    pub fn member(&self, name: &str) -> Option<&MemberDoc> {
+        self.members.get(name)
+    }
+
+    /// Render the package documentation as markdown.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!(
+            "```flux\nimport \"{}\"\n```\n\n{}",
+            self.path, self.headline
+        );
+        if let Some(description) = &self.description {
+            markdown.push_str(&format!("\n\n{}", description));
+        }
+        markdown
+            .push_str(&format!("\n\n[Documentation]({})", self.link));
+        markdown
+    }
+}
+
+impl MemberDoc {
+    /// Render the member documentation as markdown.
+    pub fn markdown(&self) -> String {
+        let mut markdown = format!(
+            "```flux\n{}: {}\n```\n\n{}",
+            self.name, self.flux_type, self.headline
+        );
+        if let Some(description) = &self.description {
+            markdown.push_str(&format!("\n\n{}", description));
+        }
+        if !self.parameters.is_empty() {
+            markdown.push_str("\n\n**Parameters**\n");
+            for parameter in &self.parameters {
+                markdown.push_str(&format!(
+                    "\n- `{}`{}: {}",
+                    parameter.name,
+                    if parameter.required {
+                        ""
+                    } else {
+                        " (optional)"
+                    },
+                    parameter.headline
+                ));
+                if let Some(description) = &parameter.description {
+                    markdown.push_str(&format!(" {}", description));
+                }
+            }
+        }
+        // A single example is enough to show the function in use.
+        if let Some(example) = self.examples.first() {
+            markdown.push_str(&format!(
+                "\n\n**Example**: {}\n\n```flux\n{}\n```",
+                example.title, example.content
+            ));
+        }
+        markdown
+            .push_str(&format!("\n\n[Documentation]({})", self.link));
+        markdown
+    }
+}
+
+/// A runtime option, which scripts set with an `option` statement.
+#[derive(Debug)]
+pub struct RuntimeOption {
+    /// The path of the package of the option, or None for options that aren't
+    /// in a package.
+    pub package: Option<&'static str>,
+    pub name: &'static str,
+    /// The value written when the option is completed, as a snippet.
+    pub snippet: &'static str,
+    pub description: &'static str,
+    /// What reads the option when the script runs.
+    pub consumer: &'static str,
+    /// The fields of an option that is a record, with their types.
+    pub fields: &'static [(&'static str, &'static str)],
+}
+
+/// The options known to be read by a runtime.
+pub const RUNTIME_OPTIONS: &[RuntimeOption] = &[
+    RuntimeOption {
+        package: None,
+        name: "now",
+        snippet: "() => ${1:2022-01-01T00:00:00Z}",
+        description: "The function that returns the current time. Relative times, such as the `-1h` of `range(start: -1h)`, are relative to it.",
+        consumer: "the Flux runtime, which sets it to the time the query starts unless a script sets it",
+        fields: &[],
+    },
+    RuntimeOption {
+        package: None,
+        name: "task",
+        snippet: "{name: \"${1:name}\", every: ${2:1h}}",
+        description: "Runs the script as a task, on the schedule of either `every` or `cron`.",
+        consumer: "the InfluxDB task scheduler, which ignores it when the script is run as a query",
+        fields: &[
+            ("name", "string"),
+            ("every", "duration"),
+            ("cron", "string"),
+            ("offset", "duration"),
+            ("concurrency", "int"),
+            ("retry", "int"),
+        ],
+    },
+    RuntimeOption {
+        package: None,
+        name: "location",
+        snippet: "{zone: \"${1:UTC}\", offset: ${2:0h}}",
+        description: "The time zone of calendar times, e.g. the boundaries of the windows of `window` and `aggregateWindow` and the results of `date` functions.",
+        consumer: "the Flux runtime, which uses UTC unless a script sets it",
+        fields: &[("zone", "string"), ("offset", "duration")],
+    },
+    RuntimeOption {
+        package: Some("influxdata/influxdb"),
+        name: "defaultURL",
+        snippet: "\"${1:http://localhost:8086}\"",
+        description: "The URL of the InfluxDB instance read and written by `from` and `to` when they aren't given a `host`.",
+        consumer: "the `influxdata/influxdb` package",
+        fields: &[],
+    },
+];
+
+/// Get a runtime option by package path and name.
+pub fn runtime_option(
+    package: Option<&str>,
+    name: &str,
+) -> Option<&'static RuntimeOption> {
+    RUNTIME_OPTIONS.iter().find(|option| {
+        option.package == package && option.name == name
+    })
+}
+
+/// A snippet placeholder for a value of a type, e.g. `"$1"` for strings.
+pub fn type_placeholder(typ: &str) -> &'static str {
+    match typ {
+        "string" => "\"$1\"",
+        "duration" => "${1:1h}",
+        "int" => "${1:1}",
+        "bool" => "${1:true}",
+        _ => "$1",
+    }
+}
+
+/// Stdlib functions that are deprecated, as (package path, name, replacement).
+pub const DEPRECATED_FUNCTIONS: &[(&str, &str, &str)] = &[
+    ("experimental", "addDuration", "date.add"),
+    ("experimental", "subDuration", "date.sub"),
+    ("experimental/http", "get", "http/requests.get"),
+    (
+        "influxdata/influxdb/v1",
+        "fieldsAsCols",
+        "schema.fieldsAsCols",
+    ),
+    (
+        "influxdata/influxdb/v1",
+        "measurements",
+        "schema.measurements",
+    ),
+    (
+        "influxdata/influxdb/v1",
+        "measurementTagKeys",
+        "schema.measurementTagKeys",
+    ),
+    (
+        "influxdata/influxdb/v1",
+        "measurementTagValues",
+        "schema.measurementTagValues",
+    ),
+    ("influxdata/influxdb/v1", "tagKeys", "schema.tagKeys"),
+    ("influxdata/influxdb/v1", "tagValues", "schema.tagValues"),
+];
+
+/// Get the replacement of a deprecated stdlib function, or None if the function
+/// isn't deprecated.
+pub fn deprecated_function(
+    package: &str,
+    name: &str,
+) -> Option<&'static str> {
+    DEPRECATED_FUNCTIONS
+        .iter()
+        .find(|(path, function, _)| {
+            *path == package && *function == name
+        })
+        .map(|(_, _, replacement)| *replacement)
+}
+
+impl RuntimeOption {
+    /// Render a description of the option as markdown, with the name it is
+    /// written with in the script, e.g. an alias of its package.
+    pub fn markdown(&self, name: &str) -> String {
+        let mut markdown = format!(
+            "```flux\noption {}\n```\n\n{}\n\nRead by {}.",
+            name, self.description, self.consumer
+        );
+        if !self.fields.is_empty() {
+            markdown.push_str("\n\n**Fields**\n");
+            for (field, typ) in self.fields {
+                markdown
+                    .push_str(&format!("\n- `{}`: {}", field, typ));
+            }
+        }
+        markdown
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -599,4 +1112,209 @@ mod tests {
             .unwrap(),
         );
     }
+
+    /// Every documented package and member exists in the stdlib, so the
+    /// bundled docs don't drift from the flux version in use.
+    #[test]
+    fn stdlib_docs_match_stdlib() {
+        for doc in STDLIB_DOCS.iter() {
+            let package = if doc.path == "universe" {
+                UNIVERSE.clone()
+            } else {
+                STDLIB.package(&doc.path).unwrap_or_else(|| {
+                    panic!("{} is not a stdlib package", doc.path)
+                })
+            };
+            for name in doc.members.keys() {
+                assert!(
+                    package.function(name).is_some(),
+                    "{}.{} is not a stdlib function",
+                    doc.path,
+                    name
+                );
+            }
+        }
+    }
+
+    /// Every runtime option of a package is exported by the package.
+    #[test]
+    fn runtime_options_match_stdlib() {
+        for option in RUNTIME_OPTIONS {
+            let path = match option.package {
+                Some(path) => path,
+                None => continue,
+            };
+            let package = STDLIB.package(path).unwrap_or_else(|| {
+                panic!("{} is not a stdlib package", path)
+            });
+            assert!(
+                package
+                    .exports
+                    .iter()
+                    .any(|(key, _)| *key == option.name),
+                "{}.{} is not exported",
+                path,
+                option.name
+            );
+        }
+        assert!(runtime_option(None, "task").is_some());
+        assert!(runtime_option(Some("influxdata/influxdb"), "task")
+            .is_none());
+    }
+
+    #[test]
+    fn runtime_option_markdown() {
+        expect_test::expect![[r#"
+            ```flux
+            option location
+            ```
+
+            The time zone of calendar times, e.g. the boundaries of the windows of `window` and `aggregateWindow` and the results of `date` functions.
+
+            Read by the Flux runtime, which uses UTC unless a script sets it.
+
+            **Fields**
+
+            - `zone`: string
+            - `offset`: duration"#]]
+        .assert_eq(
+            &runtime_option(None, "location").unwrap().markdown("location"),
+        );
+    }
+
+    /// Every function with known parameter values exists in the stdlib, with
+    /// a string parameter of that name.
+    #[test]
+    fn parameter_values_match_stdlib() {
+        for (path, name, parameter, _) in PARAMETER_VALUES {
+            let package = if *path == "universe" {
+                UNIVERSE.clone()
+            } else {
+                STDLIB.package(path).unwrap()
+            };
+            let function =
+                package.function(name).unwrap_or_else(|| {
+                    panic!(
+                        "{}.{} is not a stdlib function",
+                        path, name
+                    )
+                });
+            assert!(
+                function
+                    .parameters()
+                    .iter()
+                    .any(|(k, v)| k == parameter
+                        && *v == MonoType::STRING),
+                "{}.{} has no string parameter {}",
+                path,
+                name,
+                parameter
+            );
+        }
+        assert_eq!(
+            &["by", "except"],
+            parameter_values("universe", "group", "mode")
+        );
+        assert!(parameter_values("universe", "group", "columns")
+            .is_empty());
+    }
+
+    #[test]
+    fn member_doc_markdown() {
+        let doc = package_doc("strings")
+            .unwrap()
+            .member("toUpper")
+            .unwrap();
+
+        expect_test::expect![[r#"
+            ```flux
+            toUpper: (v: string) => string
+            ```
+
+            strings.toUpper() converts a string to uppercase.
+
+            **Parameters**
+
+            - `v`: String value to convert.
+
+            **Example**: Convert all values of a column to upper case
+
+            ```flux
+            import "strings"
+
+            data
+                |> map(fn: (r) => ({r with _value: strings.toUpper(v: r._value)}))
+            ```
+
+            [Documentation](https://docs.influxdata.com/flux/v0.x/stdlib/strings/toupper/)"#]]
+        .assert_eq(&doc.markdown());
+    }
+
+    #[test]
+    fn package_doc_markdown() {
+        let doc = package_doc("csv").unwrap();
+
+        expect_test::expect![[r#"
+            ```flux
+            import "csv"
+            ```
+
+            Package csv provides functions for working with data in annotated CSV format.
+
+            [Documentation](https://docs.influxdata.com/flux/v0.x/stdlib/csv/)"#]]
+        .assert_eq(&doc.markdown());
+    }
+
+    #[test]
+    fn function_markdown() {
+        let function = STDLIB
+            .package("strings")
+            .unwrap()
+            .function("trim")
+            .unwrap();
+
+        expect_test::expect![[r#"
+            ```flux
+            strings.trim: (cutset: string, v: string) => string
+            ```
+
+            strings.trim() removes leading and trailing characters specified in the cutset from a string.
+
+            | Parameter | Type | Description |
+            | --- | --- | --- |
+            | `cutset` | `string` | Leading and trailing characters to remove from the string. Only characters that match the cutset string exactly are trimmed. |
+            | `v` | `string` | String to remove characters from. |
+
+            **Returns**: `string`
+
+            **Example**: Trim leading and trailing periods from a string
+
+            ```flux
+            import "strings"
+
+            strings.trim(v: ".abc.", cutset: ".")
+            ```
+
+            [Documentation](https://docs.influxdata.com/flux/v0.x/stdlib/strings/trim/)"#]]
+        .assert_eq(&function.markdown());
+    }
+
+    #[test]
+    fn package_markdown() {
+        let package = STDLIB.package("csv").unwrap();
+
+        expect_test::expect![[r#"
+            ```flux
+            import "csv"
+            ```
+
+            Package csv provides functions for working with data in annotated CSV format.
+
+            [Documentation](https://docs.influxdata.com/flux/v0.x/stdlib/csv/)
+
+            **Members**
+
+            - `from`: csv.from() retrieves annotated CSV from a file or raw string."#]]
+        .assert_eq(&package.markdown());
+    }
 }
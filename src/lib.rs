@@ -8,8 +8,11 @@
 mod completion;
 mod composition;
 mod diagnostics;
+mod fuzzy;
 mod lang;
 mod lsp;
+mod pipeline;
+mod refactor;
 mod server;
 mod visitors;
 #[cfg(feature = "wasm")]
@@ -14,6 +14,112 @@ pub fn position_in_range(
     (range.start..=range.end).contains(position)
 }
 
+/// Return the byte offset of a Position in the provided text.
+pub fn offset_of(
+    contents: &str,
+    position: &lsp::Position,
+) -> Option<usize> {
+    let mut offset = 0;
+    for (index, line) in contents.split_inclusive('\n').enumerate() {
+        if index as u32 == position.line {
+            return line
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(line.len()))
+                .nth(position.character as usize)
+                .map(|i| offset + i);
+        }
+        offset += line.len();
+    }
+    // A position on the line after a trailing newline is the end of the text.
+    if position.character == 0
+        && (contents.is_empty() || contents.ends_with('\n'))
+        && position.line as usize == contents.lines().count()
+    {
+        return Some(contents.len());
+    }
+    None
+}
+
+/// Return the text found within the provided Range.
+pub fn text_in_range(
+    contents: &str,
+    range: &lsp::Range,
+) -> Option<String> {
+    let start = offset_of(contents, &range.start)?;
+    let end = offset_of(contents, &range.end)?;
+    contents.get(start..end).map(String::from)
+}
+
+/// Return the Position of a byte offset in the provided text, the inverse of
+/// `offset_of`.
+pub fn position_of(contents: &str, offset: usize) -> lsp::Position {
+    let before = &contents[..offset.min(contents.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    lsp::Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].chars().count() as u32,
+    }
+}
+
+/// Encode semantic tokens for a response. The tokens are given with absolute
+/// positions, ordered by position, and each token of the result is relative
+/// to the one before it.
+pub fn encode_semantic_tokens(
+    tokens: &[lsp::SemanticToken],
+) -> Vec<lsp::SemanticToken> {
+    let mut line = 0;
+    let mut start = 0;
+    tokens
+        .iter()
+        .map(|token| {
+            let delta_line = token.delta_line - line;
+            let delta_start = if delta_line == 0 {
+                token.delta_start - start
+            } else {
+                token.delta_start
+            };
+            line = token.delta_line;
+            start = token.delta_start;
+            lsp::SemanticToken {
+                delta_line,
+                delta_start,
+                length: token.length,
+                token_type: token.token_type,
+                token_modifiers_bitset: token.token_modifiers_bitset,
+            }
+        })
+        .collect()
+}
+
+/// The edits from one list of encoded semantic tokens to another: a single edit
+/// replacing the tokens between their common prefix and suffix. The `start` and
+/// `delete_count` of an edit count integers, of which a token has five.
+pub fn semantic_tokens_edits(
+    previous: &[lsp::SemanticToken],
+    current: &[lsp::SemanticToken],
+) -> Vec<lsp::SemanticTokensEdit> {
+    if previous == current {
+        return vec![];
+    }
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    vec![lsp::SemanticTokensEdit {
+        start: 5 * prefix as u32,
+        delete_count: 5 * (previous.len() - prefix - suffix) as u32,
+        data: Some(current[prefix..current.len() - suffix].to_vec()),
+    }]
+}
+
 #[cfg(test)]
 mod test {
     use lspower::lsp;
@@ -135,4 +241,127 @@ mod test {
         assert!(position_in_range(&range.start, &range));
         assert!(position_in_range(&range.end, &range));
     }
+
+    #[test]
+    fn text_in_range_single_line() {
+        let contents = "x = 1\ny = x + 1\n";
+        let range = lsp::Range {
+            start: lsp::Position {
+                line: 1,
+                character: 4,
+            },
+            end: lsp::Position {
+                line: 1,
+                character: 9,
+            },
+        };
+        assert_eq!(
+            Some("x + 1".to_string()),
+            text_in_range(contents, &range)
+        );
+    }
+
+    #[test]
+    fn text_in_range_multiple_lines() {
+        let contents = "x = 1\ny = x + 1\n";
+        let range = lsp::Range {
+            start: lsp::Position {
+                line: 0,
+                character: 4,
+            },
+            end: lsp::Position {
+                line: 2,
+                character: 0,
+            },
+        };
+        assert_eq!(
+            Some("1\ny = x + 1\n".to_string()),
+            text_in_range(contents, &range)
+        );
+    }
+
+    #[test]
+    fn offset_of_out_of_bounds() {
+        let contents = "x = 1";
+        let position = lsp::Position {
+            line: 3,
+            character: 0,
+        };
+        assert_eq!(None, offset_of(contents, &position));
+    }
+
+    #[test]
+    fn position_of_offset() {
+        let contents = "x = 1\ny = x + 1\n";
+        assert_eq!(
+            lsp::Position {
+                line: 1,
+                character: 4,
+            },
+            position_of(contents, 10)
+        );
+        assert_eq!(
+            Some(10),
+            offset_of(contents, &position_of(contents, 10))
+        );
+    }
+
+    fn token(
+        line: u32,
+        start: u32,
+        length: u32,
+        token_type: u32,
+    ) -> lsp::SemanticToken {
+        lsp::SemanticToken {
+            delta_line: line,
+            delta_start: start,
+            length,
+            token_type,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn encode_semantic_tokens_relative() {
+        let tokens = vec![
+            token(0, 2, 1, 0),
+            token(0, 6, 1, 1),
+            token(2, 4, 2, 2),
+        ];
+        assert_eq!(
+            vec![
+                token(0, 2, 1, 0),
+                token(0, 4, 1, 1),
+                token(2, 4, 2, 2)
+            ],
+            encode_semantic_tokens(&tokens)
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_edits_replace_middle() {
+        let previous = vec![
+            token(0, 2, 1, 0),
+            token(0, 4, 1, 1),
+            token(2, 4, 2, 2),
+        ];
+        let current = vec![
+            token(0, 2, 1, 0),
+            token(0, 4, 3, 1),
+            token(0, 4, 1, 3),
+            token(2, 4, 2, 2),
+        ];
+        assert_eq!(
+            vec![lsp::SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![
+                    token(0, 4, 3, 1),
+                    token(0, 4, 1, 3)
+                ]),
+            }],
+            semantic_tokens_edits(&previous, &current)
+        );
+        assert!(semantic_tokens_edits(&current, &current).is_empty());
+    }
 }
@@ -0,0 +1,631 @@
+/// Static inference of the columns of the tables flowing through a pipeline.
+///
+/// The columns are found from the source of a pipeline, e.g. the standard columns
+/// read by `from` or the record type of the rows of `array.from`, and each stage is
+/// applied to them in turn. Only the columns known from the source text are
+/// inferred: the tags read by `from`, or the columns created by `pivot`, depend on
+/// the data and are not included.
+use std::collections::HashMap;
+
+use flux::ast::{self, Expression, PropertyKey};
+use flux::semantic::types::{CollectionType, MonoType};
+
+use crate::completion;
+
+/// The columns of every table read by `from`.
+const FROM_COLUMNS: &[&str] = &[
+    "_time",
+    "_value",
+    "_field",
+    "_measurement",
+    "_start",
+    "_stop",
+];
+
+/// The columns of the annotated CSV format that aren't columns of the tables.
+const CSV_ANNOTATION_COLUMNS: &[&str] = &["result", "table"];
+
+/// The stages that only filter, reorder or regroup the rows of their input, and
+/// keep its columns. Any other stage that isn't inferred may add columns.
+const COLUMN_PRESERVING_STAGES: &[&str] = &[
+    "bottom",
+    "fill",
+    "filter",
+    "first",
+    "group",
+    "last",
+    "limit",
+    "max",
+    "min",
+    "sample",
+    "sort",
+    "tail",
+    "timeShift",
+    "top",
+    "unique",
+    "yield",
+];
+
+/// Pipelines are resolved through variables no deeper than this, so invalid
+/// recursive assignments can't loop forever.
+const MAX_DEPTH: usize = 32;
+
+/// Infers the columns of pipelines in a file.
+pub(crate) struct Pipeline<'a> {
+    pkg: &'a flux::semantic::nodes::Package,
+    variables: HashMap<&'a str, &'a Expression>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Create a pipeline inference for a file, which resolves the variables it
+    /// assigns. Without a file, variables are not resolved.
+    pub fn new(
+        pkg: &'a flux::semantic::nodes::Package,
+        file: Option<&'a ast::File>,
+    ) -> Self {
+        let variables = file
+            .map(|file| {
+                file.body
+                    .iter()
+                    .filter_map(|statement| match statement {
+                        ast::Statement::Variable(assign) => Some((
+                            assign.id.name.as_str(),
+                            &assign.init,
+                        )),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { pkg, variables }
+    }
+
+    /// The schema of the tables produced by an expression, or None if the
+    /// expression isn't a pipeline with a known source.
+    pub fn schema(&self, expr: &Expression) -> Option<Schema> {
+        self.schema_at_depth(expr, 0)
+    }
+
+    fn schema_at_depth(
+        &self,
+        expr: &Expression,
+        depth: usize,
+    ) -> Option<Schema> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match expr {
+            Expression::PipeExpr(pipe) => {
+                let input =
+                    self.schema_at_depth(&pipe.argument, depth + 1)?;
+                Some(apply_stage(&pipe.call, input))
+            }
+            Expression::Call(call) => self.source(call),
+            Expression::Identifier(identifier) => {
+                let init =
+                    self.variables.get(identifier.name.as_str())?;
+                self.schema_at_depth(init, depth + 1)
+            }
+            Expression::Paren(paren) => {
+                self.schema_at_depth(&paren.expression, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    /// The schema of the tables read by the source of a pipeline.
+    fn source(&self, call: &ast::CallExpr) -> Option<Schema> {
+        let (path, name) =
+            completion::callee_path(self.pkg, &call.callee)?;
+        let columns = match (path.as_str(), name.as_str()) {
+            // The tags are columns too, but they depend on the data.
+            ("universe", "from")
+            | ("influxdata/influxdb", "from") => {
+                return Some(Schema::new(
+                    FROM_COLUMNS
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect(),
+                    true,
+                ))
+            }
+            ("array", "from") => {
+                let rows = argument(call, "rows")?;
+                match completion::expression_type(self.pkg, rows) {
+                    Some(MonoType::Collection(collection))
+                        if collection.collection
+                            == CollectionType::Array =>
+                    {
+                        match &collection.arg {
+                            MonoType::Record(record) => Some(
+                                record
+                                    .fields()
+                                    .map(|field| field.k.to_string())
+                                    .collect(),
+                            ),
+                            _ => None,
+                        }
+                    }
+                    _ => object_keys(rows),
+                }
+            }
+            ("csv", "from") => match argument(call, "csv")? {
+                Expression::StringLit(lit) => csv_columns(&lit.value),
+                _ => None,
+            },
+            _ => None,
+        };
+        columns.map(|columns| Schema::new(columns, false))
+    }
+}
+
+/// The value of a named argument of a call.
+fn argument<'a>(
+    call: &'a ast::CallExpr,
+    name: &str,
+) -> Option<&'a Expression> {
+    match call.arguments.first()? {
+        Expression::Object(object) => {
+            object.properties.iter().find_map(|property| {
+                match (&property.key, &property.value) {
+                    (PropertyKey::Identifier(key), Some(value))
+                        if key.name == name =>
+                    {
+                        Some(value)
+                    }
+                    _ => None,
+                }
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The values of an array of string literals, e.g. `["_time", "host"]`.
+fn string_list(expr: &Expression) -> Option<Vec<String>> {
+    match expr {
+        Expression::Array(array) => array
+            .elements
+            .iter()
+            .map(|element| match &element.expression {
+                Expression::StringLit(lit) => Some(lit.value.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn string_argument(
+    call: &ast::CallExpr,
+    name: &str,
+) -> Option<String> {
+    match argument(call, name)? {
+        Expression::StringLit(lit) => Some(lit.value.clone()),
+        _ => None,
+    }
+}
+
+fn property_name(key: &PropertyKey) -> &str {
+    match key {
+        PropertyKey::Identifier(identifier) => &identifier.name,
+        PropertyKey::StringLit(lit) => &lit.value,
+    }
+}
+
+/// The keys of the first object of an array of objects, e.g. the rows of
+/// `array.from` when their type can't be inferred.
+fn object_keys(expr: &Expression) -> Option<Vec<String>> {
+    match expr {
+        Expression::Array(array) => {
+            match &array.elements.first()?.expression {
+                Expression::Object(object) => Some(
+                    object
+                        .properties
+                        .iter()
+                        .map(|property| {
+                            property_name(&property.key).into()
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The columns named by the header row of CSV data. The columns of annotated CSV
+/// that only identify results and tables are left out.
+fn csv_columns(csv: &str) -> Option<Vec<String>> {
+    let mut lines =
+        csv.lines().map(str::trim).filter(|line| !line.is_empty());
+    let mut annotated = false;
+    let header = loop {
+        let line = lines.next()?;
+        if line.starts_with('#') {
+            annotated = true;
+        } else {
+            break line;
+        }
+    };
+    Some(
+        header
+            .split(',')
+            .map(str::trim)
+            .filter(|column| {
+                !column.is_empty()
+                    && !(annotated
+                        && CSV_ANNOTATION_COLUMNS.contains(column))
+            })
+            .map(String::from)
+            .collect(),
+    )
+}
+
+/// The columns of the tables at some stage of a pipeline.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Schema {
+    /// The known columns, in the order they were first known.
+    pub columns: Vec<String>,
+    /// Whether the tables may have columns that aren't known, e.g. the tags
+    /// read by `from`.
+    pub open: bool,
+    /// The columns removed by earlier stages, which are known not to be in the
+    /// tables even when they may have unknown columns.
+    removed: Vec<String>,
+}
+
+impl Schema {
+    fn new(columns: Vec<String>, open: bool) -> Self {
+        Self {
+            columns,
+            open,
+            removed: vec![],
+        }
+    }
+
+    /// Add a column, unless it is already known.
+    fn add(&mut self, column: String) {
+        self.removed.retain(|removed| *removed != column);
+        if !self.columns.contains(&column) {
+            self.columns.push(column);
+        }
+    }
+
+    fn remove(&mut self, column: &str) {
+        self.columns.retain(|known| known != column);
+        if !self.removed.iter().any(|removed| removed == column) {
+            self.removed.push(column.into());
+        }
+    }
+
+    /// Rename a column, keeping its place among the known columns.
+    fn rename(&mut self, from: &str, to: &str) {
+        match self.columns.iter().position(|known| known == from) {
+            Some(index) => {
+                self.columns.remove(index);
+                self.columns.retain(|known| known != to);
+                self.columns
+                    .insert(index.min(self.columns.len()), to.into());
+                self.removed.retain(|removed| removed != to);
+                if !self.is_removed(from) {
+                    self.removed.push(from.into());
+                }
+            }
+            None => {
+                self.remove(from);
+                self.add(to.into());
+            }
+        }
+    }
+
+    /// Whether a column is definitely not in the tables: it is unknown and
+    /// there are no unknown columns, or an earlier stage removed it.
+    pub fn is_missing(&self, column: &str) -> bool {
+        !self.columns.iter().any(|known| known == column)
+            && (!self.open
+                || self
+                    .removed
+                    .iter()
+                    .any(|removed| removed == column))
+    }
+
+    /// Allow any column in the tables, after a stage whose columns can't be
+    /// inferred. It may add back the columns removed before it too.
+    fn open(&mut self) {
+        self.open = true;
+        self.removed.clear();
+    }
+
+    /// Whether a column was removed by an earlier stage.
+    pub fn is_removed(&self, column: &str) -> bool {
+        self.removed.iter().any(|removed| removed == column)
+    }
+}
+
+/// The schema of the tables produced by a pipe stage from the schema of its
+/// input. Stages that don't change the columns, or only remove columns that
+/// can't be inferred, e.g. `keep(fn: ...)`, keep the schema of their input.
+/// Any other stage whose columns can't be inferred, e.g. `stateCount`, opens
+/// the schema to unknown columns.
+fn apply_stage(call: &ast::CallExpr, mut schema: Schema) -> Schema {
+    let name = match &call.callee {
+        Expression::Identifier(identifier) => {
+            identifier.name.as_str()
+        }
+        _ => {
+            schema.open();
+            return schema;
+        }
+    };
+    match name {
+        "keep" => {
+            if let Some(kept) =
+                argument(call, "columns").and_then(string_list)
+            {
+                for column in schema.columns.clone() {
+                    if !kept.contains(&column) {
+                        schema.remove(&column);
+                    }
+                }
+                // Kept columns that aren't known may be among the unknown
+                // columns, but there are no others.
+                if schema.open {
+                    for column in kept {
+                        schema.add(column);
+                    }
+                }
+                schema.open = false;
+            }
+        }
+        "drop" => {
+            if let Some(dropped) =
+                argument(call, "columns").and_then(string_list)
+            {
+                for column in dropped {
+                    schema.remove(&column);
+                }
+            }
+        }
+        "rename" => match argument(call, "columns") {
+            Some(Expression::Object(object)) => {
+                for property in &object.properties {
+                    let to = match &property.value {
+                        Some(Expression::StringLit(lit)) => {
+                            &lit.value
+                        }
+                        _ => {
+                            schema.open();
+                            continue;
+                        }
+                    };
+                    let from = property_name(&property.key);
+                    if schema.is_missing(from) {
+                        continue;
+                    }
+                    schema.rename(from, to);
+                }
+            }
+            _ => schema.open(),
+        },
+        "map" => match argument(call, "fn").and_then(map_record) {
+            Some(object) => {
+                if object.with.is_none() {
+                    for column in schema.columns.clone() {
+                        schema.remove(&column);
+                    }
+                    schema.open = false;
+                }
+                for property in &object.properties {
+                    schema.add(property_name(&property.key).into());
+                }
+            }
+            None => schema.open(),
+        },
+        "pivot" => {
+            let pivoted: Vec<String> = ["columnKey", "valueColumn"]
+                .iter()
+                .filter_map(|name| argument(call, name))
+                .filter_map(|expr| match expr {
+                    Expression::StringLit(lit) => {
+                        Some(vec![lit.value.clone()])
+                    }
+                    expr => string_list(expr),
+                })
+                .flatten()
+                .collect();
+            for column in pivoted {
+                schema.remove(&column);
+            }
+            if let Some(row_key) =
+                argument(call, "rowKey").and_then(string_list)
+            {
+                for column in row_key {
+                    schema.add(column);
+                }
+            }
+            // The values of the column key become columns.
+            schema.open = true;
+        }
+        "duplicate" => match string_argument(call, "as") {
+            Some(column) => schema.add(column),
+            None => schema.open(),
+        },
+        // The bounds of the time range, or window, become columns.
+        "range" | "window" => {
+            schema.add("_start".into());
+            schema.add("_stop".into());
+        }
+        name if COLUMN_PRESERVING_STAGES.contains(&name) => (),
+        _ => schema.open(),
+    }
+    schema
+}
+
+/// The record returned by the function of a `map`, e.g. `({r with x: 1})` in
+/// `map(fn: (r) => ({r with x: 1}))`.
+fn map_record(expr: &Expression) -> Option<&ast::ObjectExpr> {
+    let func = match expr {
+        Expression::Function(func) => func,
+        _ => return None,
+    };
+    let mut body = match &func.body {
+        ast::FunctionBody::Expr(body) => body,
+        ast::FunctionBody::Block(block) => {
+            match block.body.last()? {
+                ast::Statement::Return(ret) => &ret.argument,
+                _ => return None,
+            }
+        }
+    };
+    while let Expression::Paren(paren) = body {
+        body = &paren.expression;
+    }
+    match body {
+        Expression::Object(object) => Some(object),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(source: &str) -> ast::CallExpr {
+        let file = flux::parser::parse_string("".into(), source);
+        match file.body.into_iter().next() {
+            Some(ast::Statement::Expr(stmt)) => match stmt.expression
+            {
+                Expression::Call(call) => *call,
+                _ => panic!("{} is not a call", source),
+            },
+            _ => panic!("{} is not an expression", source),
+        }
+    }
+
+    fn from_schema() -> Schema {
+        Schema::new(
+            FROM_COLUMNS.iter().map(|c| c.to_string()).collect(),
+            true,
+        )
+    }
+
+    #[test]
+    fn keep_removes_unknown_columns() {
+        let schema = apply_stage(
+            &call(r#"keep(columns: ["_time", "host"])"#),
+            from_schema(),
+        );
+
+        assert_eq!(vec!["_time", "host"], schema.columns);
+        assert!(!schema.is_missing("host"));
+        assert!(schema.is_missing("_value"));
+        assert!(schema.is_missing("region"));
+    }
+
+    #[test]
+    fn drop_removes_columns_among_unknown_columns() {
+        let schema = apply_stage(
+            &call(r#"drop(columns: ["host"])"#),
+            from_schema(),
+        );
+
+        assert!(schema.is_missing("host"));
+        assert!(schema.is_removed("host"));
+        assert!(!schema.is_missing("region"));
+    }
+
+    #[test]
+    fn rename_keeps_column_order() {
+        let schema = apply_stage(
+            &call(
+                r#"rename(columns: {_value: "value", host: "server"})"#,
+            ),
+            from_schema(),
+        );
+
+        assert_eq!(
+            vec![
+                "_time",
+                "value",
+                "_field",
+                "_measurement",
+                "_start",
+                "_stop",
+                "server"
+            ],
+            schema.columns
+        );
+        assert!(schema.is_missing("_value"));
+        assert!(schema.is_missing("host"));
+    }
+
+    #[test]
+    fn unknown_stage_opens_schema() {
+        let schema = apply_stage(
+            &call(r#"keep(columns: ["_time", "_value"])"#),
+            from_schema(),
+        );
+        let schema = apply_stage(
+            &call(r#"stateCount(fn: (r) => r._value > 0)"#),
+            schema,
+        );
+
+        assert!(schema.open);
+        assert!(!schema.is_missing("stateCount"));
+    }
+
+    #[test]
+    fn unreadable_map_opens_schema() {
+        let schema = apply_stage(
+            &call(r#"keep(columns: ["_time", "_value"])"#),
+            from_schema(),
+        );
+        let schema =
+            apply_stage(&call(r#"map(fn: addCount)"#), schema);
+
+        assert!(schema.open);
+        assert!(!schema.is_missing("count"));
+    }
+
+    #[test]
+    fn column_preserving_stage_keeps_schema() {
+        let schema = apply_stage(
+            &call(r#"keep(columns: ["_time", "_value"])"#),
+            from_schema(),
+        );
+        let schema = apply_stage(
+            &call(r#"filter(fn: (r) => r._value > 0)"#),
+            schema,
+        );
+
+        assert!(!schema.open);
+        assert!(schema.is_missing("host"));
+    }
+
+    #[test]
+    fn csv_header_columns() {
+        assert_eq!(
+            Some(vec![
+                "_time".to_string(),
+                "_value".into(),
+                "host".into()
+            ]),
+            csv_columns(
+                r#"
+#datatype,string,long,dateTime:RFC3339,double,string
+#group,false,false,false,false,true
+#default,_result,,,,
+,result,table,_time,_value,host
+,,0,2022-01-01T00:00:00Z,1.0,a
+"#
+            )
+        );
+        assert_eq!(
+            Some(vec!["result".to_string(), "count".into()]),
+            csv_columns("result,count\nok,1\n")
+        );
+        assert_eq!(None, csv_columns("#datatype,string\n"));
+    }
+}
@@ -0,0 +1,1026 @@
+/// Refactoring code actions
+///
+/// Unlike the quick fixes offered in response to diagnostics, refactorings are offered
+/// for any selected range. Each refactoring produces a single `lsp::CodeAction` whose
+/// edit applies to the document it was requested for.
+use std::collections::HashMap;
+
+use flux::ast;
+use flux::semantic::nodes::{
+    Block, CallExpr, Expression, Package, StringExprPart, Symbol,
+};
+use flux::semantic::types::{BuiltinType, MonoType};
+use flux::semantic::walk::{self, Node, Visitor};
+use lspower::lsp;
+
+use crate::composition;
+use crate::visitors::semantic::{
+    DefinitionFinderVisitor, IdentFinderVisitor, NodeFinderVisitor,
+};
+
+/// Return the file name portion of a document url, which is how
+/// semantic locations refer to their files.
+fn file_name(uri: &lsp::Url) -> Option<String> {
+    uri.path_segments()
+        .and_then(|segments| segments.last())
+        .map(String::from)
+}
+
+/// The range of the source text for an expression.
+///
+/// Pipe expressions are represented as a call with a `pipe` argument in the
+/// semantic graph, and the location of the call does not include the piped
+/// argument.
+fn expression_range(expr: &Expression) -> lsp::Range {
+    match expr {
+        Expression::Call(call) => call_range(call),
+        _ => expr.loc().clone().into(),
+    }
+}
+
+fn call_range(call: &CallExpr) -> lsp::Range {
+    let mut range: lsp::Range = call.loc.clone().into();
+    if let Some(pipe) = &call.pipe {
+        range.start = expression_range(pipe).start;
+    }
+    range
+}
+
+/// Return true if an expression can be put in the place of an identifier
+/// without needing parentheses.
+fn is_atomic(expr: &Expression) -> bool {
+    !matches!(
+        expr,
+        Expression::Binary(_)
+            | Expression::Logical(_)
+            | Expression::Unary(_)
+            | Expression::Conditional(_)
+            | Expression::Function(_)
+    )
+}
+
+/// Return true if an expression placed directly under `parent` never needs
+/// parentheses, regardless of the expression.
+fn is_standalone_context(parent: Option<&Node>) -> bool {
+    matches!(
+        parent,
+        Some(Node::Property(_))
+            | Some(Node::VariableAssgn(_))
+            | Some(Node::ExprStmt(_))
+            | Some(Node::ReturnStmt(_))
+            | Some(Node::ArrayExpr(_))
+            | None
+    )
+}
+
+/// Return true if an expression placed directly under `parent` needs
+/// parentheses when it is a pipe, e.g. `(a |> f())._value` rather than
+/// `a |> f()._value`, which reads the member of the call.
+fn is_accessed_context(parent: Option<&Node>) -> bool {
+    matches!(
+        parent,
+        Some(Node::MemberExpr(_)) | Some(Node::IndexExpr(_))
+    )
+}
+
+fn is_pipe(expr: &Expression) -> bool {
+    matches!(expr, Expression::Call(call) if call.pipe.is_some())
+}
+
+fn parenthesize(text: String, expr: &Expression) -> String {
+    if is_atomic(expr) {
+        text
+    } else {
+        format!("({})", text)
+    }
+}
+
+/// Count the number of places in which `name` is bound.
+struct BindingCounter {
+    name: Symbol,
+    count: usize,
+}
+
+impl<'a> Visitor<'a> for BindingCounter {
+    fn visit(&mut self, node: Node<'a>) -> bool {
+        match node {
+            Node::VariableAssgn(assgn)
+                if assgn.id.name == self.name =>
+            {
+                self.count += 1
+            }
+            Node::FunctionParameter(param)
+                if param.key.name == self.name =>
+            {
+                self.count += 1
+            }
+            Node::BuiltinStmt(builtin)
+                if builtin.id.name == self.name =>
+            {
+                self.count += 1
+            }
+            _ => (),
+        }
+        true
+    }
+}
+
+fn binding_count(pkg: &Package, name: &Symbol) -> usize {
+    let visitor = crate::walk_semantic_package!(
+        BindingCounter {
+            name: name.clone(),
+            count: 0,
+        },
+        pkg
+    );
+    visitor.count
+}
+
+/// Collect the identifiers referenced by an expression.
+#[derive(Default)]
+struct FreeIdentifierVisitor<'a> {
+    identifiers: Vec<&'a flux::semantic::nodes::IdentifierExpr>,
+    bound: Vec<Symbol>,
+}
+
+impl<'a> Visitor<'a> for FreeIdentifierVisitor<'a> {
+    fn visit(&mut self, node: Node<'a>) -> bool {
+        match node {
+            Node::IdentifierExpr(ident) => {
+                self.identifiers.push(ident)
+            }
+            Node::FunctionParameter(param) => {
+                self.bound.push(param.key.name.clone())
+            }
+            _ => (),
+        }
+        true
+    }
+}
+
+fn free_identifiers<'a>(node: Node<'a>) -> FreeIdentifierVisitor<'a> {
+    let mut visitor = FreeIdentifierVisitor::default();
+    walk::walk(&mut visitor, node);
+    visitor
+}
+
+/// Return true if any function between the top of `path` and its end, that doesn't
+/// also contain `origin`, rebinds any of `names`.
+///
+/// This is the check for shadowing: an expression moved from `origin` into the
+/// innermost node of `path` must see the same bindings it saw before.
+fn is_shadowed(
+    path: &[Node],
+    origin: &lsp::Range,
+    names: &[Symbol],
+) -> bool {
+    path.iter().any(|node| match node {
+        Node::FunctionExpr(func) => {
+            let range: lsp::Range = func.loc.clone().into();
+            if crate::lsp::position_in_range(&origin.start, &range) {
+                return false;
+            }
+            names.iter().any(|name| {
+                let mut visitor =
+                    DefinitionFinderVisitor::new(name.clone());
+                walk::walk(&mut visitor, *node);
+                visitor.node.is_some()
+            })
+        }
+        _ => false,
+    })
+}
+
+fn find_path(pkg: &Package, position: lsp::Position) -> Vec<Node> {
+    let visitor = crate::walk_semantic_package!(
+        NodeFinderVisitor::new(position),
+        pkg
+    );
+    visitor.path
+}
+
+fn code_action(
+    title: String,
+    kind: lsp::CodeActionKind,
+    uri: &lsp::Url,
+    edits: Vec<lsp::TextEdit>,
+) -> lsp::CodeAction {
+    lsp::CodeAction {
+        title,
+        kind: Some(kind),
+        diagnostics: None,
+        edit: Some(lsp::WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            document_changes: None,
+            change_annotations: None,
+        }),
+        command: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    }
+}
+
+/// Replace every reference to a variable with its initializer, and remove the
+/// variable assignment.
+pub(crate) fn inline_variable(
+    uri: &lsp::Url,
+    contents: &str,
+    pkg: &Package,
+    position: lsp::Position,
+) -> Option<lsp::CodeAction> {
+    let filename = file_name(uri)?;
+    let visitor = crate::walk_semantic_package!(
+        NodeFinderVisitor::new(position),
+        pkg
+    );
+    let name = match visitor.node? {
+        Node::Identifier(ident) => ident.name.clone(),
+        Node::IdentifierExpr(ident) => ident.name.clone(),
+        _ => return None,
+    };
+    // A name bound in more than one place can't be inlined without knowing
+    // which binding each reference sees.
+    if binding_count(pkg, &name) != 1 {
+        return None;
+    }
+
+    let definition = crate::walk_semantic_package!(
+        DefinitionFinderVisitor::new(name.clone()),
+        pkg
+    );
+    let assgn = match definition.node? {
+        Node::VariableAssgn(assgn) => assgn,
+        _ => return None,
+    };
+    // Functions are inlined at their call sites instead.
+    if assgn.loc.file.as_ref() != Some(&filename)
+        || matches!(assgn.init, Expression::Function(_))
+    {
+        return None;
+    }
+    // References from other files in the package can't be edited here.
+    let referenced_elsewhere = pkg
+        .files
+        .iter()
+        .filter(|file| file.loc.file.as_ref() != Some(&filename))
+        .any(|file| {
+            let mut visitor = IdentFinderVisitor::new(name.clone());
+            walk::walk(&mut visitor, Node::File(file));
+            !visitor.identifiers.is_empty()
+        });
+    if referenced_elsewhere {
+        return None;
+    }
+
+    let definition_range: lsp::Range = assgn.id.loc.clone().into();
+    let init_range = expression_range(&assgn.init);
+    let init_text = crate::lsp::text_in_range(contents, &init_range)?;
+    let init_names: Vec<Symbol> =
+        free_identifiers(Node::from_expr(&assgn.init))
+            .identifiers
+            .iter()
+            .map(|ident| ident.name.clone())
+            .collect();
+
+    let references: Vec<lsp::Location> =
+        crate::server::find_references(
+            uri,
+            visitor.node,
+            visitor.path,
+        )
+        .into_iter()
+        .filter(|location| location.range != definition_range)
+        .collect();
+    if references.is_empty() {
+        return None;
+    }
+
+    let mut edits = vec![];
+    for reference in references.iter() {
+        let path = find_path(pkg, reference.range.start);
+        if is_shadowed(&path, &init_range, &init_names) {
+            return None;
+        }
+        let parent =
+            path.len().checked_sub(2).and_then(|i| path.get(i));
+        let new_text = match parent {
+            // Shorthand record properties, e.g. `{x}`, need their key restored.
+            Some(Node::Property(property))
+                if lsp::Range::from(property.key.loc.clone())
+                    == reference.range =>
+            {
+                format!("{}: {}", name, init_text)
+            }
+            _ if is_pipe(&assgn.init)
+                && is_accessed_context(parent) =>
+            {
+                format!("({})", init_text)
+            }
+            _ if is_standalone_context(parent) => init_text.clone(),
+            _ => parenthesize(init_text.clone(), &assgn.init),
+        };
+        edits.push(lsp::TextEdit {
+            range: reference.range,
+            new_text,
+        });
+    }
+
+    // Remove the assignment, along with the rest of its line.
+    let assgn_range: lsp::Range = assgn.loc.clone().into();
+    edits.push(lsp::TextEdit {
+        range: lsp::Range {
+            start: assgn_range.start,
+            end: lsp::Position {
+                line: assgn_range.end.line + 1,
+                character: 0,
+            },
+        },
+        new_text: "".into(),
+    });
+
+    Some(code_action(
+        format!("Inline variable `{}`", name),
+        lsp::CodeActionKind::REFACTOR_INLINE,
+        uri,
+        edits,
+    ))
+}
+
+/// Replace a call to a single expression user function with the body of the
+/// function, substituting the arguments for the parameters.
+pub(crate) fn inline_function(
+    uri: &lsp::Url,
+    contents: &str,
+    pkg: &Package,
+    position: lsp::Position,
+) -> Option<lsp::CodeAction> {
+    let filename = file_name(uri)?;
+    let path = find_path(pkg, position);
+    let (index, call) =
+        path.iter().enumerate().rev().find_map(|(index, node)| {
+            match node {
+                Node::CallExpr(call)
+                    if matches!(
+                        call.callee,
+                        Expression::Identifier(_)
+                    ) =>
+                {
+                    Some((index, *call))
+                }
+                _ => None,
+            }
+        })?;
+    let name = match &call.callee {
+        Expression::Identifier(ident) => ident.name.clone(),
+        _ => return None,
+    };
+    if binding_count(pkg, &name) != 1 {
+        return None;
+    }
+
+    let definition = crate::walk_semantic_package!(
+        DefinitionFinderVisitor::new(name.clone()),
+        pkg
+    );
+    let assgn = match definition.node? {
+        Node::VariableAssgn(assgn) => assgn,
+        _ => return None,
+    };
+    if assgn.loc.file.as_ref() != Some(&filename) {
+        return None;
+    }
+    let func = match &assgn.init {
+        Expression::Function(func) => func,
+        _ => return None,
+    };
+    let body = match &func.body {
+        Block::Return(ret) => ret,
+        _ => return None,
+    };
+    let params: Vec<Symbol> = func
+        .params
+        .iter()
+        .map(|param| param.key.name.clone())
+        .collect();
+
+    // Map each parameter to the text that will take its place.
+    let mut arguments: HashMap<String, String> = HashMap::new();
+    let mut argument_names: Vec<Symbol> = vec![];
+    for param in func.params.iter() {
+        let value = if param.is_pipe {
+            call.pipe.as_ref()
+        } else {
+            call.arguments
+                .iter()
+                .find(|argument| argument.key.name == param.key.name)
+                .map(|argument| &argument.value)
+        };
+        let value = match value {
+            Some(value) => {
+                argument_names.extend(
+                    free_identifiers(Node::from_expr(value))
+                        .identifiers
+                        .iter()
+                        .map(|ident| ident.name.clone()),
+                );
+                value
+            }
+            None => param.default.as_ref()?,
+        };
+        let text = crate::lsp::text_in_range(
+            contents,
+            &expression_range(value),
+        )?;
+        let text = if param.is_pipe {
+            text
+        } else {
+            parenthesize(text, value)
+        };
+        arguments.insert(param.key.name.to_string(), text);
+    }
+
+    let body_visitor = free_identifiers(Node::ReturnStmt(body));
+    // A function nested in the body that rebinds a name used by the arguments,
+    // or a parameter of the inlined function, would capture it.
+    if body_visitor.bound.iter().any(|bound| {
+        argument_names.contains(bound) || params.contains(bound)
+    }) {
+        return None;
+    }
+    // The remaining identifiers in the body need to resolve to the same bindings
+    // at the call site.
+    let body_range = expression_range(&body.argument);
+    let outer_names: Vec<Symbol> = body_visitor
+        .identifiers
+        .iter()
+        .filter(|ident| !params.contains(&ident.name))
+        .map(|ident| ident.name.clone())
+        .collect();
+    if is_shadowed(&path[..=index], &body_range, &outer_names) {
+        return None;
+    }
+
+    let body_start =
+        crate::lsp::offset_of(contents, &body_range.start)?;
+    let body_end = crate::lsp::offset_of(contents, &body_range.end)?;
+    let mut substitutions: Vec<(usize, usize, &String)> = vec![];
+    for ident in body_visitor.identifiers.iter() {
+        if let Some(text) = arguments.get(&*ident.name) {
+            let range: lsp::Range = ident.loc.clone().into();
+            substitutions.push((
+                crate::lsp::offset_of(contents, &range.start)?,
+                crate::lsp::offset_of(contents, &range.end)?,
+                text,
+            ));
+        }
+    }
+    substitutions.sort_by_key(|(start, _, _)| *start);
+
+    let mut new_text = String::new();
+    let mut cursor = body_start;
+    for (start, end, text) in substitutions {
+        new_text.push_str(contents.get(cursor..start)?);
+        new_text.push_str(text);
+        cursor = end;
+    }
+    new_text.push_str(contents.get(cursor..body_end)?);
+
+    let parent = index.checked_sub(1).and_then(|i| path.get(i));
+    let new_text =
+        if is_pipe(&body.argument) && is_accessed_context(parent) {
+            format!("({})", new_text)
+        } else if is_standalone_context(parent) {
+            new_text
+        } else {
+            parenthesize(new_text, &body.argument)
+        };
+
+    Some(code_action(
+        format!("Inline function `{}`", name),
+        lsp::CodeActionKind::REFACTOR_INLINE,
+        uri,
+        vec![lsp::TextEdit {
+            range: call_range(call),
+            new_text,
+        }],
+    ))
+}
+
+/// All pipe expressions in an ast file, in the order they are walked. An outer pipe
+/// expression is walked before the pipe expressions in its argument.
+#[derive(Default)]
+struct PipeExprVisitor<'a> {
+    pipes: Vec<&'a ast::PipeExpr>,
+}
+
+impl<'a> ast::walk::Visitor<'a> for PipeExprVisitor<'a> {
+    fn visit(&mut self, node: ast::walk::Node<'a>) -> bool {
+        if let ast::walk::Node::PipeExpr(pipe) = node {
+            self.pipes.push(pipe);
+        }
+        true
+    }
+}
+
+/// A stage of a pipeline, e.g. the `range` call in
+/// `from(bucket: "b") |> range(start: -1h) |> yield()`, along with its neighbours.
+struct PipeStage<'a> {
+    pipe: &'a ast::PipeExpr,
+    /// The pipe expression of the stage before this one, if that stage is a call
+    /// and not the source of the pipeline.
+    previous: Option<&'a ast::PipeExpr>,
+    /// The pipe expression of the stage after this one.
+    next: Option<&'a ast::PipeExpr>,
+}
+
+fn find_pipe_stage(
+    file: &ast::File,
+    position: lsp::Position,
+) -> Option<PipeStage> {
+    let mut visitor = PipeExprVisitor::default();
+    ast::walk::walk(&mut visitor, ast::walk::Node::File(file));
+
+    let pipe = visitor
+        .pipes
+        .iter()
+        .rev()
+        .find(|pipe| {
+            crate::lsp::position_in_range(
+                &position,
+                &pipe.call.base.location.clone().into(),
+            )
+        })
+        .copied()?;
+    let previous = match &pipe.argument {
+        ast::Expression::PipeExpr(previous) => Some(&**previous),
+        _ => None,
+    };
+    let next = visitor.pipes.iter().copied().find(|next| {
+        matches!(&next.argument, ast::Expression::PipeExpr(argument) if std::ptr::eq(&**argument, pipe))
+    });
+    Some(PipeStage {
+        pipe,
+        previous,
+        next,
+    })
+}
+
+/// The parameter name and predicate of a `filter(fn: (r) => ...)` call. Filters with
+/// any other arguments, e.g. `onEmpty`, or with a block for a body are not matched.
+fn filter_predicate(
+    call: &ast::CallExpr,
+) -> Option<(&str, &ast::Expression)> {
+    match &call.callee {
+        ast::Expression::Identifier(ident)
+            if ident.name == "filter" => {}
+        _ => return None,
+    }
+    let property = match call.arguments.as_slice() {
+        [ast::Expression::Object(object)]
+            if object.with.is_none() =>
+        {
+            match object.properties.as_slice() {
+                [property] => property,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let func = match (&property.key, &property.value) {
+        (
+            ast::PropertyKey::Identifier(key),
+            Some(ast::Expression::Function(func)),
+        ) if key.name == "fn" => func,
+        _ => return None,
+    };
+    match (func.params.as_slice(), &func.body) {
+        (
+            [ast::Property {
+                key: ast::PropertyKey::Identifier(param),
+                value: None,
+                ..
+            }],
+            ast::FunctionBody::Expr(predicate),
+        ) => Some((param.name.as_str(), predicate)),
+        _ => None,
+    }
+}
+
+/// Collect the operands of a chain of `and` expressions, e.g. `a`, `b` and `c` in
+/// `a and (b and c)`.
+fn conjuncts<'a>(
+    expr: &'a ast::Expression,
+    operands: &mut Vec<&'a ast::Expression>,
+) {
+    match expr {
+        ast::Expression::Logical(logical)
+            if matches!(
+                logical.operator,
+                ast::LogicalOperator::AndOperator
+            ) =>
+        {
+            conjuncts(&logical.left, operands);
+            conjuncts(&logical.right, operands);
+        }
+        ast::Expression::Paren(paren)
+            if matches!(
+                &paren.expression,
+                ast::Expression::Logical(logical) if matches!(logical.operator, ast::LogicalOperator::AndOperator)
+            ) =>
+        {
+            conjuncts(&paren.expression, operands)
+        }
+        _ => operands.push(expr),
+    }
+}
+
+/// Format a single expression with the flux formatter.
+fn format_expression(expr: ast::Expression) -> Option<String> {
+    let mut file = flux::parser::parse_string("".into(), "");
+    file.body = vec![ast::Statement::Expr(Box::new(ast::ExprStmt {
+        base: ast::BaseNode::default(),
+        expression: expr,
+    }))];
+    flux::formatter::convert_to_string(&file)
+        .ok()
+        .map(|text| text.trim().to_string())
+}
+
+fn format_filter(
+    param: &str,
+    predicate: ast::Expression,
+) -> Option<String> {
+    format_expression(ast::Expression::Call(Box::new(
+        composition::filter_call(param, predicate),
+    )))
+}
+
+/// Merge two adjacent filter stages into a single filter, joining their predicates
+/// with `and`.
+fn merge_filter_edit(
+    first: &ast::PipeExpr,
+    second: &ast::PipeExpr,
+) -> Option<lsp::TextEdit> {
+    let (param, left) = filter_predicate(&first.call)?;
+    let (other_param, right) = filter_predicate(&second.call)?;
+    // Filters with differently named parameters could be merged by renaming
+    // one of them, but that requires a rename of every reference in the
+    // predicate.
+    if param != other_param {
+        return None;
+    }
+    let predicate = composition::chained_logical_expr(
+        ast::LogicalOperator::AndOperator,
+        &[left.clone(), right.clone()],
+    )
+    .ok()?;
+    Some(lsp::TextEdit {
+        range: lsp::Range {
+            start: first.call.base.location.start.into(),
+            end: second.call.base.location.end.into(),
+        },
+        new_text: format_filter(param, predicate)?,
+    })
+}
+
+/// Merge a filter stage with an adjacent filter stage.
+pub(crate) fn merge_filters(
+    uri: &lsp::Url,
+    file: &ast::File,
+    position: lsp::Position,
+) -> Vec<lsp::CodeAction> {
+    let stage = match find_pipe_stage(file, position) {
+        Some(stage) => stage,
+        None => return vec![],
+    };
+    let mut actions = vec![];
+    if let Some(previous) = stage.previous {
+        if let Some(edit) = merge_filter_edit(previous, stage.pipe) {
+            actions.push(code_action(
+                "Merge with previous `filter`".into(),
+                lsp::CodeActionKind::REFACTOR_REWRITE,
+                uri,
+                vec![edit],
+            ));
+        }
+    }
+    if let Some(next) = stage.next {
+        if let Some(edit) = merge_filter_edit(stage.pipe, next) {
+            actions.push(code_action(
+                "Merge with next `filter`".into(),
+                lsp::CodeActionKind::REFACTOR_REWRITE,
+                uri,
+                vec![edit],
+            ));
+        }
+    }
+    actions
+}
+
+/// Split a filter with an `and` predicate into a filter stage per operand.
+pub(crate) fn split_filter(
+    uri: &lsp::Url,
+    contents: &str,
+    file: &ast::File,
+    position: lsp::Position,
+) -> Option<lsp::CodeAction> {
+    let stage = find_pipe_stage(file, position)?;
+    let (param, predicate) = filter_predicate(&stage.pipe.call)?;
+    let mut operands = vec![];
+    conjuncts(predicate, &mut operands);
+    if operands.len() < 2 {
+        return None;
+    }
+
+    // Keep the new stages in the same layout as the existing stage, i.e. each
+    // stage on its own line when the stage starts its line with `|>`.
+    let range: lsp::Range =
+        stage.pipe.call.base.location.clone().into();
+    let prefix: String = contents
+        .lines()
+        .nth(range.start.line as usize)?
+        .chars()
+        .take(range.start.character as usize)
+        .collect();
+    let separator = if prefix.trim() == "|>" {
+        let indent: String = prefix
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .collect();
+        format!("\n{}|> ", indent)
+    } else {
+        " |> ".into()
+    };
+
+    let filters = operands
+        .into_iter()
+        .map(|operand| format_filter(param, operand.clone()))
+        .collect::<Option<Vec<String>>>()?;
+    Some(code_action(
+        "Split `filter` predicate".into(),
+        lsp::CodeActionKind::REFACTOR_REWRITE,
+        uri,
+        vec![lsp::TextEdit {
+            range,
+            new_text: filters.join(&separator),
+        }],
+    ))
+}
+
+/// Swap the calls of two pipe stages.
+fn swap_stages_edits(
+    contents: &str,
+    first: &ast::PipeExpr,
+    second: &ast::PipeExpr,
+) -> Option<Vec<lsp::TextEdit>> {
+    let first_range: lsp::Range =
+        first.call.base.location.clone().into();
+    let second_range: lsp::Range =
+        second.call.base.location.clone().into();
+    Some(vec![
+        lsp::TextEdit {
+            range: first_range,
+            new_text: crate::lsp::text_in_range(
+                contents,
+                &second_range,
+            )?,
+        },
+        lsp::TextEdit {
+            range: second_range,
+            new_text: crate::lsp::text_in_range(
+                contents,
+                &first_range,
+            )?,
+        },
+    ])
+}
+
+/// Move a pipe stage before the previous stage, or after the next stage.
+pub(crate) fn move_pipe_stage(
+    uri: &lsp::Url,
+    contents: &str,
+    file: &ast::File,
+    position: lsp::Position,
+) -> Vec<lsp::CodeAction> {
+    let stage = match find_pipe_stage(file, position) {
+        Some(stage) => stage,
+        None => return vec![],
+    };
+    let name = match crate::lsp::text_in_range(
+        contents,
+        &stage.pipe.call.callee.base().location.clone().into(),
+    ) {
+        Some(name) => name,
+        None => return vec![],
+    };
+    let mut actions = vec![];
+    if let Some(previous) = stage.previous {
+        if let Some(edits) =
+            swap_stages_edits(contents, previous, stage.pipe)
+        {
+            actions.push(code_action(
+                format!("Move `{}` up in pipeline", name),
+                lsp::CodeActionKind::REFACTOR_REWRITE,
+                uri,
+                edits,
+            ));
+        }
+    }
+    if let Some(next) = stage.next {
+        if let Some(edits) =
+            swap_stages_edits(contents, stage.pipe, next)
+        {
+            actions.push(code_action(
+                format!("Move `{}` down in pipeline", name),
+                lsp::CodeActionKind::REFACTOR_REWRITE,
+                uri,
+                edits,
+            ));
+        }
+    }
+    actions
+}
+
+fn is_string(typ: &MonoType) -> bool {
+    matches!(typ, MonoType::Builtin(BuiltinType::String))
+}
+
+fn is_string_concatenation(node: &Node) -> bool {
+    match node {
+        Node::BinaryExpr(binary) => {
+            matches!(binary.operator, ast::Operator::AdditionOperator)
+                && is_string(&binary.typ)
+        }
+        _ => false,
+    }
+}
+
+/// Collect the operands of a chain of `+` expressions, e.g. `a`, `b` and `c` in
+/// `a + b + c`.
+fn concatenation_operands<'a>(
+    expr: &'a Expression,
+    operands: &mut Vec<&'a Expression>,
+) {
+    match expr {
+        Expression::Binary(binary)
+            if matches!(
+                binary.operator,
+                ast::Operator::AdditionOperator
+            ) =>
+        {
+            concatenation_operands(&binary.left, operands);
+            concatenation_operands(&binary.right, operands);
+        }
+        _ => operands.push(expr),
+    }
+}
+
+/// The source text between the quotes of a string literal or string expression.
+fn quoted_text(contents: &str, expr: &Expression) -> Option<String> {
+    let text = crate::lsp::text_in_range(
+        contents,
+        &expr.loc().clone().into(),
+    )?;
+    text.strip_prefix('"')?.strip_suffix('"').map(String::from)
+}
+
+/// The argument of a `string(v: ...)` conversion, which isn't needed inside
+/// an interpolation.
+fn string_conversion_argument(
+    expr: &Expression,
+) -> Option<&Expression> {
+    match expr {
+        Expression::Call(call) if call.pipe.is_none() => {
+            match (&call.callee, call.arguments.as_slice()) {
+                (Expression::Identifier(ident), [argument])
+                    if ident.name == "string"
+                        && argument.key.name == "v" =>
+                {
+                    Some(&argument.value)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Convert a chain of string concatenations, e.g. `"host=" + r.host`, into an
+/// interpolated string, e.g. `"host=${r.host}"`.
+pub(crate) fn concatenation_to_interpolation(
+    uri: &lsp::Url,
+    contents: &str,
+    pkg: &Package,
+    position: lsp::Position,
+) -> Option<lsp::CodeAction> {
+    let path = find_path(pkg, position);
+    // Start from the innermost concatenation, and extend it to the whole chain.
+    let mut index = path.iter().rposition(is_string_concatenation)?;
+    while index > 0 && is_string_concatenation(&path[index - 1]) {
+        index -= 1;
+    }
+    let binary = match path[index] {
+        Node::BinaryExpr(binary) => binary,
+        _ => return None,
+    };
+
+    let mut operands = vec![];
+    concatenation_operands(&binary.left, &mut operands);
+    concatenation_operands(&binary.right, &mut operands);
+    let parts = operands
+        .into_iter()
+        .map(|operand| match operand {
+            Expression::StringLit(_) | Expression::StringExpr(_) => {
+                quoted_text(contents, operand)
+            }
+            _ => {
+                let expr = string_conversion_argument(operand)
+                    .unwrap_or(operand);
+                crate::lsp::text_in_range(
+                    contents,
+                    &expression_range(expr),
+                )
+                .map(|text| format!("${{{}}}", text))
+            }
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    Some(code_action(
+        "Convert to string interpolation".into(),
+        lsp::CodeActionKind::REFACTOR_REWRITE,
+        uri,
+        vec![lsp::TextEdit {
+            range: binary.loc.clone().into(),
+            new_text: format!("\"{}\"", parts.concat()),
+        }],
+    ))
+}
+
+/// Convert an interpolated string, e.g. `"host=${r.host}"`, into a chain of string
+/// concatenations, e.g. `"host=" + r.host`. Interpolated values that aren't strings
+/// are converted with `string()`.
+pub(crate) fn interpolation_to_concatenation(
+    uri: &lsp::Url,
+    contents: &str,
+    pkg: &Package,
+    position: lsp::Position,
+) -> Option<lsp::CodeAction> {
+    let path = find_path(pkg, position);
+    let index = path
+        .iter()
+        .rposition(|node| matches!(node, Node::StringExpr(_)))?;
+    let string_expr = match path[index] {
+        Node::StringExpr(string_expr) => string_expr,
+        _ => return None,
+    };
+    if !string_expr
+        .parts
+        .iter()
+        .any(|part| matches!(part, StringExprPart::Interpolated(_)))
+    {
+        return None;
+    }
+
+    let operands = string_expr
+        .parts
+        .iter()
+        .map(|part| match part {
+            StringExprPart::Text(text) => crate::lsp::text_in_range(
+                contents,
+                &text.loc.clone().into(),
+            )
+            .map(|text| format!("\"{}\"", text)),
+            StringExprPart::Interpolated(interpolated) => {
+                let expr = &interpolated.expression;
+                let text = crate::lsp::text_in_range(
+                    contents,
+                    &expression_range(expr),
+                )?;
+                if is_string(&expr.type_of()) {
+                    Some(parenthesize(text, expr))
+                } else {
+                    Some(format!("string(v: {})", text))
+                }
+            }
+        })
+        .collect::<Option<Vec<String>>>()?;
+
+    let mut new_text = operands.join(" + ");
+    if operands.len() > 1
+        && !is_standalone_context(
+            index.checked_sub(1).map(|i| &path[i]),
+        )
+    {
+        new_text = format!("({})", new_text);
+    }
+    Some(code_action(
+        "Convert to string concatenation".into(),
+        lsp::CodeActionKind::REFACTOR_REWRITE,
+        uri,
+        vec![lsp::TextEdit {
+            range: string_expr.loc.clone().into(),
+            new_text,
+        }],
+    ))
+}
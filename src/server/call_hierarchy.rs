@@ -0,0 +1,287 @@
+/// Call hierarchy of the functions defined in a package.
+///
+/// Calls are made from functions, other variables and top-level expressions,
+/// e.g. pipelines, in any file of the package. Only calls of functions by their
+/// name are followed, so calls of stdlib members like `strings.trim` aren't part
+/// of the hierarchy, and neither are calls of parameters or variables of a
+/// function that shadow a function of the package.
+use flux::semantic::nodes::{self, Expression, Package, Statement};
+use flux::semantic::walk::{self, Node};
+use lspower::lsp;
+
+use crate::visitors::semantic::{self, CallFinderVisitor};
+
+/// The url of a file of the package, from the urls of its files in the store.
+fn file_url(
+    urls: &[lsp::Url],
+    file: &nodes::File,
+) -> Option<lsp::Url> {
+    urls.iter()
+        .find(|url| {
+            url.path_segments().and_then(|segments| segments.last())
+                == file.loc.file.as_deref()
+        })
+        .cloned()
+}
+
+fn item(
+    name: String,
+    kind: lsp::SymbolKind,
+    detail: String,
+    uri: lsp::Url,
+    range: lsp::Range,
+    selection_range: lsp::Range,
+) -> lsp::CallHierarchyItem {
+    lsp::CallHierarchyItem {
+        name,
+        kind,
+        tags: None,
+        detail: Some(detail),
+        uri,
+        range,
+        selection_range,
+        data: None,
+    }
+}
+
+fn variable_item(
+    uri: lsp::Url,
+    assign: &nodes::VariableAssgn,
+) -> lsp::CallHierarchyItem {
+    let kind = match assign.init {
+        Expression::Function(_) => lsp::SymbolKind::FUNCTION,
+        _ => lsp::SymbolKind::VARIABLE,
+    };
+    item(
+        assign.id.name.to_string(),
+        kind,
+        assign.init.type_of().to_string(),
+        uri,
+        assign.loc.clone().into(),
+        assign.id.loc.clone().into(),
+    )
+}
+
+/// The variables, functions and expressions at the top level of a file, which
+/// calls are made from, with the nodes to find the calls in. Expressions are
+/// named after the pipeline they are, or else their source, e.g.
+/// `yield(tables: data)`.
+fn callers<'a>(
+    uri: &lsp::Url,
+    file: &'a nodes::File,
+) -> Vec<(lsp::CallHierarchyItem, Node<'a>)> {
+    file.body
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::Variable(assign) => Some((
+                variable_item(uri.clone(), assign),
+                Node::VariableAssgn(assign),
+            )),
+            Statement::Expr(stmt) => {
+                let (name, detail) = match semantic::pipeline_name_of(
+                    &stmt.expression,
+                ) {
+                    Some(name) => (name, "pipeline"),
+                    None => (
+                        stmt.loc
+                            .source
+                            .as_deref()
+                            .and_then(|source| source.lines().next())
+                            .unwrap_or("expression")
+                            .to_string(),
+                        "expression",
+                    ),
+                };
+                let range: lsp::Range = stmt.loc.clone().into();
+                Some((
+                    item(
+                        name,
+                        lsp::SymbolKind::EVENT,
+                        detail.into(),
+                        uri.clone(),
+                        range,
+                        range,
+                    ),
+                    Node::ExprStmt(stmt),
+                ))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The function a name is bound to at the top level of the package, with the
+/// url of its file.
+fn definition<'a>(
+    pkg: &'a Package,
+    urls: &[lsp::Url],
+    name: &nodes::Symbol,
+) -> Option<(lsp::Url, &'a nodes::VariableAssgn)> {
+    pkg.files.iter().find_map(|file| {
+        file.body.iter().find_map(|stmt| match stmt {
+            Statement::Variable(assign)
+                if assign.id.name == *name
+                    && matches!(
+                        assign.init,
+                        Expression::Function(_)
+                    ) =>
+            {
+                let assign: &nodes::VariableAssgn = assign;
+                Some((file_url(urls, file)?, assign))
+            }
+            _ => None,
+        })
+    })
+}
+
+/// Whether the callee of a call in a node is the function of the package with
+/// its name, rather than a parameter or variable of a function the call is in.
+fn calls_package_function(
+    node: Node<'_>,
+    call: &nodes::CallExpr,
+) -> bool {
+    let name = match &call.callee {
+        Expression::Identifier(ident) => &ident.name,
+        _ => return false,
+    };
+    let mut visitor = semantic::NodeFinderVisitor::new(
+        call.callee.loc().start.into(),
+    );
+    walk::walk(&mut visitor, node);
+    !semantic::is_bound_in_function(&visitor.path, name.as_str())
+}
+
+/// The caller an item was made for, found by its url and the range of its name.
+fn find_caller<'a>(
+    pkg: &'a Package,
+    urls: &[lsp::Url],
+    target: &lsp::CallHierarchyItem,
+) -> Option<Node<'a>> {
+    let file = pkg.files.iter().find(|file| {
+        file_url(urls, file).as_ref() == Some(&target.uri)
+    })?;
+    callers(&target.uri, file)
+        .into_iter()
+        .find_map(|(item, node)| {
+            if item.name == target.name
+                && item.selection_range == target.selection_range
+            {
+                Some(node)
+            } else {
+                None
+            }
+        })
+}
+
+/// The ranges of the calls of a function in a node, e.g. `f` in `f(x: 1)`.
+fn call_ranges(
+    node: Node<'_>,
+    name: &nodes::Symbol,
+) -> Vec<lsp::Range> {
+    let mut visitor = CallFinderVisitor::default();
+    walk::walk(&mut visitor, node);
+    visitor
+        .calls
+        .iter()
+        .filter(|call| match &call.callee {
+            Expression::Identifier(ident) => {
+                ident.name == *name
+                    && calls_package_function(node, call)
+            }
+            _ => false,
+        })
+        .map(|call| call.callee.loc().clone().into())
+        .collect()
+}
+
+/// The function defined, or called, at a position of a file.
+pub fn prepare(
+    pkg: &Package,
+    urls: &[lsp::Url],
+    position: lsp::Position,
+) -> Option<lsp::CallHierarchyItem> {
+    let visitor = crate::walk_semantic_package!(
+        semantic::NodeFinderVisitor::new(position),
+        pkg
+    );
+    let name = match visitor.node? {
+        Node::Identifier(ident) => &ident.name,
+        Node::IdentifierExpr(ident) => &ident.name,
+        _ => return None,
+    };
+    if semantic::is_bound_in_function(&visitor.path, name.as_str()) {
+        return None;
+    }
+    let (uri, assign) = definition(pkg, urls, name)?;
+    Some(variable_item(uri, assign))
+}
+
+/// The functions, variables and pipelines that call a function.
+pub fn incoming_calls(
+    pkg: &Package,
+    urls: &[lsp::Url],
+    target: &lsp::CallHierarchyItem,
+) -> Vec<lsp::CallHierarchyIncomingCall> {
+    let name = match find_caller(pkg, urls, target) {
+        Some(Node::VariableAssgn(assign)) => &assign.id.name,
+        _ => return vec![],
+    };
+    pkg.files
+        .iter()
+        .filter_map(|file| Some((file_url(urls, file)?, file)))
+        .flat_map(|(uri, file)| callers(&uri, file))
+        .filter_map(|(from, node)| {
+            let from_ranges = call_ranges(node, name);
+            if from_ranges.is_empty() {
+                None
+            } else {
+                Some(lsp::CallHierarchyIncomingCall {
+                    from,
+                    from_ranges,
+                })
+            }
+        })
+        .collect()
+}
+
+/// The functions of the package called by a function, variable or pipeline,
+/// in the order they are first called.
+pub fn outgoing_calls(
+    pkg: &Package,
+    urls: &[lsp::Url],
+    target: &lsp::CallHierarchyItem,
+) -> Vec<lsp::CallHierarchyOutgoingCall> {
+    let node = match find_caller(pkg, urls, target) {
+        Some(node) => node,
+        None => return vec![],
+    };
+    let mut visitor = CallFinderVisitor::default();
+    walk::walk(&mut visitor, node);
+    // The stages of a pipeline are walked from its last stage.
+    let mut calls = visitor.calls;
+    calls.sort_by_key(|call| {
+        let start = call.callee.loc().start;
+        (start.line, start.column)
+    });
+
+    let mut names: Vec<&nodes::Symbol> = vec![];
+    for call in calls {
+        if let Expression::Identifier(ident) = &call.callee {
+            if calls_package_function(node, call)
+                && !names.contains(&&ident.name)
+            {
+                names.push(&ident.name);
+            }
+        }
+    }
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (uri, assign) = definition(pkg, urls, name)?;
+            Some(lsp::CallHierarchyOutgoingCall {
+                to: variable_item(uri, assign),
+                from_ranges: call_ranges(node, name),
+            })
+        })
+        .collect()
+}
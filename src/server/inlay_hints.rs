@@ -0,0 +1,225 @@
+/// Inlay hints for inferred types.
+///
+/// `lsp-types` 0.91 predates inlay hints in the LSP spec, so the request and its
+/// types are defined here, following the 3.17 spec.
+use flux::semantic::nodes::{Expression, Package};
+use flux::semantic::walk::{self, Visitor};
+use lspower::lsp;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    #[serde(flatten)]
+    pub work_done_progress_params: lsp::WorkDoneProgressParams,
+    pub text_document: lsp::TextDocumentIdentifier,
+    pub range: lsp::Range,
+}
+
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize,
+)]
+#[serde(transparent)]
+pub struct InlayHintKind(i32);
+
+impl InlayHintKind {
+    pub const TYPE: InlayHintKind = InlayHintKind(1);
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: lsp::Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<InlayHintKind>,
+    /// The full type, when the label is truncated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+}
+
+pub enum InlayHintRequest {}
+
+impl lsp::request::Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+/// Which inlay hints are shown, from the `inlayHints` settings, e.g.
+///
+/// ```json
+/// {"settings": {"inlayHints": {"variableTypes": true, "parameterTypes": false, "pipeTypes": true, "maxLength": 40}}}
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct InlayHintSettings {
+    /// The types of variables, after their names.
+    pub variable_types: bool,
+    /// The types of the parameters of user functions.
+    pub parameter_types: bool,
+    /// The type of the stream at the end of each pipe stage.
+    pub pipe_types: bool,
+    /// Longer types are truncated.
+    pub max_length: usize,
+}
+
+impl Default for InlayHintSettings {
+    fn default() -> Self {
+        Self {
+            variable_types: true,
+            parameter_types: true,
+            pipe_types: true,
+            max_length: 60,
+        }
+    }
+}
+
+/// Collect the inlay hints of a package, with the types rendered by `render`.
+struct InlayHintVisitor<'a, F> {
+    settings: InlayHintSettings,
+    range: lsp::Range,
+    render: F,
+    /// The enclosing nodes, for the constraints of the types.
+    path: Vec<walk::Node<'a>>,
+    hints: Vec<InlayHint>,
+}
+
+impl<'a, F> InlayHintVisitor<'a, F>
+where
+    F: Fn(
+        &[walk::Node<'a>],
+        flux::semantic::types::MonoType,
+    ) -> String,
+{
+    fn push(
+        &mut self,
+        position: lsp::Position,
+        typ: String,
+        label: String,
+        padding_left: bool,
+    ) {
+        if !crate::lsp::position_in_range(&position, &self.range) {
+            return;
+        }
+        let (label, tooltip) =
+            if label.chars().count() > self.settings.max_length {
+                (
+                    format!(
+                        "{}…",
+                        label
+                            .chars()
+                            .take(self.settings.max_length)
+                            .collect::<String>()
+                    ),
+                    Some(typ),
+                )
+            } else {
+                (label, None)
+            };
+        self.hints.push(InlayHint {
+            position,
+            label,
+            kind: Some(InlayHintKind::TYPE),
+            tooltip,
+            padding_left: if padding_left {
+                Some(true)
+            } else {
+                None
+            },
+        });
+    }
+}
+
+impl<'a, F> Visitor<'a> for InlayHintVisitor<'a, F>
+where
+    F: Fn(
+        &[walk::Node<'a>],
+        flux::semantic::types::MonoType,
+    ) -> String,
+{
+    fn visit(&mut self, node: walk::Node<'a>) -> bool {
+        self.path.push(node);
+        match node {
+            walk::Node::VariableAssgn(assign)
+                if self.settings.variable_types =>
+            {
+                // Functions are described by their parameter hints instead.
+                if !matches!(assign.init, Expression::Function(_)) {
+                    let typ = (self.render)(
+                        &self.path,
+                        assign.init.type_of(),
+                    );
+                    self.push(
+                        assign.id.loc.end.into(),
+                        typ.clone(),
+                        format!(": {}", typ),
+                        false,
+                    );
+                }
+            }
+            walk::Node::FunctionExpr(func)
+                if self.settings.parameter_types =>
+            {
+                for param in &func.params {
+                    if let Some(typ) =
+                        func.typ.parameter(param.key.name.as_str())
+                    {
+                        let typ =
+                            (self.render)(&self.path, typ.clone());
+                        self.push(
+                            param.key.loc.end.into(),
+                            typ.clone(),
+                            format!(": {}", typ),
+                            false,
+                        );
+                    }
+                }
+            }
+            walk::Node::CallExpr(call)
+                if self.settings.pipe_types
+                    && call.pipe.is_some() =>
+            {
+                let typ = (self.render)(&self.path, call.typ.clone());
+                self.push(
+                    call.loc.end.into(),
+                    typ.clone(),
+                    typ,
+                    true,
+                );
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn done(&mut self, _node: walk::Node<'a>) {
+        self.path.pop();
+    }
+}
+
+/// The inlay hints of a package in a range.
+pub fn inlay_hints<'a, F>(
+    pkg: &'a Package,
+    range: lsp::Range,
+    settings: &InlayHintSettings,
+    render: F,
+) -> Vec<InlayHint>
+where
+    F: Fn(
+        &[walk::Node<'a>],
+        flux::semantic::types::MonoType,
+    ) -> String,
+{
+    let mut visitor = InlayHintVisitor {
+        settings: settings.clone(),
+        range,
+        render,
+        path: vec![],
+        hints: vec![],
+    };
+    walk::walk(&mut visitor, walk::Node::Package(pkg));
+    visitor.hints
+}
@@ -1,6 +1,11 @@
+mod call_hierarchy;
 mod commands;
+mod inlay_hints;
+mod schema;
+mod snippets;
 mod store;
 mod types;
+mod workspace_symbols;
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
@@ -17,11 +22,14 @@ use flux::semantic::types::{
 };
 use flux::semantic::{walk, ErrorKind};
 use lspower::{
-    jsonrpc::Result as RpcResult, lsp, Client, LanguageServer,
+    jsonrpc::Result as RpcResult, lsp, lsp::request::Request, Client,
+    LanguageServer,
 };
 use strum::IntoEnumIterator;
 
-use crate::{completion, composition, lang, visitors::semantic};
+use crate::{
+    completion, composition, lang, refactor, visitors::semantic,
+};
 
 use self::commands::{
     ClientCommandNotification, CompositionInitializeParams,
@@ -47,7 +55,7 @@ fn node_to_location(
     }
 }
 
-fn find_references<'a>(
+pub(crate) fn find_references<'a>(
     uri: &lsp::Url,
     node: Option<flux::semantic::walk::Node<'a>>,
     path: Vec<flux::semantic::walk::Node<'a>>,
@@ -108,12 +116,23 @@ fn find_references<'a>(
 struct LspServerState {
     buckets: Vec<String>,
     compositions: HashMap<lsp::Url, composition::Composition>,
+    inlay_hints: inlay_hints::InlayHintSettings,
+    /// Whether the client answers `flux/schema` requests.
+    schema_requests: bool,
+    /// The last semantic tokens sent for each document, for delta requests.
+    semantic_tokens: HashMap<lsp::Url, lsp::SemanticTokens>,
+    semantic_tokens_id: u64,
+    snippets: snippets::Snippets,
+    /// The folders indexed for workspace symbols.
+    #[cfg(not(target_arch = "wasm32"))]
+    workspace_folders: Vec<lsp::Url>,
+    /// The symbols of the flux files in the workspace folders.
+    workspace_symbols: workspace_symbols::Index,
+    workspace_symbol_settings:
+        workspace_symbols::WorkspaceSymbolSettings,
 }
 
 impl LspServerState {
-    // XXX: rockstar (21 Jun 2022) - This `allow` pragma is temporary, until we can add
-    // bucket completion, which is blocked on the completion refactor.
-    #[allow(dead_code)]
     pub fn buckets(&self) -> &Vec<String> {
         &self.buckets
     }
@@ -122,6 +141,78 @@ impl LspServerState {
         self.buckets = buckets;
     }
 
+    pub fn inlay_hints(&self) -> &inlay_hints::InlayHintSettings {
+        &self.inlay_hints
+    }
+
+    pub fn set_inlay_hints(
+        &mut self,
+        inlay_hints: inlay_hints::InlayHintSettings,
+    ) {
+        self.inlay_hints = inlay_hints;
+    }
+
+    pub fn schema_requests(&self) -> bool {
+        self.schema_requests
+    }
+
+    pub fn set_schema_requests(&mut self, schema_requests: bool) {
+        self.schema_requests = schema_requests;
+    }
+
+    pub fn snippets(&self) -> &snippets::Snippets {
+        &self.snippets
+    }
+
+    pub fn set_snippets(&mut self, snippets: snippets::Snippets) {
+        self.snippets = snippets;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn workspace_folders(&self) -> &Vec<lsp::Url> {
+        &self.workspace_folders
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_workspace_folders(&mut self, folders: Vec<lsp::Url>) {
+        self.workspace_folders = folders;
+    }
+
+    pub fn workspace_symbols(&self) -> &workspace_symbols::Index {
+        &self.workspace_symbols
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_workspace_symbols(
+        &mut self,
+        workspace_symbols: workspace_symbols::Index,
+    ) {
+        self.workspace_symbols = workspace_symbols;
+    }
+
+    /// Replace the indexed symbols of a file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_file_symbols(
+        &mut self,
+        uri: lsp::Url,
+        symbols: Vec<lsp::SymbolInformation>,
+    ) {
+        self.workspace_symbols.insert(uri, symbols);
+    }
+
+    pub fn workspace_symbol_settings(
+        &self,
+    ) -> &workspace_symbols::WorkspaceSymbolSettings {
+        &self.workspace_symbol_settings
+    }
+
+    pub fn set_workspace_symbol_settings(
+        &mut self,
+        settings: workspace_symbols::WorkspaceSymbolSettings,
+    ) {
+        self.workspace_symbol_settings = settings;
+    }
+
     /// Get a composition from the state
     ///
     /// We return a copy here, as the pointer across threads isn't supported.
@@ -143,13 +234,40 @@ impl LspServerState {
     pub fn drop_composition(&mut self, uri: &lsp::Url) {
         self.compositions.remove(uri);
     }
+
+    pub fn get_semantic_tokens(
+        &self,
+        uri: &lsp::Url,
+    ) -> Option<&lsp::SemanticTokens> {
+        self.semantic_tokens.get(uri)
+    }
+
+    /// Store the semantic tokens of a document, with a new result id.
+    pub fn set_semantic_tokens(
+        &mut self,
+        uri: lsp::Url,
+        data: Vec<lsp::SemanticToken>,
+    ) -> lsp::SemanticTokens {
+        self.semantic_tokens_id += 1;
+        let tokens = lsp::SemanticTokens {
+            result_id: Some(self.semantic_tokens_id.to_string()),
+            data,
+        };
+        self.semantic_tokens.insert(uri, tokens.clone());
+        tokens
+    }
+
+    pub fn drop_semantic_tokens(&mut self, uri: &lsp::Url) {
+        self.semantic_tokens.remove(uri);
+    }
 }
 
 pub struct LspServer {
     client: Arc<Mutex<Option<Client>>>,
     diagnostics: Vec<Diagnostic>,
     store: store::Store,
-    state: Mutex<LspServerState>,
+    schema: schema::SchemaCache,
+    state: Arc<Mutex<LspServerState>>,
     client_capabilities: RwLock<lsp::ClientCapabilities>,
 }
 
@@ -164,7 +282,8 @@ impl LspServer {
                 super::diagnostics::prefer_camel_case,
             ],
             store: store::Store::default(),
-            state: Mutex::new(LspServerState::default()),
+            schema: schema::SchemaCache::default(),
+            state: Arc::new(Mutex::new(LspServerState::default())),
             client_capabilities: RwLock::new(
                 lsp::ClientCapabilities::default(),
             ),
@@ -187,6 +306,273 @@ impl LspServer {
         }
     }
 
+    /// Index the flux files of the workspace folders on a thread, so a large
+    /// workspace doesn't hold up the requests made in the meantime.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn index_workspace(&self) -> std::thread::JoinHandle<()> {
+        let folders = match self.state.lock() {
+            Ok(state) => state.workspace_folders().clone(),
+            Err(err) => {
+                log::error!("{}", err);
+                vec![]
+            }
+        };
+        let state = Arc::clone(&self.state);
+        std::thread::spawn(move || {
+            let symbols = workspace_symbols::index(&folders);
+            match state.lock() {
+                Ok(mut state) => state.set_workspace_symbols(symbols),
+                Err(err) => log::error!("{}", err),
+            }
+        })
+    }
+
+    /// The buckets configured by the client.
+    fn buckets(&self) -> Vec<String> {
+        match self.state.lock() {
+            Ok(state) => state.buckets().clone(),
+            Err(err) => {
+                log::error!("{}", err);
+                vec![]
+            }
+        }
+    }
+
+    /// The inlay hint settings configured by the client.
+    fn inlay_hint_settings(&self) -> inlay_hints::InlayHintSettings {
+        match self.state.lock() {
+            Ok(state) => state.inlay_hints().clone(),
+            Err(err) => {
+                log::error!("{}", err);
+                inlay_hints::InlayHintSettings::default()
+            }
+        }
+    }
+
+    /// Choose where schemas come from, dropping the cached ones. A schema
+    /// file in the workspace is preferred, so schemas can be provided
+    /// without a client that knows how to answer schema requests, and the
+    /// client is only asked if it said it answers them.
+    fn select_schema_provider(&self, folders: &[lsp::Url]) {
+        let schema_requests = match self.state.lock() {
+            Ok(state) => state.schema_requests(),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        };
+        let provider = schema::file_provider(folders).or_else(|| {
+            if !schema_requests {
+                return None;
+            }
+            let client = self.get_client()?;
+            Some(Arc::new(schema::ClientSchemaProvider::new(client))
+                as Arc<dyn schema::SchemaProvider>)
+        });
+        self.schema.set_provider(provider);
+    }
+
+    /// The snippets of the workspace and the client.
+    fn snippets(&self) -> snippets::Snippets {
+        match self.state.lock() {
+            Ok(state) => state.snippets().clone(),
+            Err(err) => {
+                log::error!("{}", err);
+                snippets::Snippets::new()
+            }
+        }
+    }
+
+    /// Return true if the client can render markdown in hover contents.
+    fn supports_markdown_hover(&self) -> bool {
+        match self.client_capabilities.read() {
+            Ok(client_capabilities) => client_capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| {
+                    text_document.hover.as_ref()
+                })
+                .and_then(|hover| hover.content_format.as_ref())
+                .map_or(false, |formats| {
+                    formats.contains(&lsp::MarkupKind::Markdown)
+                }),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn supports_markdown_completion_documentation(&self) -> bool {
+        match self.client_capabilities.read() {
+            Ok(client_capabilities) => client_capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| {
+                    text_document.completion.as_ref()
+                })
+                .and_then(|completion| {
+                    completion.completion_item.as_ref()
+                })
+                .and_then(|completion_item| {
+                    completion_item.documentation_format.as_ref()
+                })
+                .map_or(false, |formats| {
+                    formats.contains(&lsp::MarkupKind::Markdown)
+                }),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
+    }
+
+    fn supports_markdown_signature_documentation(&self) -> bool {
+        match self.client_capabilities.read() {
+            Ok(client_capabilities) => client_capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| {
+                    text_document.signature_help.as_ref()
+                })
+                .and_then(|signature_help| {
+                    signature_help.signature_information.as_ref()
+                })
+                .and_then(|signature_information| {
+                    signature_information
+                        .documentation_format
+                        .as_ref()
+                })
+                .map_or(false, |formats| {
+                    formats.contains(&lsp::MarkupKind::Markdown)
+                }),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
+    }
+
+    /// Return true if the client can register the inlay hint request
+    /// dynamically. `lsp-types` 0.91 drops the `inlayHint` client capability,
+    /// so this follows the dynamic registration of hover, which clients
+    /// support along with that of the other text document requests.
+    fn supports_inlay_hint_registration(&self) -> bool {
+        match self.client_capabilities.read() {
+            Ok(client_capabilities) => client_capabilities
+                .text_document
+                .as_ref()
+                .and_then(|text_document| {
+                    text_document.hover.as_ref()
+                })
+                .and_then(|hover| hover.dynamic_registration)
+                .unwrap_or(false),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
+    }
+
+    /// Return true if the client can register file watchers dynamically.
+    fn supports_watched_files_registration(&self) -> bool {
+        match self.client_capabilities.read() {
+            Ok(client_capabilities) => client_capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| {
+                    workspace.did_change_watched_files.as_ref()
+                })
+                .and_then(|watched_files| {
+                    watched_files.dynamic_registration
+                })
+                .unwrap_or(false),
+            Err(err) => {
+                log::error!("{}", err);
+                false
+            }
+        }
+    }
+
+    /// Completion documentation, as plain text for clients that can't render
+    /// markdown.
+    fn completion_documentation(
+        &self,
+        value: String,
+    ) -> lsp::Documentation {
+        if self.supports_markdown_completion_documentation() {
+            lsp::Documentation::MarkupContent(lsp::MarkupContent {
+                kind: lsp::MarkupKind::Markdown,
+                value,
+            })
+        } else {
+            lsp::Documentation::String(value)
+        }
+    }
+
+    /// The doc comments of the variables and builtins of a document, by name.
+    fn doc_comments(
+        &self,
+        key: &lsp::Url,
+    ) -> HashMap<String, crate::visitors::ast::DocComment> {
+        match self.store.get_ast_package(key) {
+            Ok(pkg) => {
+                crate::walk_ast_package!(
+                    crate::visitors::ast::DocCommentVisitor::default(
+                    ),
+                    pkg
+                )
+                .docs
+            }
+            Err(err) => {
+                log::error!("{}", err);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// The semantic tokens of a document, with absolute positions.
+    fn semantic_tokens(
+        &self,
+        key: &lsp::Url,
+    ) -> RpcResult<Vec<lsp::SemanticToken>> {
+        let contents = self.get_document(key)?;
+        let file = match self.store.get_ast_file(key) {
+            Ok(file) => file,
+            Err(err) => return Err(err.into()),
+        };
+        let mut visitor =
+            crate::visitors::ast::SemanticTokenVisitor::new(
+                &contents,
+            );
+        flux::ast::walk::walk(
+            &mut visitor,
+            flux::ast::walk::Node::File(&file),
+        );
+        Ok(visitor.into_tokens())
+    }
+
+    /// Remember the semantic tokens sent for a document, so the next request
+    /// can be answered with the changes to them.
+    fn cache_semantic_tokens(
+        &self,
+        key: &lsp::Url,
+        data: Vec<lsp::SemanticToken>,
+    ) -> lsp::SemanticTokens {
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.set_semantic_tokens(key.clone(), data)
+            }
+            Err(err) => {
+                log::error!("{}", err);
+                lsp::SemanticTokens {
+                    result_id: None,
+                    data,
+                }
+            }
+        }
+    }
+
     fn get_document(&self, key: &lsp::Url) -> RpcResult<String> {
         match self.store.get(key) {
             Ok(contents) => Ok(contents),
@@ -243,6 +629,8 @@ impl LspServer {
                         .diagnostics
                         .iter()
                         .flat_map(|func| func(&package))
+                        .chain(super::diagnostics::unknown_buckets(&package, &self.buckets()))
+                        .chain(self.store.get_ast_package(key).map(|ast_pkg| super::diagnostics::unknown_columns(&ast_pkg, &package)).unwrap_or_default())
                         .collect::<Vec<(Option<String>, lsp::Diagnostic)>>()
                     } else {
                         vec![]
@@ -299,73 +687,580 @@ impl LspServer {
         diagnostic_map
     }
 
-    fn complete_member_expression(
+    fn complete_member_expression(
+        &self,
+        sem_pkg: &SemanticPackage,
+        contents: &str,
+        node: &crate::visitors::ast::NodeFinderNode,
+        member: &ast::MemberExpr,
+        position: &completion::CallPosition,
+    ) -> Option<Vec<lsp::CompletionItem>> {
+        let mut items = match &member.object {
+            AstExpression::Identifier(identifier) => {
+                // XXX: rockstar (6 Jul 2022) - This is the last holdout from the previous
+                // completion code. There is a bit of indirection/cruft here that can be cleaned
+                // up when recursive support for member expressions is implemented.
+                let mut list: Vec<Box<dyn completion::Completable>> =
+                    vec![];
+                if let Some(import) = completion::get_imports(sem_pkg)
+                    .iter()
+                    .find(|x| x.name == identifier.name)
+                {
+                    for package in lang::STDLIB.packages() {
+                        if package.path == import.path {
+                            completion::walk_package(
+                                &package.path,
+                                &mut list,
+                                &package.exports.typ().expr,
+                            );
+                        }
+                    }
+                } else {
+                    for package in lang::STDLIB.packages() {
+                        if package.name == identifier.name {
+                            completion::walk_package(
+                                &package.path,
+                                &mut list,
+                                &package.exports.typ().expr,
+                            );
+                        }
+                    }
+                }
+
+                let visitor = crate::walk_semantic_package!(
+                    completion::CompletableObjectFinderVisitor::new(
+                        &identifier.name
+                    ),
+                    sem_pkg
+                );
+                let imports = completion::get_imports(sem_pkg);
+                let items: Vec<lsp::CompletionItem> = vec![
+                    visitor
+                        .completables
+                        .iter()
+                        .map(|completable| {
+                            completable.completion_item(&imports)
+                        })
+                        .collect::<Vec<lsp::CompletionItem>>(),
+                    list.iter()
+                        .map(|completable| {
+                            completable.completion_item(&imports)
+                        })
+                        .collect(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                if items.is_empty() {
+                    // The identifier isn't a package or an object literal, e.g. it is a
+                    // function parameter, so fall back to its inferred type.
+                    completion::complete_record_members(
+                        sem_pkg,
+                        &member.object,
+                    )
+                } else {
+                    items
+                }
+            }
+            // Any other object, e.g. `a.b.`, `f().` or `a[0].`, is completed from its
+            // inferred record type.
+            object => {
+                completion::complete_record_members(sem_pkg, object)
+            }
+        };
+        completion::retain_members_for_call_position(
+            sem_pkg,
+            &member.object,
+            &mut items,
+            position,
+        );
+        let postfix = completion::complete_postfix_templates(
+            sem_pkg, contents, node, member, &items,
+        );
+        items.extend(postfix);
+        Some(items)
+    }
+
+    /// Quick fixes for the semantic errors found in the requested range.
+    ///
+    /// These actions should all be connected with a diagnostic. The
+    /// client user experience can vary when not directly connected to
+    /// a diagnostic, which is sorta the client's fault, but we also
+    /// don't have a need for trying to support any other flows.
+    fn quickfix_actions(
+        &self,
+        params: &lsp::CodeActionParams,
+    ) -> RpcResult<Vec<lsp::CodeActionOrCommand>> {
+        if params.context.diagnostics.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let errors = match self
+            .store
+            .get_package_errors(&params.text_document.uri)
+        {
+            Some(errors) => errors,
+            None => return Ok(vec![]),
+        };
+
+        let relevant: Vec<&flux::semantic::Error> = errors
+            .diagnostics
+            .errors
+            .iter()
+            .filter(|error| {
+                crate::lsp::ranges_overlap(
+                    &params.range,
+                    &error.location.clone().into(),
+                )
+            })
+            .collect();
+        if relevant.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let pkg = match self
+            .store
+            .get_semantic_package(&params.text_document.uri)
+        {
+            Ok(pkg) => pkg,
+            Err(err) => unreachable!("{:?}", err),
+        };
+
+        let import_position = completion::import_position(&pkg);
+
+        let actions: Vec<lsp::CodeActionOrCommand> = relevant.iter().map(|error| {
+            if let ErrorKind::Inference(kind) = &error.error {
+                match kind {
+                    SemanticNodeErrorKind::UndefinedIdentifier(identifier) => {
+                        // When encountering undefined identifiers, check to see if they match any corresponding
+                        // packages available for import.
+                        let potential_imports: Vec<lang::Package> = lang::STDLIB.matches(identifier).collect();
+                        if potential_imports.is_empty() {
+                            return None;
+                        }
+
+                        let inner_actions: Vec<lsp::CodeActionOrCommand> = potential_imports.iter().map(|package| {
+                            lsp::CodeAction {
+                                title: format!("Import `{}`", package.path),
+                                kind: Some(lsp::CodeActionKind::QUICKFIX),
+                                diagnostics: None,
+                                edit: Some(lsp::WorkspaceEdit {
+                                    changes: Some(HashMap::from([
+                                        (params.text_document.uri.clone(), vec![
+                                            lsp::TextEdit {
+                                                range: lsp::Range {
+                                                    start: import_position,
+                                                    end: import_position,
+                                                },
+                                                new_text: format!("import \"{}\"\n", package.path),
+                                            }
+                                        ])
+                                    ])),
+                                    document_changes: None,
+                                    change_annotations: None,
+                                }),
+                                command: None,
+                                is_preferred: Some(true),
+                                disabled: None,
+                                data: None,
+                            }.into()
+                        }).collect();
+                        return Some(inner_actions);
+                    },
+                    _ => return None,
+                }
+            }
+            None
+        }).filter(|action| action.is_some()).flat_map(|action| {
+            action.expect("Previous .filter() call failed.")
+        }).collect();
+
+        Ok(actions)
+    }
+
+    /// Quick fixes replacing an unknown bucket with one of the suggested buckets.
+    fn bucket_quickfix_actions(
+        &self,
+        params: &lsp::CodeActionParams,
+    ) -> Vec<lsp::CodeActionOrCommand> {
+        params
+            .context
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.code
+                    == Some(lsp::NumberOrString::String(
+                        super::diagnostics::UNKNOWN_BUCKET.into(),
+                    ))
+            })
+            .flat_map(|diagnostic| {
+                let suggestions: Vec<String> = diagnostic
+                    .data
+                    .clone()
+                    .and_then(|data| {
+                        serde_json::from_value(data).ok()
+                    })
+                    .unwrap_or_default();
+                suggestions.into_iter().enumerate().map(
+                    move |(index, bucket)| {
+                        lsp::CodeAction {
+                            title: format!(
+                                "Replace with `{}`",
+                                bucket
+                            ),
+                            kind: Some(lsp::CodeActionKind::QUICKFIX),
+                            diagnostics: Some(vec![
+                                diagnostic.clone()
+                            ]),
+                            edit: Some(lsp::WorkspaceEdit {
+                                changes: Some(HashMap::from([(
+                                    params.text_document.uri.clone(),
+                                    vec![lsp::TextEdit {
+                                        range: diagnostic.range,
+                                        new_text: format!(
+                                            "\"{}\"",
+                                            bucket
+                                        ),
+                                    }],
+                                )])),
+                                document_changes: None,
+                                change_annotations: None,
+                            }),
+                            command: None,
+                            is_preferred: Some(index == 0),
+                            disabled: None,
+                            data: None,
+                        }
+                        .into()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Quick fixes replacing an unknown column with one of the columns known at
+    /// that stage of the pipeline.
+    fn column_quickfix_actions(
+        &self,
+        params: &lsp::CodeActionParams,
+    ) -> Vec<lsp::CodeActionOrCommand> {
+        params
+            .context
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.code
+                    == Some(lsp::NumberOrString::String(
+                        super::diagnostics::UNKNOWN_COLUMN.into(),
+                    ))
+            })
+            .flat_map(|diagnostic| {
+                let replacements: Vec<
+                    super::diagnostics::ColumnReplacement,
+                > = diagnostic
+                    .data
+                    .clone()
+                    .and_then(|data| {
+                        serde_json::from_value(data).ok()
+                    })
+                    .unwrap_or_default();
+                replacements.into_iter().enumerate().map(
+                    move |(index, replacement)| {
+                        lsp::CodeAction {
+                            title: format!(
+                                "Replace with column `{}`",
+                                replacement.column
+                            ),
+                            kind: Some(lsp::CodeActionKind::QUICKFIX),
+                            diagnostics: Some(vec![
+                                diagnostic.clone()
+                            ]),
+                            edit: Some(lsp::WorkspaceEdit {
+                                changes: Some(HashMap::from([(
+                                    params.text_document.uri.clone(),
+                                    vec![lsp::TextEdit {
+                                        range: diagnostic.range,
+                                        new_text: replacement
+                                            .new_text,
+                                    }],
+                                )])),
+                                document_changes: None,
+                                change_annotations: None,
+                            }),
+                            command: None,
+                            is_preferred: Some(index == 0),
+                            disabled: None,
+                            data: None,
+                        }
+                        .into()
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Hover for a bucket name, saying whether it is one of the configured buckets.
+    fn bucket_hover(
+        &self,
+        pkg: &SemanticPackage,
+        position: lsp::Position,
+    ) -> Option<lsp::Hover> {
+        let buckets = self.buckets();
+        if buckets.is_empty() {
+            return None;
+        }
+        let visitor = crate::walk_semantic_package!(
+            semantic::BucketLiteralVisitor::default(),
+            pkg
+        );
+        let lit = visitor.literals.into_iter().find(|lit| {
+            crate::lsp::position_in_range(
+                &position,
+                &lit.loc.clone().into(),
+            )
+        })?;
+        let value = if buckets.contains(&lit.value) {
+            format!("Bucket `{}`", lit.value)
+        } else {
+            format!("Bucket `{}` is not a known bucket", lit.value)
+        };
+        Some(lsp::Hover {
+            contents: match self.supports_markdown_hover() {
+                true => {
+                    lsp::HoverContents::Markup(lsp::MarkupContent {
+                        kind: lsp::MarkupKind::Markdown,
+                        value,
+                    })
+                }
+                false => lsp::HoverContents::Scalar(
+                    lsp::MarkedString::String(value),
+                ),
+            },
+            range: Some(lit.loc.clone().into()),
+        })
+    }
+
+    /// Hover for the name of a runtime option set by an `option` statement,
+    /// explaining what reads it.
+    fn option_hover(
+        &self,
+        key: &lsp::Url,
+        pkg: &SemanticPackage,
+        position: lsp::Position,
+    ) -> Option<lsp::Hover> {
+        let ast_pkg = self.store.get_ast_package(key).ok()?;
+        let visitor = crate::walk_ast_package!(
+            crate::visitors::ast::NodeFinderVisitor::new(position),
+            ast_pkg
+        );
+        let mut node = visitor.node.as_ref();
+        let statement = loop {
+            match &node?.node {
+                AstNode::OptionStmt(statement) => break *statement,
+                _ => node = node?.parent.as_deref(),
+            }
+        };
+        let range: lsp::Range = match &statement.assignment {
+            ast::Assignment::Variable(assign) => {
+                assign.id.base.location.clone().into()
+            }
+            ast::Assignment::Member(assign) => {
+                assign.member.base.location.clone().into()
+            }
+        };
+        if !crate::lsp::position_in_range(&position, &range) {
+            return None;
+        }
+        let (package, name) =
+            completion::option_name(pkg, statement)?;
+        let option = lang::runtime_option(package.as_deref(), &name)?;
+        let written = crate::lsp::text_in_range(
+            &self.get_document(key).ok()?,
+            &range,
+        )?;
+        Some(self.markdown_hover(option.markdown(&written), range))
+    }
+
+    /// A hover of markdown documentation, as plain text for clients that can't
+    /// render markdown.
+    fn markdown_hover(
+        &self,
+        value: String,
+        range: lsp::Range,
+    ) -> lsp::Hover {
+        lsp::Hover {
+            contents: match self.supports_markdown_hover() {
+                true => {
+                    lsp::HoverContents::Markup(lsp::MarkupContent {
+                        kind: lsp::MarkupKind::Markdown,
+                        value,
+                    })
+                }
+                false => lsp::HoverContents::Scalar(
+                    lsp::MarkedString::String(value),
+                ),
+            },
+            range: Some(range),
+        }
+    }
+
+    /// Hover the documentation of a stdlib function, e.g. `aggregateWindow` or
+    /// `strings.trim`, or of the package of an import path or package name.
+    fn stdlib_hover(
         &self,
-        sem_pkg: &SemanticPackage,
-        member: &ast::MemberExpr,
-    ) -> Option<Vec<lsp::CompletionItem>> {
-        match &member.object {
-            AstExpression::Identifier(identifier) => {
-                // XXX: rockstar (6 Jul 2022) - This is the last holdout from the previous
-                // completion code. There is a bit of indirection/cruft here that can be cleaned
-                // up when recursive support for member expressions is implemented.
-                let mut list: Vec<Box<dyn completion::Completable>> =
-                    vec![];
-                if let Some(import) = completion::get_imports(sem_pkg)
+        key: &lsp::Url,
+        pkg: &SemanticPackage,
+        position: lsp::Position,
+    ) -> Option<lsp::Hover> {
+        let ast_pkg = self.store.get_ast_package(key).ok()?;
+        let visitor = crate::walk_ast_package!(
+            crate::visitors::ast::NodeFinderVisitor::new(position),
+            ast_pkg
+        );
+        let node = visitor.node.as_ref()?;
+        let parent = node.parent.as_ref().map(|parent| &parent.node);
+        let imports = completion::get_imports(pkg);
+        let range: lsp::Range =
+            node.node.base().location.clone().into();
+        match (&node.node, parent) {
+            (
+                AstNode::StringLit(lit),
+                Some(AstNode::ImportDeclaration(_)),
+            ) => {
+                let package = lang::STDLIB.package(&lit.value)?;
+                Some(self.markdown_hover(package.markdown(), range))
+            }
+            (
+                AstNode::Identifier(identifier),
+                Some(AstNode::MemberExpr(member)),
+            ) => {
+                let object = match &member.object {
+                    AstExpression::Identifier(object) => object,
+                    _ => return None,
+                };
+                let import = imports
                     .iter()
-                    .find(|x| x.name == identifier.name)
-                {
-                    for package in lang::STDLIB.packages() {
-                        if package.path == import.path {
-                            completion::walk_package(
-                                &package.path,
-                                &mut list,
-                                &package.exports.typ().expr,
-                            );
-                        }
-                    }
+                    .find(|import| import.name == object.name)?;
+                let package = lang::STDLIB.package(&import.path)?;
+                if object.base.location == identifier.base.location {
+                    Some(
+                        self.markdown_hover(
+                            package.markdown(),
+                            range,
+                        ),
+                    )
                 } else {
-                    for package in lang::STDLIB.packages() {
-                        if package.name == identifier.name {
-                            completion::walk_package(
-                                &package.path,
-                                &mut list,
-                                &package.exports.typ().expr,
-                            );
-                        }
+                    let function =
+                        package.function(&identifier.name)?;
+                    Some(
+                        self.markdown_hover(
+                            function.markdown(),
+                            range,
+                        ),
+                    )
+                }
+            }
+            (AstNode::Identifier(identifier), parent) => {
+                // Only references can be prelude functions, not the names of
+                // properties or variables.
+                let is_name = match parent {
+                    Some(AstNode::Property(property)) => {
+                        *completion::property_key_location(
+                            &property.key,
+                        ) == identifier.base.location
+                    }
+                    Some(AstNode::VariableAssgn(assign)) => {
+                        assign.id.base.location
+                            == identifier.base.location
                     }
+                    _ => false,
+                };
+                // A binding of the same name shadows the prelude.
+                if is_name
+                    || completion::is_bound_in_scope(
+                        pkg,
+                        position,
+                        &identifier.name,
+                    )
+                {
+                    return None;
                 }
-
-                let visitor = crate::walk_semantic_package!(
-                    completion::CompletableObjectFinderVisitor::new(
-                        &identifier.name
-                    ),
-                    sem_pkg
-                );
-                let imports = completion::get_imports(sem_pkg);
-                Some(
-                    vec![
-                        visitor
-                            .completables
-                            .iter()
-                            .map(|completable| {
-                                completable.completion_item(&imports)
-                            })
-                            .collect::<Vec<lsp::CompletionItem>>(),
-                        list.iter()
-                            .map(|completable| {
-                                completable.completion_item(&imports)
-                            })
-                            .collect(),
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect(),
-                )
+                let function =
+                    lang::UNIVERSE.function(&identifier.name)?;
+                Some(self.markdown_hover(function.markdown(), range))
             }
             _ => None,
         }
     }
+
+    /// Refactorings available at the start of the requested range.
+    fn refactor_actions(
+        &self,
+        params: &lsp::CodeActionParams,
+    ) -> Vec<lsp::CodeActionOrCommand> {
+        let uri = &params.text_document.uri;
+        // A failure here shouldn't take the quick fixes down with it.
+        let (contents, pkg, file) = match (
+            self.get_document(uri),
+            self.store.get_semantic_package(uri),
+            self.store.get_ast_file(uri),
+        ) {
+            (Ok(contents), Ok(pkg), Ok(file)) => {
+                (contents, pkg, file)
+            }
+            (Err(err), _, _) => {
+                log::error!("{:?}", err);
+                return vec![];
+            }
+            (_, Err(err), _) | (_, _, Err(err)) => {
+                log::error!("{:?}", err);
+                return vec![];
+            }
+        };
+        let position = params.range.start;
+
+        vec![
+            refactor::inline_variable(uri, &contents, &pkg, position),
+            refactor::inline_function(uri, &contents, &pkg, position),
+            refactor::split_filter(uri, &contents, &file, position),
+            refactor::concatenation_to_interpolation(
+                uri, &contents, &pkg, position,
+            ),
+            refactor::interpolation_to_concatenation(
+                uri, &contents, &pkg, position,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(refactor::merge_filters(uri, &file, position))
+        .chain(refactor::move_pipe_stage(
+            uri, &contents, &file, position,
+        ))
+        .map(lsp::CodeActionOrCommand::from)
+        .collect()
+    }
+
+    /// Show the inferred types of variables, function parameters and pipe
+    /// stages, rendered with their constraints like in `hover`.
+    pub async fn inlay_hint(
+        &self,
+        params: inlay_hints::InlayHintParams,
+    ) -> RpcResult<Option<Vec<inlay_hints::InlayHint>>> {
+        let pkg = match self
+            .store
+            .get_semantic_package(&params.text_document.uri)
+        {
+            Ok(pkg) => pkg,
+            Err(err) => return Err(err.into()),
+        };
+        let hints = inlay_hints::inlay_hints(
+            &pkg,
+            params.range,
+            &self.inlay_hint_settings(),
+            |path, t| include_constraints(path, t).to_string(),
+        );
+        Ok(Some(hints))
+    }
 }
 
 #[lspower::async_trait]
@@ -374,6 +1269,33 @@ impl LanguageServer for LspServer {
         &self,
         params: lsp::InitializeParams,
     ) -> RpcResult<lsp::InitializeResult> {
+        let folders: Vec<lsp::Url> = match &params.workspace_folders {
+            Some(folders) => folders
+                .iter()
+                .map(|folder| folder.uri.clone())
+                .collect(),
+            None => params.root_uri.iter().cloned().collect(),
+        };
+
+        let snippets = snippets::load(
+            &folders,
+            params.initialization_options.as_ref(),
+        );
+        match self.state.lock() {
+            Ok(mut state) => {
+                state.set_schema_requests(
+                    schema::client_answers_requests(
+                        params.initialization_options.as_ref(),
+                    ),
+                );
+                state.set_snippets(snippets);
+                #[cfg(not(target_arch = "wasm32"))]
+                state.set_workspace_folders(folders.clone());
+            }
+            Err(err) => log::error!("{}", err),
+        }
+        self.select_schema_provider(&folders);
+
         match self.client_capabilities.write() {
             Ok(mut client_capabilities) => {
                 *client_capabilities = params.capabilities;
@@ -383,9 +1305,22 @@ impl LanguageServer for LspServer {
 
         Ok(lsp::InitializeResult {
             capabilities: lsp::ServerCapabilities {
-                code_action_provider: Some(lsp::CodeActionProviderCapability::Simple(true)),
-                completion_provider: Some(lsp::CompletionOptions {
+                call_hierarchy_provider: Some(
+                    lsp::CallHierarchyServerCapability::Simple(true),
+                ),
+                code_action_provider: Some(lsp::CodeActionProviderCapability::Options(lsp::CodeActionOptions {
+                    code_action_kinds: Some(vec![
+                        lsp::CodeActionKind::QUICKFIX,
+                        lsp::CodeActionKind::REFACTOR_INLINE,
+                        lsp::CodeActionKind::REFACTOR_REWRITE,
+                    ]),
+                    work_done_progress_options: lsp::WorkDoneProgressOptions {
+                        work_done_progress: None,
+                    },
                     resolve_provider: None,
+                })),
+                completion_provider: Some(lsp::CompletionOptions {
+                    resolve_provider: Some(true),
                     trigger_characters: Some(vec![
                         ".".to_string(),
                         ":".to_string(),
@@ -423,16 +1358,21 @@ impl LanguageServer for LspServer {
                 ),
                 references_provider: Some(lsp::OneOf::Left(true)),
                 rename_provider: Some(lsp::OneOf::Left(true)),
+                selection_range_provider: Some(
+                    lsp::SelectionRangeProviderCapability::Simple(true),
+                ),
                 semantic_tokens_provider: Some(lsp::SemanticTokensServerCapabilities::SemanticTokensOptions(lsp::SemanticTokensOptions{
                     work_done_progress_options: lsp::WorkDoneProgressOptions {
                         work_done_progress: None
                     },
                     legend: lsp::SemanticTokensLegend {
                         token_types: crate::visitors::ast::SemanticToken::LSP_MAPPING.to_owned(),
-                        token_modifiers: vec![],
+                        token_modifiers: crate::visitors::ast::SemanticTokenModifier::LSP_MAPPING.to_owned(),
                     },
-                    range: None,
-                    full: None,
+                    range: Some(true),
+                    full: Some(lsp::SemanticTokensFullOptions::Delta {
+                        delta: Some(true),
+                    }),
                 })),
                 signature_help_provider: Some(
                     lsp::SignatureHelpOptions {
@@ -440,7 +1380,8 @@ impl LanguageServer for LspServer {
                             "(".to_string()
                         ]),
                         retrigger_characters: Some(vec![
-                            "(".to_string()
+                            "(".to_string(),
+                            ",".to_string(),
                         ]),
                         work_done_progress_options:
                             lsp::WorkDoneProgressOptions {
@@ -457,6 +1398,7 @@ impl LanguageServer for LspServer {
                         }
                     ),
                 ),
+                workspace_symbol_provider: Some(lsp::OneOf::Left(true)),
                 ..Default::default()
             },
             server_info: Some(lsp::ServerInfo {
@@ -466,6 +1408,54 @@ impl LanguageServer for LspServer {
         })
     }
 
+    async fn initialized(&self, _: lsp::InitializedParams) {
+        // The indexing thread is detached, and searches only find the open
+        // documents until it's done.
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = self.index_workspace();
+
+        // The inlay hint capability is registered dynamically, as `lsp-types`
+        // doesn't have the inlay hint provider of the initialize result yet.
+        // Schema files are watched so edits to them reach completion. Each is
+        // only registered with clients that support registering it.
+        let mut registrations = vec![];
+        if self.supports_inlay_hint_registration() {
+            registrations.push(lsp::Registration {
+                id: inlay_hints::InlayHintRequest::METHOD.to_string(),
+                method: inlay_hints::InlayHintRequest::METHOD
+                    .to_string(),
+                register_options: None,
+            });
+        }
+        if self.supports_watched_files_registration() {
+            let watchers =
+                lsp::DidChangeWatchedFilesRegistrationOptions {
+                    watchers: vec![lsp::FileSystemWatcher {
+                        glob_pattern: format!(
+                            "**/{}",
+                            schema::SCHEMA_FILE
+                        ),
+                        kind: None,
+                    }],
+                };
+            registrations.push(lsp::Registration {
+                id: "workspace/didChangeWatchedFiles".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(watchers).ok(),
+            });
+        }
+        if registrations.is_empty() {
+            return;
+        }
+        if let Some(client) = self.get_client() {
+            if let Err(err) =
+                client.register_capability(registrations).await
+            {
+                log::error!("{}", err);
+            }
+        }
+    }
+
     async fn shutdown(&self) -> RpcResult<()> {
         // XXX: rockstar (19 May 2022) - This chunk of code will no longer be needed,
         // when tower-lsp is added again.
@@ -600,14 +1590,53 @@ impl LanguageServer for LspServer {
         params: lsp::DidCloseTextDocumentParams,
     ) -> () {
         self.store.remove(&params.text_document.uri);
+        // Unsaved changes are gone once the document is closed, so indexed
+        // files go back to their symbols on disk.
+        #[cfg(not(target_arch = "wasm32"))]
+        let indexed =
+            params.text_document.uri.to_file_path().ok().and_then(
+                |path| workspace_symbols::index_file(&path),
+            );
         match self.state.lock() {
             Ok(mut state) => {
-                state.drop_composition(&params.text_document.uri)
+                state.drop_composition(&params.text_document.uri);
+                state.drop_semantic_tokens(&params.text_document.uri);
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some((uri, symbols)) = indexed {
+                    if state.workspace_symbols().contains_key(&uri) {
+                        state.set_file_symbols(uri, symbols);
+                    }
+                }
             }
             Err(err) => panic!("{}", err),
         }
     }
 
+    async fn did_change_watched_files(
+        &self,
+        params: lsp::DidChangeWatchedFilesParams,
+    ) -> () {
+        if params
+            .changes
+            .iter()
+            .any(|change| schema::is_schema_file(&change.uri))
+        {
+            // The schema file may have been created or deleted, so the
+            // provider is chosen again.
+            #[cfg(not(target_arch = "wasm32"))]
+            let folders = match self.state.lock() {
+                Ok(state) => state.workspace_folders().clone(),
+                Err(err) => {
+                    log::error!("{}", err);
+                    vec![]
+                }
+            };
+            #[cfg(target_arch = "wasm32")]
+            let folders: Vec<lsp::Url> = vec![];
+            self.select_schema_provider(&folders);
+        }
+    }
+
     async fn did_change_configuration(
         &self,
         params: lsp::DidChangeConfigurationParams,
@@ -615,6 +1644,47 @@ impl LanguageServer for LspServer {
         if let serde_json::value::Value::Object(map) = params.settings
         {
             if let Some(settings) = map.get("settings") {
+                if let Some(inlay_hints) = settings.get("inlayHints")
+                {
+                    match serde_json::from_value(inlay_hints.clone())
+                    {
+                        Ok(inlay_hints) => match self.state.lock() {
+                            Ok(mut state) => {
+                                state.set_inlay_hints(inlay_hints)
+                            }
+                            Err(err) => log::error!("{}", err),
+                        },
+                        Err(err) => {
+                            log::error!(
+                                "settings.inlayHints: {}",
+                                err
+                            )
+                        }
+                    }
+                }
+                if let Some(workspace_symbols) =
+                    settings.get("workspaceSymbols")
+                {
+                    match serde_json::from_value(
+                        workspace_symbols.clone(),
+                    ) {
+                        Ok(workspace_symbols) => {
+                            match self.state.lock() {
+                                Ok(mut state) => state
+                                    .set_workspace_symbol_settings(
+                                        workspace_symbols,
+                                    ),
+                                Err(err) => log::error!("{}", err),
+                            }
+                        }
+                        Err(err) => {
+                            log::error!(
+                                "settings.workspaceSymbols: {}",
+                                err
+                            )
+                        }
+                    }
+                }
                 if let Some(serde_json::value::Value::Array(
                     buckets,
                 )) = settings.get("buckets")
@@ -649,64 +1719,144 @@ impl LanguageServer for LspServer {
     ) -> RpcResult<Option<lsp::SignatureHelp>> {
         let key =
             params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
         let pkg = match self.store.get_semantic_package(&key) {
             Ok(pkg) => pkg,
             Err(err) => return Err(err.into()),
         };
 
         let visitor = crate::walk_semantic_package!(
-            semantic::NodeFinderVisitor::new(
-                params.text_document_position_params.position
-            ),
+            semantic::NodeFinderVisitor::new(position),
             pkg
         );
-        let signatures: Vec<lsp::SignatureInformation> = if let Some(
-            node,
-        ) =
-            visitor.node
-        {
-            if let walk::Node::CallExpr(call) = node {
-                let callee = call.callee.clone();
+        // The innermost call around the position, which is usually in one of
+        // the arguments of the call.
+        let call =
+            match visitor.path.iter().rev().find_map(
+                |node| match node {
+                    walk::Node::CallExpr(call) => Some(*call),
+                    _ => None,
+                },
+            ) {
+                Some(call) => call,
+                None => {
+                    log::debug!(
+                        "signature_help on non-call expression"
+                    );
+                    return Ok(None);
+                }
+            };
 
-                if let flux::semantic::nodes::Expression::Member(member) = callee.clone() {
-                    let name = member.property.clone();
-                    if let flux::semantic::nodes::Expression::Identifier(ident) = member.object.clone() {
-                        match lang::STDLIB.package(&ident.name) {
-                            None => return Ok(None),
-                            Some(package) => match package.function(&name) {
-                                None => return Ok(None),
-                                Some(function) => function.signature_information(),
+        let signatures: Vec<lsp::SignatureInformation> = match &call
+            .callee
+        {
+            flux::semantic::nodes::Expression::Member(member) => {
+                let object = match &member.object {
+                    flux::semantic::nodes::Expression::Identifier(
+                        ident,
+                    ) => ident.name.to_string(),
+                    _ => return Ok(None),
+                };
+                match completion::get_imports(&pkg)
+                    .into_iter()
+                    .find(|import| import.name == object)
+                {
+                    Some(import) => {
+                        match lang::STDLIB
+                            .package(&import.path)
+                            .and_then(|package| {
+                                package.function(&member.property)
+                            }) {
+                            Some(function) => {
+                                function.signature_information()
                             }
+                            None => return Ok(None),
                         }
-                    } else {
-                        return Ok(None);
                     }
-                } else if let flux::semantic::nodes::Expression::Identifier(ident) = callee {
+                    // A function in a record, e.g. a function of a user package.
+                    None => match &member.typ {
+                        MonoType::Fun(function) => {
+                            vec![completion::user_function_signature(
+                                &format!(
+                                    "{}.{}",
+                                    object, member.property
+                                ),
+                                function,
+                                None,
+                                self.supports_markdown_signature_documentation(),
+                            )]
+                        }
+                        _ => return Ok(None),
+                    },
+                }
+            }
+            flux::semantic::nodes::Expression::Identifier(ident) => {
+                let user_function = match &ident.typ {
+                    MonoType::Fun(function)
+                        if completion::is_bound_in_scope(
+                            &pkg,
+                            position,
+                            &ident.name,
+                        ) =>
+                    {
+                        Some(function)
+                    }
+                    _ => None,
+                };
+                if let Some(function) = user_function {
+                    let docs = self.doc_comments(&key);
+                    vec![completion::user_function_signature(
+                        &ident.name,
+                        function,
+                        docs.get(&ident.name.to_string()),
+                        self.supports_markdown_signature_documentation(),
+                    )]
+                } else {
                     match lang::UNIVERSE.function(&ident.name) {
                         Some(function) => {
                             function.signature_information()
                         }
                         None => return Ok(None),
                     }
-                } else {
-                    log::debug!("signature_help on non-member and non-identifier");
-                    return Ok(None);
                 }
-            } else {
-                log::debug!("signature_help on non-call expression");
+            }
+            _ => {
+                log::debug!(
+                    "signature_help on non-member and non-identifier"
+                );
                 return Ok(None);
             }
-        } else {
-            return Ok(None);
         };
 
+        let written: Vec<String> = call
+            .arguments
+            .iter()
+            .map(|argument| argument.key.name.to_string())
+            .collect();
+        let current = call
+            .arguments
+            .iter()
+            .find(|argument| {
+                crate::lsp::position_in_range(
+                    &position,
+                    &argument.loc.clone().into(),
+                )
+            })
+            .map(|argument| argument.key.name.to_string());
+        let (active_signature, active_parameter) =
+            completion::active_signature(
+                &signatures,
+                &written,
+                current.as_deref(),
+            );
+
         let response = if signatures.is_empty() {
             None
         } else {
             Some(lsp::SignatureHelp {
                 signatures,
-                active_signature: None,
-                active_parameter: None,
+                active_signature,
+                active_parameter,
             })
         };
         Ok(response)
@@ -823,32 +1973,74 @@ impl LanguageServer for LspServer {
             Err(err) => return Err(err.into()),
         };
 
-        let visitor = crate::walk_semantic_package!(
-            semantic::SymbolsVisitor::new(key),
-            pkg
-        );
-        let mut symbols = visitor.symbols;
-
-        symbols.sort_by(|a, b| {
-            let a_start = a.location.range.start;
-            let b_start = b.location.range.start;
-
-            if a_start.line == b_start.line {
-                a_start.character.cmp(&b_start.character)
-            } else {
-                a_start.line.cmp(&b_start.line)
-            }
-        });
+        // The package can have other files, which have their own outline.
+        let filename =
+            key.path_segments().and_then(|segments| segments.last());
+        let symbols: Vec<lsp::DocumentSymbol> = pkg
+            .files
+            .iter()
+            .filter(|file| file.loc.file.as_deref() == filename)
+            .flat_map(semantic::document_symbols)
+            .collect();
 
         let response = if symbols.is_empty() {
             None
         } else {
-            Some(lsp::DocumentSymbolResponse::Flat(symbols))
+            Some(lsp::DocumentSymbolResponse::Nested(symbols))
         };
 
         Ok(response)
     }
 
+    async fn symbol(
+        &self,
+        params: lsp::WorkspaceSymbolParams,
+    ) -> RpcResult<Option<Vec<lsp::SymbolInformation>>> {
+        let (mut index, settings) = match self.state.lock() {
+            Ok(state) => (
+                state.workspace_symbols().clone(),
+                state.workspace_symbol_settings().clone(),
+            ),
+            Err(err) => {
+                log::error!("{}", err);
+                return Ok(None);
+            }
+        };
+
+        // Open documents are searched as they are in the editor, rather than
+        // as they were indexed from disk.
+        for url in self.store.get_urls() {
+            match self.store.get_ast_file(&url) {
+                Ok(file) => {
+                    let symbols =
+                        workspace_symbols::file_symbols(&url, &file);
+                    index.insert(url, symbols);
+                }
+                Err(err) => log::error!("{:?}", err),
+            }
+        }
+
+        // Sort by url, so symbols with the same score are in a stable order.
+        let mut files: Vec<(lsp::Url, Vec<lsp::SymbolInformation>)> =
+            index.into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut symbols: Vec<lsp::SymbolInformation> = files
+            .into_iter()
+            .flat_map(|(_, symbols)| symbols)
+            .collect();
+        if settings.stdlib {
+            symbols.extend(workspace_symbols::stdlib_symbols());
+        }
+
+        let symbols =
+            workspace_symbols::search(symbols, &params.query);
+        Ok(if symbols.is_empty() {
+            None
+        } else {
+            Some(symbols)
+        })
+    }
+
     async fn goto_definition(
         &self,
         params: lsp::GotoDefinitionParams,
@@ -889,6 +2081,58 @@ impl LanguageServer for LspServer {
         Ok(None)
     }
 
+    async fn prepare_call_hierarchy(
+        &self,
+        params: lsp::CallHierarchyPrepareParams,
+    ) -> RpcResult<Option<Vec<lsp::CallHierarchyItem>>> {
+        let key =
+            params.text_document_position_params.text_document.uri;
+        let pkg = match self.store.get_semantic_package(&key) {
+            Ok(pkg) => pkg,
+            Err(err) => return Err(err.into()),
+        };
+
+        let urls = self.store.get_package_urls(&key);
+        Ok(call_hierarchy::prepare(
+            &pkg,
+            &urls,
+            params.text_document_position_params.position,
+        )
+        .map(|item| vec![item]))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: lsp::CallHierarchyIncomingCallsParams,
+    ) -> RpcResult<Option<Vec<lsp::CallHierarchyIncomingCall>>> {
+        let key = &params.item.uri;
+        let pkg = match self.store.get_semantic_package(key) {
+            Ok(pkg) => pkg,
+            Err(err) => return Err(err.into()),
+        };
+
+        let urls = self.store.get_package_urls(key);
+        let calls =
+            call_hierarchy::incoming_calls(&pkg, &urls, &params.item);
+        Ok(if calls.is_empty() { None } else { Some(calls) })
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: lsp::CallHierarchyOutgoingCallsParams,
+    ) -> RpcResult<Option<Vec<lsp::CallHierarchyOutgoingCall>>> {
+        let key = &params.item.uri;
+        let pkg = match self.store.get_semantic_package(key) {
+            Ok(pkg) => pkg,
+            Err(err) => return Err(err.into()),
+        };
+
+        let urls = self.store.get_package_urls(key);
+        let calls =
+            call_hierarchy::outgoing_calls(&pkg, &urls, &params.item);
+        Ok(if calls.is_empty() { None } else { Some(calls) })
+    }
+
     async fn rename(
         &self,
         params: lsp::RenameParams,
@@ -922,6 +2166,45 @@ impl LanguageServer for LspServer {
         }))
     }
 
+    async fn selection_range(
+        &self,
+        params: lsp::SelectionRangeParams,
+    ) -> RpcResult<Option<Vec<lsp::SelectionRange>>> {
+        let file = match self
+            .store
+            .get_ast_file(&params.text_document.uri)
+        {
+            Ok(file) => file,
+            Err(err) => return Err(err.into()),
+        };
+
+        // Every position needs a selection range, so a position outside of
+        // the nodes of the file selects nothing.
+        let ranges = params
+            .positions
+            .into_iter()
+            .map(|position| {
+                let mut visitor =
+                    crate::visitors::ast::NodeFinderVisitor::new(
+                        position,
+                    );
+                flux::ast::walk::walk(
+                    &mut visitor,
+                    flux::ast::walk::Node::File(&file),
+                );
+                visitor
+                    .node
+                    .and_then(|node| node.selection_range())
+                    .unwrap_or(lsp::SelectionRange {
+                        range: lsp::Range::new(position, position),
+                        parent: None,
+                    })
+            })
+            .collect();
+
+        Ok(Some(ranges))
+    }
+
     async fn document_highlight(
         &self,
         params: lsp::DocumentHighlightParams,
@@ -987,6 +2270,29 @@ impl LanguageServer for LspServer {
             Err(err) => return Err(err.into()),
         };
 
+        if let Some(hover) = self.bucket_hover(
+            &pkg,
+            params.text_document_position_params.position,
+        ) {
+            return Ok(Some(hover));
+        }
+
+        if let Some(hover) = self.option_hover(
+            &key,
+            &pkg,
+            params.text_document_position_params.position,
+        ) {
+            return Ok(Some(hover));
+        }
+
+        if let Some(hover) = self.stdlib_hover(
+            &key,
+            &pkg,
+            params.text_document_position_params.position,
+        ) {
+            return Ok(Some(hover));
+        }
+
         let visitor = crate::walk_semantic_package!(
             semantic::NodeFinderVisitor::new(
                 params.text_document_position_params.position
@@ -1043,47 +2349,51 @@ impl LanguageServer for LspServer {
                     _ => None,
                 });
             if let Some(typ) = hover_type {
-                let supports_markdown = match self
-                    .client_capabilities
-                    .read()
-                {
-                    Ok(client_capabilities) => {
-                        if let Some(text_document) =
-                            (client_capabilities)
-                                .text_document
-                                .as_ref()
-                        {
-                            if let Some(hover) =
-                                text_document.hover.as_ref()
-                            {
-                                hover.content_format.as_ref().map_or(false, |formats| formats.contains(&lsp::MarkupKind::Markdown))
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
+                let name = match node {
+                    walk::Node::Identifier(ident) => {
+                        Some(ident.name.to_string())
                     }
-                    Err(err) => {
-                        log::error!("{}", err);
-                        false
+                    walk::Node::IdentifierExpr(ident) => {
+                        Some(ident.name.to_string())
                     }
+                    _ => None,
                 };
-                let hover_contents: lsp::HoverContents =
-                    match supports_markdown {
-                        true => lsp::HoverContents::Markup(
-                            lsp::MarkupContent {
-                                kind: lsp::MarkupKind::Markdown,
-                                value: format!(
-                                    "```flux\n{}\n```",
-                                    typ
-                                ),
-                            },
-                        ),
-                        false => lsp::HoverContents::Scalar(
-                            lsp::MarkedString::String(typ),
+                // Doc comments are only for the top-level bindings, so
+                // not for the parameters and variables that shadow them.
+                if let Some((name, doc)) = name
+                    .filter(|name| {
+                        !semantic::is_bound_in_function(path, name)
+                    })
+                    .and_then(|name| {
+                        let doc =
+                            self.doc_comments(&key).remove(&name)?;
+                        Some((name, doc))
+                    })
+                {
+                    return Ok(Some(self.markdown_hover(
+                        format!(
+                            "```flux\n{}: {}\n```\n\n{}",
+                            name,
+                            typ,
+                            doc.markdown()
                         ),
-                    };
+                        node.loc().clone().into(),
+                    )));
+                }
+
+                let hover_contents: lsp::HoverContents = match self
+                    .supports_markdown_hover()
+                {
+                    true => lsp::HoverContents::Markup(
+                        lsp::MarkupContent {
+                            kind: lsp::MarkupKind::Markdown,
+                            value: format!("```flux\n{}\n```", typ),
+                        },
+                    ),
+                    false => lsp::HoverContents::Scalar(
+                        lsp::MarkedString::String(typ),
+                    ),
+                };
 
                 return Ok(Some(lsp::Hover {
                     contents: hover_contents,
@@ -1098,14 +2408,6 @@ impl LanguageServer for LspServer {
         &self,
         params: lsp::CompletionParams,
     ) -> RpcResult<Option<lsp::CompletionResponse>> {
-        // This is the rules for matching whether a string should be part of
-        // the completion matching.
-        let fuzzy_match = |haystack: &str, needle: &str| -> bool {
-            return haystack
-                .to_lowercase()
-                .contains(needle.to_lowercase().as_str());
-        };
-
         let ast_pkg = match self.store.get_ast_package(
             &params.text_document_position.text_document.uri,
         ) {
@@ -1127,15 +2429,112 @@ impl LanguageServer for LspServer {
             ),
             ast_pkg
         );
-        let items = match visitor.node {
-            Some(walk_node) => match walk_node.node {
-                AstNode::CallExpr(call) => {
-                    completion::complete_call_expr(
-                        &params, &sem_pkg, call,
-                    )
+
+        if let Some(query) =
+            visitor.node.as_ref().and_then(completion::schema_query)
+        {
+            if let Some(schema) = self.schema.get(&query.bucket).await
+            {
+                let measurement = query.measurement.as_deref();
+                let values = match &query.kind {
+                    completion::SchemaQueryKind::Measurements => {
+                        schema.measurements()
+                    }
+                    completion::SchemaQueryKind::Fields => {
+                        schema.fields(measurement)
+                    }
+                    completion::SchemaQueryKind::TagKeys => {
+                        schema.tag_keys(measurement)
+                    }
+                    completion::SchemaQueryKind::TagValues(tag) => {
+                        schema.tag_values(measurement, tag)
+                    }
+                };
+                let items = completion::schema_completion_items(
+                    &query, values,
+                );
+                if !items.is_empty() {
+                    return Ok(Some(lsp::CompletionResponse::List(
+                        lsp::CompletionList {
+                            is_incomplete: false,
+                            items,
+                        },
+                    )));
                 }
-                AstNode::Identifier(identifier) => {
-                    match walk_node
+            }
+        }
+
+        let contents = self.get_document(
+            &params.text_document_position.text_document.uri,
+        )?;
+        if let Some(items) = completion::complete_option_statement(
+            &sem_pkg,
+            &contents,
+            params.text_document_position.position,
+        ) {
+            return Ok(Some(lsp::CompletionResponse::List(
+                lsp::CompletionList {
+                    is_incomplete: false,
+                    items,
+                },
+            )));
+        }
+
+        if let Some(walk_node) = visitor.node.as_ref() {
+            if let Some(items) = completion::complete_option_record(
+                &sem_pkg, walk_node,
+            ) {
+                return Ok(Some(lsp::CompletionResponse::List(
+                    lsp::CompletionList {
+                        is_incomplete: false,
+                        items,
+                    },
+                )));
+            }
+            if let Some(items) = completion::complete_argument_value(
+                &sem_pkg,
+                &contents,
+                walk_node,
+                params.text_document_position.position,
+            ) {
+                return Ok(Some(lsp::CompletionResponse::List(
+                    lsp::CompletionList {
+                        is_incomplete: true,
+                        items,
+                    },
+                )));
+            }
+            if let Some(items) = completion::complete_pipeline_columns(
+                &sem_pkg, walk_node,
+            ) {
+                return Ok(Some(lsp::CompletionResponse::List(
+                    lsp::CompletionList {
+                        is_incomplete: false,
+                        items,
+                    },
+                )));
+            }
+        }
+
+        // Query skeletons are only offered where a statement can be written.
+        let snippet_candidates = if completion::is_statement_position(
+            visitor.node.as_ref(),
+        ) {
+            snippets::snippet_candidates(&sem_pkg, &self.snippets())
+        } else {
+            vec![]
+        };
+
+        let items = match visitor.node {
+            Some(walk_node) => {
+                match walk_node.node {
+                    AstNode::CallExpr(call) => {
+                        completion::complete_call_expr(
+                            &params, &sem_pkg, call,
+                        )
+                    }
+                    AstNode::Identifier(identifier) => {
+                        match walk_node
                         .parent
                         .as_ref()
                         .map(|node| &node.node)
@@ -1150,21 +2549,34 @@ impl LanguageServer for LspServer {
                                 == identifier.base.location.start =>
                         {
                             match self.complete_member_expression(
-                                &sem_pkg, member,
+                                &sem_pkg,
+                                &contents,
+                                &walk_node,
+                                member,
+                                &completion::call_position(
+                                    &sem_pkg, &walk_node,
+                                ),
                             ) {
                                 Some(items) => items,
                                 None => return Ok(None),
                             }
                         }
+                        // The identifier is the expression of a `${}` interpolation.
+                        Some(AstNode::InterpolatedPart(_)) => {
+                            completion::complete_identifiers_in_scope(
+                                &sem_pkg,
+                                params.text_document_position.position,
+                            )
+                        }
                         _ => {
                             // XXX: rockstar (6 Jul 2022) - This is helping to complete packages that
                             // have never been imported. That's probably not a great pattern.
                             let stdlib_completions: Vec<
-                                lsp::CompletionItem,
+                                completion::Candidate,
                             > = lang::STDLIB
                                 .fuzzy_matches(&identifier.name)
-                                .map(|package| {
-                                    lsp::CompletionItem {
+                                .map(|package| completion::Candidate {
+                                    item: lsp::CompletionItem {
                                 label: package.path.clone(),
                                 detail: Some("Package".into()),
                                 documentation: Some(
@@ -1184,26 +2596,28 @@ impl LanguageServer for LspServer {
                                 kind: Some(
                                     lsp::CompletionItemKind::MODULE,
                                 ),
+                                data: completion::CompletionItemData::package(&package.path),
                                 sort_text: Some(package.path),
                                 ..lsp::CompletionItem::default()
-                            }
+                            },
+                                    source: completion::CompletionSource::Stdlib,
+                                    typ: None,
                                 })
                                 .collect();
 
                             let builtin_completions: Vec<
-                        lsp::CompletionItem,
+                        completion::Candidate,
                     > = lang::UNIVERSE.exports.iter().filter(|(key, val)| {
                             // Don't allow users to "discover" private-ish functionality.
-                            // Filter out irrelevent items that won't match.
                             // Only pass expressions that have completion support.
-                            !key.starts_with('_') && fuzzy_match(key, &identifier.name) &&
+                            !key.starts_with('_') &&
                             match &val.expr {
                                 MonoType::Fun(_) | MonoType::Builtin(_) => true,
                                 MonoType::Collection(collection) => collection.collection == CollectionType::Array,
                                 _ => false
                             }
-                        }).map(|(key, val)| {
-                            match &val.expr {
+                        }).map(|(key, val)| completion::Candidate {
+                            item: match &val.expr {
                                 MonoType::Fun(function) => {
                                     lsp::CompletionItem {
                                         label: key.to_string(),
@@ -1212,6 +2626,7 @@ impl LanguageServer for LspServer {
                                         insert_text_format: Some(lsp::InsertTextFormat::SNIPPET),
                                         kind: Some(lsp::CompletionItemKind::FUNCTION),
                                         sort_text: Some(key.to_string()),
+                                        data: completion::CompletionItemData::member("universe", key),
                                         ..lsp::CompletionItem::default()
                                     }
                                 }
@@ -1241,52 +2656,107 @@ impl LanguageServer for LspServer {
                                     }
                                 }
                                 _ => unreachable!("Previous filter on expression value failed. Got: {}", val.expr)
-                            }
+                            },
+                            source: completion::CompletionSource::Prelude,
+                            typ: Some(val.expr.clone()),
                         }).collect();
 
-                            vec![
+                            let package_function_completions =
+                                completion::complete_package_functions(
+                                    &sem_pkg,
+                                    &identifier.name,
+                                );
+
+                            let mut local_completions =
+                                completion::complete_bindings_in_scope(
+                                    &sem_pkg,
+                                    params.text_document_position.position,
+                                );
+                            let docs = self.doc_comments(
+                                &params.text_document_position.text_document.uri,
+                            );
+                            for candidate in local_completions.iter_mut() {
+                                if let Some(doc) = docs.get(&candidate.item.label) {
+                                    candidate.item.documentation = Some(
+                                        self.completion_documentation(doc.markdown()),
+                                    );
+                                }
+                            }
+
+                            let mut candidates: Vec<completion::Candidate> = vec![
+                                local_completions,
                                 stdlib_completions,
                                 builtin_completions,
+                                package_function_completions,
+                                snippet_candidates,
                             ]
                             .into_iter()
                             .flatten()
-                            .collect()
+                            .collect();
+                            completion::retain_for_call_position(
+                                &mut candidates,
+                                &completion::call_position(
+                                    &sem_pkg, &walk_node,
+                                ),
+                            );
+
+                            completion::rank_candidates(
+                                candidates,
+                                &identifier.name,
+                                completion::expected_argument_type(
+                                    &sem_pkg,
+                                    params.text_document_position.position,
+                                )
+                                .as_ref(),
+                            )
                         }
                     }
-                }
-                AstNode::MemberExpr(member) => {
-                    match self
-                        .complete_member_expression(&sem_pkg, member)
-                    {
-                        Some(items) => items,
-                        None => return Ok(None),
                     }
-                }
-                AstNode::ObjectExpr(_) => {
-                    let parent = walk_node
-                        .parent
-                        .as_ref()
-                        .map(|parent| &parent.node);
-                    match parent {
-                        Some(AstNode::CallExpr(call)) => {
-                            completion::complete_call_expr(
-                                &params, &sem_pkg, call,
-                            )
+                    AstNode::InterpolatedPart(_) => {
+                        completion::complete_identifiers_in_scope(
+                            &sem_pkg,
+                            params.text_document_position.position,
+                        )
+                    }
+                    AstNode::MemberExpr(member) => {
+                        match self.complete_member_expression(
+                            &sem_pkg,
+                            &contents,
+                            &walk_node,
+                            member,
+                            &completion::call_position(
+                                &sem_pkg, &walk_node,
+                            ),
+                        ) {
+                            Some(items) => items,
+                            None => return Ok(None),
                         }
-                        Some(_) | None => return Ok(None),
                     }
-                }
-                AstNode::StringLit(_) => {
-                    let parent = walk_node
-                        .parent
-                        .as_ref()
-                        .map(|parent| &parent.node);
-                    match parent {
-                        Some(AstNode::ImportDeclaration(_)) => {
-                            let imports =
-                                completion::get_imports(&sem_pkg);
+                    AstNode::ObjectExpr(_) => {
+                        let parent = walk_node
+                            .parent
+                            .as_ref()
+                            .map(|parent| &parent.node);
+                        match parent {
+                            Some(AstNode::CallExpr(call)) => {
+                                completion::complete_call_expr(
+                                    &params, &sem_pkg, call,
+                                )
+                            }
+                            Some(_) | None => return Ok(None),
+                        }
+                    }
+                    AstNode::StringLit(_) => {
+                        let parent = walk_node
+                            .parent
+                            .as_ref()
+                            .map(|parent| &parent.node);
+                        match parent {
+                            Some(AstNode::ImportDeclaration(_)) => {
+                                let imports =
+                                    completion::get_imports(&sem_pkg);
 
-                            lang::STDLIB.packages().filter(|package| {
+                                lang::STDLIB.packages().filter(|package| {
                                 !&imports.iter().any(|x| x.path == package.path)
                             }).map(|package| {
                                 let trigger = if let Some(context) = & params.context {
@@ -1307,156 +2777,186 @@ impl LanguageServer for LspServer {
                                     ..lsp::CompletionItem::default()
                                 }
                             }).collect()
+                            }
+                            Some(_) | None => {
+                                completion::complete_buckets(
+                                    &sem_pkg,
+                                    params
+                                        .text_document_position
+                                        .position,
+                                    &self.buckets(),
+                                )
+                            }
                         }
-                        // This is where bucket/measurement/field/tag completion will occur.
-                        Some(_) | None => return Ok(None),
                     }
+                    AstNode::File(_) => completion::rank_candidates(
+                        snippet_candidates,
+                        "",
+                        None,
+                    ),
+                    _ => return Ok(None),
                 }
-                _ => return Ok(None),
-            },
-            None => return Ok(None),
+            }
+            None => completion::rank_candidates(
+                snippet_candidates,
+                "",
+                None,
+            ),
         };
         if items.is_empty() {
             Ok(None)
         } else {
             Ok(Some(lsp::CompletionResponse::List(
                 lsp::CompletionList {
-                    // XXX: rockstar (5 Jul 2022) - This should probably always be incomplete, so
-                    // we don't leave off to the client to try and figure out what completions to use.
-                    is_incomplete: false,
+                    // Items are filtered and ranked by what has been typed so far, so the
+                    // client needs to ask again as more is typed.
+                    is_incomplete: true,
                     items,
                 },
             )))
         }
     }
 
-    async fn semantic_tokens_full(
+    async fn completion_resolve(
         &self,
-        params: lsp::SemanticTokensParams,
-    ) -> RpcResult<Option<lsp::SemanticTokensResult>> {
-        let pkg = match self
-            .store
-            .get_ast_package(&params.text_document.uri)
+        mut item: lsp::CompletionItem,
+    ) -> RpcResult<lsp::CompletionItem> {
+        let documentation = match item
+            .data
+            .as_ref()
+            .and_then(completion::resolve_documentation)
         {
-            Ok(pkg) => pkg,
-            Err(err) => return Err(err.into()),
+            Some(documentation) => documentation,
+            None => return Ok(item),
         };
+        item.documentation =
+            Some(self.completion_documentation(documentation));
+        Ok(item)
+    }
 
-        let visitor = crate::walk_ast_package!(
-            crate::visitors::ast::SemanticTokenVisitor::default(),
-            pkg
-        );
+    async fn semantic_tokens_full(
+        &self,
+        params: lsp::SemanticTokensParams,
+    ) -> RpcResult<Option<lsp::SemanticTokensResult>> {
+        let key = params.text_document.uri;
+        let tokens = self.semantic_tokens(&key)?;
         Ok(Some(lsp::SemanticTokensResult::Tokens(
-            lsp::SemanticTokens {
-                result_id: None,
-                data: visitor.tokens,
-            },
+            self.cache_semantic_tokens(
+                &key,
+                crate::lsp::encode_semantic_tokens(&tokens),
+            ),
         )))
     }
 
-    async fn code_action(
+    async fn semantic_tokens_full_delta(
         &self,
-        params: lsp::CodeActionParams,
-    ) -> RpcResult<Option<lsp::CodeActionResponse>> {
-        // Our code actions should all be connected with a diagnostic. The
-        // client user experience can vary when not directly connected to
-        // a diagnostic, which is sorta the client's fault, but we also
-        // don't have a need for trying to support any other flows.
-        if params.context.diagnostics.is_empty() {
-            return Ok(None);
-        }
-
-        let errors = match self
-            .store
-            .get_package_errors(&params.text_document.uri)
-        {
-            Some(errors) => errors,
-            None => return Ok(None),
+        params: lsp::SemanticTokensDeltaParams,
+    ) -> RpcResult<Option<lsp::SemanticTokensFullDeltaResult>> {
+        let key = params.text_document.uri;
+        let tokens = self.semantic_tokens(&key)?;
+        let previous = match self.state.lock() {
+            Ok(state) => state
+                .get_semantic_tokens(&key)
+                .filter(|previous| {
+                    previous.result_id.as_ref()
+                        == Some(&params.previous_result_id)
+                })
+                .map(|previous| previous.data.clone()),
+            Err(err) => {
+                log::error!("{}", err);
+                None
+            }
         };
+        let current = self.cache_semantic_tokens(
+            &key,
+            crate::lsp::encode_semantic_tokens(&tokens),
+        );
+        Ok(Some(match previous {
+            Some(previous) => {
+                lsp::SemanticTokensFullDeltaResult::TokensDelta(
+                    lsp::SemanticTokensDelta {
+                        edits: crate::lsp::semantic_tokens_edits(
+                            &previous,
+                            &current.data,
+                        ),
+                        result_id: current.result_id,
+                    },
+                )
+            }
+            // The client has tokens that are no longer cached, so it gets
+            // all of them.
+            None => {
+                lsp::SemanticTokensFullDeltaResult::Tokens(current)
+            }
+        }))
+    }
 
-        let relevant: Vec<&flux::semantic::Error> = errors
-            .diagnostics
-            .errors
-            .iter()
-            .filter(|error| {
-                crate::lsp::ranges_overlap(
+    async fn semantic_tokens_range(
+        &self,
+        params: lsp::SemanticTokensRangeParams,
+    ) -> RpcResult<Option<lsp::SemanticTokensRangeResult>> {
+        let tokens: Vec<lsp::SemanticToken> = self
+            .semantic_tokens(&params.text_document.uri)?
+            .into_iter()
+            .filter(|token| {
+                crate::lsp::position_in_range(
+                    &lsp::Position::new(
+                        token.delta_line,
+                        token.delta_start,
+                    ),
                     &params.range,
-                    &error.location.clone().into(),
                 )
             })
             .collect();
-        if relevant.is_empty() {
-            return Ok(None);
-        }
-
-        let pkg = match self
-            .store
-            .get_semantic_package(&params.text_document.uri)
-        {
-            Ok(pkg) => pkg,
-            Err(err) => unreachable!("{:?}", err),
-        };
-
-        let visitor = crate::walk_semantic_package!(
-            semantic::PackageNodeFinderVisitor::default(),
-            pkg
-        );
-        let import_position = match visitor.location {
-            Some(location) => lsp::Position {
-                line: location.start.line + 1,
-                character: 0,
+        Ok(Some(lsp::SemanticTokensRangeResult::Tokens(
+            lsp::SemanticTokens {
+                result_id: None,
+                data: crate::lsp::encode_semantic_tokens(&tokens),
             },
-            None => lsp::Position::default(),
-        };
-
-        let actions: Vec<lsp::CodeActionOrCommand> = relevant.iter().map(|error| {
-            if let ErrorKind::Inference(kind) = &error.error {
-                match kind {
-                    SemanticNodeErrorKind::UndefinedIdentifier(identifier) => {
-                        // When encountering undefined identifiers, check to see if they match any corresponding
-                        // packages available for import.
-                        let potential_imports: Vec<lang::Package> = lang::STDLIB.fuzzy_matches(identifier).collect();
-                        if potential_imports.is_empty() {
-                            return None;
-                        }
+        )))
+    }
 
-                        let inner_actions: Vec<lsp::CodeActionOrCommand> = potential_imports.iter().map(|package| {
-                            lsp::CodeAction {
-                                title: format!("Import `{}`", package.path),
-                                kind: Some(lsp::CodeActionKind::QUICKFIX),
-                                diagnostics: None,
-                                edit: Some(lsp::WorkspaceEdit {
-                                    changes: Some(HashMap::from([
-                                        (params.text_document.uri.clone(), vec![
-                                            lsp::TextEdit {
-                                                range: lsp::Range {
-                                                    start: import_position,
-                                                    end: import_position,
-                                                },
-                                                new_text: format!("import \"{}\"\n", package.path),
-                                            }
-                                        ])
-                                    ])),
-                                    document_changes: None,
-                                    change_annotations: None,
-                                }),
-                                command: None,
-                                is_preferred: Some(true),
-                                disabled: None,
-                                data: None,
-                            }.into()
-                        }).collect();
-                        return Some(inner_actions);
-                    },
-                    _ => return None,
+    async fn code_action(
+        &self,
+        params: lsp::CodeActionParams,
+    ) -> RpcResult<Option<lsp::CodeActionResponse>> {
+        let mut actions = self.quickfix_actions(&params)?;
+        actions.extend(self.bucket_quickfix_actions(&params));
+        actions.extend(self.column_quickfix_actions(&params));
+        actions.extend(self.refactor_actions(&params));
+
+        // Clients can ask for a subset of the supported kinds. A kind matches
+        // when it is the requested kind or a sub-kind of it, e.g. `refactor.inline`
+        // matches a request for `refactor`.
+        if let Some(only) = &params.context.only {
+            actions.retain(|action| match action {
+                lsp::CodeActionOrCommand::CodeAction(action) => {
+                    action.kind.as_ref().map_or(false, |kind| {
+                        only.iter().any(|requested| {
+                            kind == requested
+                                || kind.as_str().starts_with(
+                                    &format!(
+                                        "{}.",
+                                        requested.as_str()
+                                    ),
+                                )
+                        })
+                    })
                 }
-            }
-            None
-        }).filter(|action| action.is_some()).flat_map(|action| {
-            action.expect("Previous .filter() call failed.")
-        }).collect();
+                lsp::CodeActionOrCommand::Command(_) => false,
+            });
+        }
 
-        return Ok(Some(actions));
+        // Requests made for diagnostics get a list, even an empty one.
+        Ok(
+            if actions.is_empty()
+                && params.context.diagnostics.is_empty()
+            {
+                None
+            } else {
+                Some(actions)
+            },
+        )
     }
 
     async fn execute_command(
@@ -1985,6 +3485,36 @@ impl LanguageServer for LspServer {
             }
         }
     }
+
+    /// Requests that are newer than `lsp-types`.
+    async fn request_else(
+        &self,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> RpcResult<Option<serde_json::Value>> {
+        match method {
+            inlay_hints::InlayHintRequest::METHOD => {
+                let params = params
+                    .map(serde_json::from_value)
+                    .transpose()
+                    .map_err(|err| {
+                        lspower::jsonrpc::Error::invalid_params(
+                            err.to_string(),
+                        )
+                    })?
+                    .ok_or_else(|| {
+                        lspower::jsonrpc::Error::invalid_params(
+                            "missing params",
+                        )
+                    })?;
+                let hints = self.inlay_hint(params).await?;
+                serde_json::to_value(hints).map(Some).map_err(|err| {
+                    LspError::InternalError(err.to_string()).into()
+                })
+            }
+            _ => Err(lspower::jsonrpc::Error::method_not_found()),
+        }
+    }
 }
 
 // `MonoType`'s extracted from a `Node` in a semantic graph do not contain the constraints directly
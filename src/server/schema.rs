@@ -0,0 +1,439 @@
+/// Schema information for completion of measurements, fields and tags.
+///
+/// The server doesn't talk to InfluxDB itself. Instead, the schema of a bucket comes
+/// from a `SchemaProvider`, either a schema file in the workspace or the client.
+use std::collections::{BTreeMap, HashMap};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use lspower::{lsp, Client};
+use serde::{Deserialize, Serialize};
+
+/// The name of the schema file looked for in each workspace folder.
+pub const SCHEMA_FILE: &str = ".flux-schema.json";
+
+#[derive(
+    Clone, Debug, Default, Deserialize, PartialEq, Serialize,
+)]
+pub struct MeasurementSchema {
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Tag keys, with the known values of each tag.
+    #[serde(default)]
+    pub tags: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(
+    Clone, Debug, Default, Deserialize, PartialEq, Serialize,
+)]
+pub struct BucketSchema {
+    #[serde(default)]
+    pub measurements: BTreeMap<String, MeasurementSchema>,
+}
+
+impl BucketSchema {
+    /// The schema of a single measurement, or of every measurement when the
+    /// measurement isn't known.
+    fn measurement_schemas<'a>(
+        &'a self,
+        measurement: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a MeasurementSchema> {
+        self.measurements
+            .iter()
+            .filter(move |(name, _)| {
+                measurement.map_or(true, |measurement| {
+                    name.as_str() == measurement
+                })
+            })
+            .map(|(_, schema)| schema)
+    }
+
+    pub fn measurements(&self) -> Vec<String> {
+        self.measurements.keys().cloned().collect()
+    }
+
+    pub fn fields(&self, measurement: Option<&str>) -> Vec<String> {
+        let mut fields: Vec<String> = self
+            .measurement_schemas(measurement)
+            .flat_map(|schema| schema.fields.iter().cloned())
+            .collect();
+        fields.sort();
+        fields.dedup();
+        fields
+    }
+
+    pub fn tag_keys(&self, measurement: Option<&str>) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .measurement_schemas(measurement)
+            .flat_map(|schema| schema.tags.keys().cloned())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        keys
+    }
+
+    pub fn tag_values(
+        &self,
+        measurement: Option<&str>,
+        tag: &str,
+    ) -> Vec<String> {
+        let mut values: Vec<String> = self
+            .measurement_schemas(measurement)
+            .filter_map(|schema| schema.tags.get(tag))
+            .flat_map(|values| values.iter().cloned())
+            .collect();
+        values.sort();
+        values.dedup();
+        values
+    }
+}
+
+/// A source of bucket schemas.
+#[async_trait::async_trait]
+pub trait SchemaProvider: Send + Sync {
+    /// The schema of a bucket, or None if it isn't available.
+    async fn schema(&self, bucket: &str) -> Option<BucketSchema>;
+}
+
+/// The format of a workspace schema file, e.g.
+///
+/// ```json
+/// {"buckets": {"telegraf": {"measurements": {"cpu": {"fields": ["usage_user"], "tags": {"host": ["server01"]}}}}}}
+/// ```
+#[derive(Default, Deserialize)]
+struct SchemaFile {
+    #[serde(default)]
+    buckets: HashMap<String, BucketSchema>,
+}
+
+/// Read schemas from the schema file in each workspace folder.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FileSchemaProvider {
+    paths: Vec<PathBuf>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileSchemaProvider {
+    /// Create a provider for the workspace folders that have a schema file,
+    /// or None if none of them do.
+    pub fn new(folders: &[lsp::Url]) -> Option<Self> {
+        let paths: Vec<PathBuf> = folders
+            .iter()
+            .filter_map(|folder| folder.to_file_path().ok())
+            .map(|folder| folder.join(SCHEMA_FILE))
+            .filter(|path| path.is_file())
+            .collect();
+        if paths.is_empty() {
+            None
+        } else {
+            Some(Self { paths })
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait::async_trait]
+impl SchemaProvider for FileSchemaProvider {
+    async fn schema(&self, bucket: &str) -> Option<BucketSchema> {
+        self.paths.iter().find_map(|path| {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    log::error!("{}: {}", path.display(), err);
+                    return None;
+                }
+            };
+            match serde_json::from_str::<SchemaFile>(&contents) {
+                Ok(mut file) => file.buckets.remove(bucket),
+                Err(err) => {
+                    log::error!("{}: {}", path.display(), err);
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// The provider for the schema files of the workspace folders, if any of them
+/// has one.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn file_provider(
+    folders: &[lsp::Url],
+) -> Option<Arc<dyn SchemaProvider>> {
+    FileSchemaProvider::new(folders)
+        .map(|provider| Arc::new(provider) as Arc<dyn SchemaProvider>)
+}
+
+/// Files can't be read in wasm, where schemas only come from the client.
+#[cfg(target_arch = "wasm32")]
+pub fn file_provider(
+    _folders: &[lsp::Url],
+) -> Option<Arc<dyn SchemaProvider>> {
+    None
+}
+
+/// Return true if a url is of a schema file, whose changes make cached schemas
+/// stale.
+pub fn is_schema_file(uri: &lsp::Url) -> bool {
+    uri.path_segments()
+        .and_then(|segments| segments.last())
+        .map_or(false, |name| name == SCHEMA_FILE)
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SchemaParams {
+    pub bucket: String,
+}
+
+/// Return true if the client said it answers schema requests, with the
+/// `schemaRequests` initialization option.
+pub fn client_answers_requests(
+    initialization_options: Option<&serde_json::Value>,
+) -> bool {
+    initialization_options
+        .and_then(|options| options.get("schemaRequests"))
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+/// A request to the client for the schema of a bucket. The client answers with a
+/// `BucketSchema`, or null if the bucket is unknown.
+pub enum SchemaRequest {}
+
+impl lsp::request::Request for SchemaRequest {
+    type Params = SchemaParams;
+    type Result = Option<BucketSchema>;
+    const METHOD: &'static str = "flux/schema";
+}
+
+/// Ask the client for schemas.
+pub struct ClientSchemaProvider {
+    client: Client,
+}
+
+impl ClientSchemaProvider {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait::async_trait]
+impl SchemaProvider for ClientSchemaProvider {
+    async fn schema(&self, bucket: &str) -> Option<BucketSchema> {
+        match self
+            .client
+            .send_custom_request::<SchemaRequest>(SchemaParams {
+                bucket: bucket.into(),
+            })
+            .await
+        {
+            Ok(schema) => schema,
+            Err(err) => {
+                log::error!("{}", err);
+                None
+            }
+        }
+    }
+}
+
+/// The schema provider, and a cache of the schemas it has provided.
+#[derive(Default)]
+pub struct SchemaCache {
+    provider: RwLock<Option<Arc<dyn SchemaProvider>>>,
+    /// The schema of each requested bucket, or None if it wasn't available.
+    buckets: Mutex<HashMap<String, Option<Arc<BucketSchema>>>>,
+}
+
+impl SchemaCache {
+    /// Replace the schema provider, dropping any cached schemas.
+    pub fn set_provider(
+        &self,
+        provider: Option<Arc<dyn SchemaProvider>>,
+    ) {
+        match self.provider.write() {
+            Ok(mut current) => *current = provider,
+            Err(err) => log::error!("{}", err),
+        }
+        self.invalidate();
+    }
+
+    /// Drop all cached schemas.
+    pub fn invalidate(&self) {
+        match self.buckets.lock() {
+            Ok(mut buckets) => buckets.clear(),
+            Err(err) => log::error!("{}", err),
+        }
+    }
+
+    /// The schema of a bucket. Schemas are only requested from the provider
+    /// once per bucket, including those that aren't available, until the
+    /// cache is invalidated.
+    pub async fn get(
+        &self,
+        bucket: &str,
+    ) -> Option<Arc<BucketSchema>> {
+        match self.buckets.lock() {
+            Ok(buckets) => {
+                if let Some(schema) = buckets.get(bucket) {
+                    return schema.clone();
+                }
+            }
+            Err(err) => log::error!("{}", err),
+        }
+
+        let provider = match self.provider.read() {
+            Ok(provider) => provider.clone()?,
+            Err(err) => {
+                log::error!("{}", err);
+                return None;
+            }
+        };
+        let schema = provider.schema(bucket).await.map(Arc::new);
+        match self.buckets.lock() {
+            Ok(mut buckets) => {
+                buckets.insert(bucket.into(), schema.clone());
+            }
+            Err(err) => log::error!("{}", err),
+        }
+        schema
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_std::test;
+
+    use super::*;
+
+    fn schema() -> BucketSchema {
+        serde_json::from_value(serde_json::json!({
+            "measurements": {
+                "cpu": {
+                    "fields": ["usage_user", "usage_system"],
+                    "tags": {"host": ["server01", "server02"], "cpu": ["cpu0"]},
+                },
+                "mem": {
+                    "fields": ["used", "usage_user"],
+                    "tags": {"host": ["server03"]},
+                },
+            }
+        }))
+        .unwrap()
+    }
+
+    #[derive(Default)]
+    struct CountingSchemaProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl SchemaProvider for CountingSchemaProvider {
+        async fn schema(&self, bucket: &str) -> Option<BucketSchema> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if bucket == "telegraf" {
+                Some(schema())
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    async fn bucket_schema_queries() {
+        let schema = schema();
+
+        assert_eq!(vec!["cpu", "mem"], schema.measurements());
+        assert_eq!(
+            vec!["usage_system", "usage_user", "used"],
+            schema.fields(None)
+        );
+        assert_eq!(
+            vec!["usage_system", "usage_user"],
+            schema.fields(Some("cpu"))
+        );
+        assert_eq!(vec!["cpu", "host"], schema.tag_keys(None));
+        assert_eq!(vec!["host"], schema.tag_keys(Some("mem")));
+        assert_eq!(
+            vec!["server01", "server02", "server03"],
+            schema.tag_values(None, "host")
+        );
+        assert!(schema.tag_values(Some("cpu"), "region").is_empty());
+    }
+
+    #[test]
+    async fn schema_cache_requests_each_bucket_once() {
+        let provider = Arc::new(CountingSchemaProvider::default());
+        let cache = SchemaCache::default();
+        cache.set_provider(Some(provider.clone()));
+
+        assert!(cache.get("telegraf").await.is_some());
+        assert!(cache.get("telegraf").await.is_some());
+        assert_eq!(1, provider.calls.load(Ordering::SeqCst));
+
+        // Unknown buckets are cached too, so a provider that can't answer
+        // isn't asked again on every completion.
+        assert!(cache.get("unknown").await.is_none());
+        assert!(cache.get("unknown").await.is_none());
+        assert_eq!(2, provider.calls.load(Ordering::SeqCst));
+
+        cache.invalidate();
+        assert!(cache.get("unknown").await.is_none());
+        assert_eq!(3, provider.calls.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    async fn schema_cache_without_provider() {
+        let cache = SchemaCache::default();
+
+        assert!(cache.get("telegraf").await.is_none());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    async fn file_schema_provider() {
+        let folder =
+            std::env::temp_dir().join("flux-lsp-schema-test");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(
+            folder.join(SCHEMA_FILE),
+            serde_json::json!({"buckets": {"telegraf": schema()}})
+                .to_string(),
+        )
+        .unwrap();
+
+        let provider = FileSchemaProvider::new(&[
+            lsp::Url::from_directory_path(&folder).unwrap(),
+        ])
+        .unwrap();
+
+        assert_eq!(Some(schema()), provider.schema("telegraf").await);
+        assert_eq!(None, provider.schema("unknown").await);
+    }
+
+    #[test]
+    async fn client_schema_requests_option() {
+        assert!(client_answers_requests(Some(
+            &serde_json::json!({"schemaRequests": true})
+        )));
+        assert!(!client_answers_requests(Some(
+            &serde_json::json!({"snippets": {}})
+        )));
+        assert!(!client_answers_requests(None));
+    }
+
+    #[test]
+    async fn schema_file_urls() {
+        assert!(is_schema_file(
+            &lsp::Url::parse("file:///home/user/.flux-schema.json")
+                .unwrap()
+        ));
+        assert!(!is_schema_file(
+            &lsp::Url::parse("file:///home/user/schema.json")
+                .unwrap()
+        ));
+    }
+}
@@ -0,0 +1,256 @@
+/// Snippet templates for common query skeletons.
+///
+/// The server has built-in templates, and teams can add their own, or replace
+/// the built-in ones, with a snippets file in the workspace or the `snippets`
+/// initialization option.
+use std::collections::BTreeMap;
+
+use lspower::lsp;
+use serde::{Deserialize, Serialize};
+
+use crate::completion::{self, Candidate, CompletionSource};
+
+/// The name of the snippets file looked for in each workspace folder.
+pub const SNIPPETS_FILE: &str = ".flux-snippets.json";
+
+/// The body of a snippet, in the snippet syntax of the LSP spec. Like VS Code
+/// snippets, a body can be written as a single string or a list of lines.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum SnippetBody {
+    Text(String),
+    Lines(Vec<String>),
+}
+
+impl SnippetBody {
+    fn text(&self) -> String {
+        match self {
+            SnippetBody::Text(text) => text.clone(),
+            SnippetBody::Lines(lines) => lines.join("\n"),
+        }
+    }
+}
+
+/// A snippet template, keyed by its name, e.g.
+///
+/// ```json
+/// {"Mean of a field": {"prefix": "mean", "body": ["from(bucket: \"${1:bucket}\")", "  |> mean()"], "description": "The mean of a field"}}
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Snippet {
+    /// The text the snippet is completed for.
+    pub prefix: String,
+    pub body: SnippetBody,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Packages the body uses. The ones that aren't imported yet are imported
+    /// when the snippet is accepted.
+    #[serde(default)]
+    pub imports: Vec<String>,
+}
+
+pub type Snippets = BTreeMap<String, Snippet>;
+
+/// The built-in templates, as (name, prefix, description, imports, body).
+const BUILTIN_SNIPPETS: &[(&str, &str, &str, &[&str], &str)] = &[
+    (
+        "Query",
+        "query",
+        "Query a bucket and aggregate it into windows",
+        &[],
+        r#"from(bucket: "${1:bucket}")
+  |> range(start: ${2:-1h})
+  |> filter(fn: (r) => r._measurement == "${3:measurement}")
+  |> aggregateWindow(every: ${4:1m}, fn: ${5:mean}, createEmpty: false)
+  |> yield(name: "${6:mean}")"#,
+    ),
+    (
+        "Downsampling task",
+        "downsample",
+        "A task that downsamples a bucket into another bucket",
+        &[],
+        r#"option task = {name: "${1:downsample}", every: ${2:1h}}
+
+from(bucket: "${3:bucket}")
+  |> range(start: -task.every)
+  |> filter(fn: (r) => r._measurement == "${4:measurement}")
+  |> aggregateWindow(every: ${5:5m}, fn: ${6:mean})
+  |> to(bucket: "${7:downsampled}")"#,
+    ),
+    (
+        "Deadman check",
+        "deadman",
+        "Find series that have stopped reporting",
+        &["experimental", "influxdata/influxdb/monitor"],
+        r#"from(bucket: "${1:bucket}")
+  |> range(start: -${2:10m})
+  |> filter(fn: (r) => r._measurement == "${3:measurement}")
+  |> monitor.deadman(t: experimental.subDuration(from: now(), d: ${4:5m}))
+  |> filter(fn: (r) => r.dead)"#,
+    ),
+    (
+        "Join",
+        "join",
+        "Join two streams of tables on their time",
+        &["join"],
+        r#"left = from(bucket: "${1:bucket}")
+  |> range(start: ${2:-1h})
+  |> filter(fn: (r) => r._measurement == "${3:left}")
+
+right = from(bucket: "${1:bucket}")
+  |> range(start: ${2:-1h})
+  |> filter(fn: (r) => r._measurement == "${4:right}")
+
+join.inner(
+  left: left,
+  right: right,
+  on: (l, r) => l._time == r._time,
+  as: (l, r) => ({l with ${5:right_value}: r._value}),
+)"#,
+    ),
+];
+
+fn builtin_snippets() -> Snippets {
+    BUILTIN_SNIPPETS
+        .iter()
+        .map(|(name, prefix, description, imports, body)| {
+            (
+                name.to_string(),
+                Snippet {
+                    prefix: prefix.to_string(),
+                    body: SnippetBody::Text(body.to_string()),
+                    description: Some(description.to_string()),
+                    imports: imports
+                        .iter()
+                        .map(|path| path.to_string())
+                        .collect(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Parse snippets keyed by name, logging rather than failing on a bad value.
+fn parse_snippets(
+    source: &str,
+    value: serde_json::Value,
+) -> Snippets {
+    match serde_json::from_value(value) {
+        Ok(snippets) => snippets,
+        Err(err) => {
+            log::error!("{}: {}", source, err);
+            Snippets::new()
+        }
+    }
+}
+
+/// Read the snippets file in each workspace folder.
+#[cfg(not(target_arch = "wasm32"))]
+fn load_files(folders: &[lsp::Url]) -> Snippets {
+    let mut snippets = Snippets::new();
+    for path in folders
+        .iter()
+        .filter_map(|folder| folder.to_file_path().ok())
+        .map(|folder| folder.join(SNIPPETS_FILE))
+        .filter(|path| path.is_file())
+    {
+        let source = path.display().to_string();
+        match std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                serde_json::from_str(&contents)
+                    .map_err(|err| err.to_string())
+            }) {
+            Ok(value) => {
+                snippets.extend(parse_snippets(&source, value))
+            }
+            Err(err) => log::error!("{}: {}", source, err),
+        }
+    }
+    snippets
+}
+
+/// There is no file system to read snippets files from in the browser.
+#[cfg(target_arch = "wasm32")]
+fn load_files(_folders: &[lsp::Url]) -> Snippets {
+    Snippets::new()
+}
+
+/// Load the user snippets, from the snippets file in each workspace folder and
+/// the `snippets` initialization option. The initialization option wins over
+/// the files when both define a snippet.
+pub fn load(
+    folders: &[lsp::Url],
+    initialization_options: Option<&serde_json::Value>,
+) -> Snippets {
+    let mut snippets = load_files(folders);
+    if let Some(value) = initialization_options
+        .and_then(|options| options.get("snippets"))
+    {
+        snippets.extend(parse_snippets(
+            "initializationOptions.snippets",
+            value.clone(),
+        ));
+    }
+    snippets
+}
+
+/// Complete the built-in snippets and the user snippets, which replace built-in
+/// snippets of the same name.
+pub(crate) fn snippet_candidates(
+    pkg: &flux::semantic::nodes::Package,
+    user_snippets: &Snippets,
+) -> Vec<Candidate> {
+    let mut snippets = builtin_snippets();
+    snippets.extend(user_snippets.clone());
+
+    let imports = completion::get_imports(pkg);
+    let position = completion::import_position(pkg);
+    snippets
+        .into_iter()
+        .map(|(name, snippet)| {
+            let new_text: String = snippet
+                .imports
+                .iter()
+                .filter(|path| {
+                    !imports
+                        .iter()
+                        .any(|import| &import.path == *path)
+                })
+                .map(|path| format!("import \"{}\"\n", path))
+                .collect();
+            let additional_text_edits = if new_text.is_empty() {
+                None
+            } else {
+                Some(vec![lsp::TextEdit {
+                    range: lsp::Range {
+                        start: position,
+                        end: position,
+                    },
+                    new_text,
+                }])
+            };
+            let body = snippet.body.text();
+            Candidate {
+                item: lsp::CompletionItem {
+                    label: snippet.prefix.clone(),
+                    additional_text_edits,
+                    detail: Some(snippet.description.unwrap_or(name)),
+                    documentation: Some(lsp::Documentation::String(
+                        body.clone(),
+                    )),
+                    filter_text: Some(snippet.prefix.clone()),
+                    insert_text: Some(body),
+                    insert_text_format: Some(
+                        lsp::InsertTextFormat::SNIPPET,
+                    ),
+                    kind: Some(lsp::CompletionItemKind::SNIPPET),
+                    sort_text: Some(snippet.prefix),
+                    ..lsp::CompletionItem::default()
+                },
+                source: CompletionSource::Snippet,
+                typ: None,
+            }
+        })
+        .collect()
+}
@@ -125,6 +125,19 @@ impl Store {
         }
     }
 
+    /// Get urls for all files in the store.
+    pub fn get_urls(&self) -> Vec<lsp::Url> {
+        match self.backend.read() {
+            Ok(store) => store
+                .values()
+                .flat_map(|files| {
+                    files.values().map(|(_contents, url)| url.clone())
+                })
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
     /// Get urls for all files in a specified file's package.
     pub fn get_package_urls(&self, url: &lsp::Url) -> Vec<lsp::Url> {
         let (key, _) = url_to_key_val(url);
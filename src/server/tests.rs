@@ -88,6 +88,106 @@ async fn test_initialized() {
     );
 }
 
+/// Initialize a server for a client with some capabilities, returning the
+/// methods the server registers with it once it's initialized.
+async fn registered_methods(
+    capabilities: serde_json::Value,
+) -> Vec<String> {
+    use futures::StreamExt;
+    use tower_service::Service;
+
+    let (service, mut messages) =
+        lspower::LspService::new(|client| {
+            LspServer::new(Some(client))
+        });
+    let service = Arc::new(Mutex::new(service));
+
+    // The client accepts registrations, and ignores other messages.
+    let methods: Arc<Mutex<Vec<String>>> = Arc::default();
+    let client_service = service.clone();
+    let client_methods = methods.clone();
+    async_std::task::spawn(async move {
+        while let Some(message) = messages.next().await {
+            let message = serde_json::to_value(&message).unwrap();
+            if message["method"] != "client/registerCapability" {
+                continue;
+            }
+            for registration in
+                message["params"]["registrations"].as_array().unwrap()
+            {
+                client_methods.lock().unwrap().push(
+                    registration["method"].as_str().unwrap().into(),
+                );
+            }
+            let response: lspower::jsonrpc::Incoming =
+                serde_json::from_value(json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "result": null,
+                }))
+                .unwrap();
+            let response =
+                client_service.lock().unwrap().call(response);
+            response.await.unwrap();
+        }
+    });
+
+    let call = |message: serde_json::Value| {
+        let message: lspower::jsonrpc::Incoming =
+            serde_json::from_value(message).unwrap();
+        service.lock().unwrap().call(message)
+    };
+    call(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {"capabilities": capabilities},
+    }))
+    .await
+    .unwrap();
+    call(json!({
+        "jsonrpc": "2.0",
+        "method": "initialized",
+        "params": {},
+    }))
+    .await
+    .unwrap();
+
+    let methods = methods.lock().unwrap().clone();
+    methods
+}
+
+/// Capabilities are only registered with clients that support registering
+/// them dynamically.
+#[test]
+async fn test_initialized_registrations() {
+    assert!(registered_methods(json!({})).await.is_empty());
+
+    assert_eq!(
+        vec!["workspace/didChangeWatchedFiles".to_string()],
+        registered_methods(json!({
+            "workspace": {
+                "didChangeWatchedFiles": {"dynamicRegistration": true},
+            },
+        }))
+        .await
+    );
+
+    assert_eq!(
+        vec![
+            "textDocument/inlayHint".to_string(),
+            "workspace/didChangeWatchedFiles".to_string(),
+        ],
+        registered_methods(json!({
+            "textDocument": {"hover": {"dynamicRegistration": true}},
+            "workspace": {
+                "didChangeWatchedFiles": {"dynamicRegistration": true},
+            },
+        }))
+        .await
+    );
+}
+
 #[test]
 async fn test_shutdown() {
     let server = create_server();
@@ -825,52 +925,40 @@ async fn test_document_symbol_not_opened() {
     assert!(result.is_err());
 }
 
+/// Flatten nested document symbols into (depth, name, kind), in order.
+fn flatten_document_symbols(
+    symbols: &[lsp::DocumentSymbol],
+    depth: usize,
+) -> Vec<(usize, String, lsp::SymbolKind)> {
+    symbols
+        .iter()
+        .flat_map(|symbol| {
+            std::iter::once((depth, symbol.name.clone(), symbol.kind))
+                .chain(flatten_document_symbols(
+                    symbol.children.as_deref().unwrap_or_default(),
+                    depth + 1,
+                ))
+        })
+        .collect()
+}
+
+fn document_symbol_params() -> lsp::DocumentSymbolParams {
+    lsp::DocumentSymbolParams {
+        text_document: lsp::TextDocumentIdentifier {
+            uri: lsp::Url::parse("file:///home/user/file.flux")
+                .unwrap(),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+    }
+}
+
 #[test]
 async fn test_document_symbol() {
-    let expected_symbol_names: Vec<String> = vec![
-        "strings",
-        "env",
-        "prod01-us-west-2",
-        "errorCounts",
-        "from",
-        "bucket",
-        "kube-infra/monthly",
-        "range",
-        "start",
-        "filter",
-        "fn",
-        "r._measurement",
-        "query_log",
-        "r.error",
-        "",
-        "r._field",
-        "responseSize",
-        "r.env",
-        "env",
-        "group",
-        "columns",
-        "[]",
-        "env",
-        "error",
-        "count",
-        "group",
-        "columns",
-        "[]",
-        "env",
-        "_stop",
-        "_start",
-        "filter",
-        "fn",
-        "strings.containsStr",
-        "v",
-        "r.error",
-        "substr",
-        "AppendMappedRecordWithNulls",
-    ]
-    .into_iter()
-    .map(|x| x.into())
-    .collect::<Vec<String>>();
-
     let fluxscript = r#"import "strings"
 env = "prod01-us-west-2"
 
@@ -889,35 +977,107 @@ errorCounts
     let server = create_server();
     open_file(&server, fluxscript.to_string(), None).await;
 
-    let params = lsp::DocumentSymbolParams {
-        text_document: lsp::TextDocumentIdentifier {
-            uri: lsp::Url::parse("file:///home/user/file.flux")
-                .unwrap(),
-        },
-        work_done_progress_params: lsp::WorkDoneProgressParams {
-            work_done_token: None,
-        },
-        partial_result_params: lsp::PartialResultParams {
-            partial_result_token: None,
-        },
-    };
-    let symbol_response =
-        server.document_symbol(params).await.unwrap().unwrap();
+    let symbol_response = server
+        .document_symbol(document_symbol_params())
+        .await
+        .unwrap()
+        .unwrap();
 
     match symbol_response {
-        lsp::DocumentSymbolResponse::Flat(symbols) => {
+        lsp::DocumentSymbolResponse::Nested(symbols) => {
+            let expected: Vec<(usize, String, lsp::SymbolKind)> =
+                vec![
+                    (0, "strings", lsp::SymbolKind::MODULE),
+                    (0, "env", lsp::SymbolKind::VARIABLE),
+                    (0, "errorCounts", lsp::SymbolKind::VARIABLE),
+                    (1, "from", lsp::SymbolKind::FUNCTION),
+                    (1, "range", lsp::SymbolKind::FUNCTION),
+                    (1, "filter", lsp::SymbolKind::FUNCTION),
+                    (1, "group", lsp::SymbolKind::FUNCTION),
+                    (1, "count", lsp::SymbolKind::FUNCTION),
+                    (1, "group", lsp::SymbolKind::FUNCTION),
+                    // A pipeline without a yield or a bucket is named after the
+                    // variable it starts from.
+                    (0, "errorCounts", lsp::SymbolKind::EVENT),
+                    (1, "filter", lsp::SymbolKind::FUNCTION),
+                ]
+                .into_iter()
+                .map(|(depth, name, kind)| {
+                    (depth, name.to_string(), kind)
+                })
+                .collect();
             assert_eq!(
-                expected_symbol_names,
-                symbols
-                    .iter()
-                    .map(|x| x.name.clone())
-                    .collect::<Vec<String>>()
+                expected,
+                flatten_document_symbols(&symbols, 0)
             );
         }
         _ => unreachable!(),
     }
 }
 
+/// Options, functions with their parameters and declarations, and pipelines
+/// named after their yield or bucket.
+#[test]
+async fn test_document_symbol_functions_and_pipelines() {
+    let fluxscript = r#"option now = () => 2022-01-01T00:00:00Z
+
+toF = (c, scale=1.0) => {
+    f = c * scale
+    return f
+}
+
+from(bucket: "b")
+    |> range(start: -1h)
+    |> yield(name: "temperatures")
+
+from(bucket: "other")
+    |> range(start: -1h)
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let symbols = match server
+        .document_symbol(document_symbol_params())
+        .await
+        .unwrap()
+        .unwrap()
+    {
+        lsp::DocumentSymbolResponse::Nested(symbols) => symbols,
+        _ => unreachable!(),
+    };
+
+    let expected: Vec<(usize, String, lsp::SymbolKind)> = vec![
+        (0, "now", lsp::SymbolKind::PROPERTY),
+        (0, "toF", lsp::SymbolKind::FUNCTION),
+        (1, "c", lsp::SymbolKind::VARIABLE),
+        (1, "scale", lsp::SymbolKind::VARIABLE),
+        (1, "f", lsp::SymbolKind::VARIABLE),
+        (0, "temperatures", lsp::SymbolKind::EVENT),
+        (1, "from", lsp::SymbolKind::FUNCTION),
+        (1, "range", lsp::SymbolKind::FUNCTION),
+        (1, "yield", lsp::SymbolKind::FUNCTION),
+        (0, "other", lsp::SymbolKind::EVENT),
+        (1, "from", lsp::SymbolKind::FUNCTION),
+        (1, "range", lsp::SymbolKind::FUNCTION),
+    ]
+    .into_iter()
+    .map(|(depth, name, kind)| (depth, name.to_string(), kind))
+    .collect();
+    assert_eq!(expected, flatten_document_symbols(&symbols, 0));
+
+    let parameter = &symbols[1].children.as_ref().unwrap()[0];
+    assert_eq!(Some("float".to_string()), parameter.detail);
+
+    // The range of a pipeline covers all of its stages.
+    assert_eq!(
+        lsp::Range {
+            start: lsp::Position::new(7, 0),
+            end: lsp::Position::new(9, 34),
+        },
+        symbols[2].range
+    );
+}
+
 #[test]
 async fn test_goto_definition_not_opened() {
     let server = create_server();
@@ -2000,6 +2160,7 @@ errorCounts
 
     let want: BTreeSet<&str> = vec![
         "buckets",
+        "cal",
         "cardinality",
         "chandeMomentumOscillator",
         "columns",
@@ -2011,6 +2172,7 @@ errorCounts
         "contrib/bonitoo-io/victorops",
         "contrib/chobbs/discord",
         "contrib/qxip/clickhouse",
+        "cool",
         "count",
         "cov",
         "covariance",
@@ -2445,7 +2607,7 @@ csv.from(
 
     expect![[r#"
         {
-          "isIncomplete": false,
+          "isIncomplete": true,
           "items": [
             {
               "label": "csv",
@@ -2568,6 +2730,54 @@ x = 1
     assert_eq!(expected, labels);
 }
 
+/// The arguments of a function of an aliased import are completed from the
+/// package it imports.
+#[test]
+async fn test_param_completion_aliased_import() {
+    let fluxscript = r#"import c "csv"
+
+c.from(
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: lsp::Position {
+                line: 3,
+                character: 0,
+            },
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: Some(lsp::CompletionContext {
+            trigger_kind: lsp::CompletionTriggerKind::INVOKED,
+            trigger_character: None,
+        }),
+    };
+
+    let result =
+        server.completion(params.clone()).await.unwrap().unwrap();
+
+    let items = match result {
+        lsp::CompletionResponse::List(l) => l.items,
+        _ => unreachable!(),
+    };
+
+    let labels: Vec<&str> =
+        items.iter().map(|item| item.label.as_str()).collect();
+
+    assert_eq!(vec!["csv", "file", "mode"], labels);
+}
+
 #[test]
 async fn test_options_completion() {
     let fluxscript = r#"import "strings"
@@ -2932,17 +3142,20 @@ async fn test_package_completion_when_it_is_not_imported() {
 
     expect_test::expect![[r#"
                     {
-                      "isIncomplete": false,
+                      "isIncomplete": true,
                       "items": [
                         {
                           "label": "sql",
                           "kind": 9,
                           "detail": "Package",
                           "documentation": "sql",
-                          "sortText": "sql",
+                          "sortText": "09821 sql",
                           "filterText": "sql",
                           "insertText": "sql",
-                          "insertTextFormat": 1
+                          "insertTextFormat": 1,
+                          "data": {
+                            "package": "sql"
+                          }
                         }
                       ]
                     }"#]]
@@ -2985,17 +3198,20 @@ sql"#;
 
     expect_test::expect![[r#"
             {
-              "isIncomplete": false,
+              "isIncomplete": true,
               "items": [
                 {
                   "label": "sql",
                   "kind": 9,
                   "detail": "Package",
                   "documentation": "sql",
-                  "sortText": "sql",
+                  "sortText": "09821 sql",
                   "filterText": "sql",
                   "insertText": "sql",
-                  "insertTextFormat": 1
+                  "insertTextFormat": 1,
+                  "data": {
+                    "package": "sql"
+                  }
                 }
               ]
             }"#]]
@@ -3043,7 +3259,7 @@ from(bucket: "bucket")
 
     expect_test::expect![[r#"
         {
-          "isIncomplete": false,
+          "isIncomplete": true,
           "items": [
             {
               "label": "encode",
@@ -3051,7 +3267,11 @@ from(bucket: "bucket")
               "detail": "(v:A) -> bytes",
               "sortText": "encode",
               "filterText": "encode",
-              "insertTextFormat": 2
+              "insertTextFormat": 2,
+              "data": {
+                "package": "json",
+                "member": "encode"
+              }
             }
           ]
         }"#]]
@@ -3099,27 +3319,33 @@ from(bucket: "bucket")
 
     expect_test::expect![[r#"
         {
-          "isIncomplete": false,
+          "isIncomplete": true,
           "items": [
             {
               "label": "experimental/json",
               "kind": 9,
               "detail": "Package",
               "documentation": "experimental/json",
-              "sortText": "experimental/json",
+              "sortText": "09796 experimental/json",
               "filterText": "json",
               "insertText": "experimental/json",
-              "insertTextFormat": 1
+              "insertTextFormat": 1,
+              "data": {
+                "package": "experimental/json"
+              }
             },
             {
               "label": "json",
               "kind": 9,
               "detail": "Package",
               "documentation": "json",
-              "sortText": "json",
+              "sortText": "09796 json",
               "filterText": "json",
               "insertText": "json",
-              "insertTextFormat": 1
+              "insertTextFormat": 1,
+              "data": {
+                "package": "json"
+              }
             }
           ]
         }"#]]
@@ -3127,29 +3353,67 @@ from(bucket: "bucket")
 }
 
 use crate::visitors::ast::{
-    SEMANTIC_TOKEN_KEYWORD, SEMANTIC_TOKEN_NUMBER,
-    SEMANTIC_TOKEN_STRING,
+    SEMANTIC_TOKEN_COMMENT, SEMANTIC_TOKEN_DURATION,
+    SEMANTIC_TOKEN_FUNCTION, SEMANTIC_TOKEN_KEYWORD,
+    SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+    SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY,
+    SEMANTIC_TOKEN_MODIFIER_DEPRECATED,
+    SEMANTIC_TOKEN_MODIFIER_READONLY, SEMANTIC_TOKEN_NAMESPACE,
+    SEMANTIC_TOKEN_NUMBER, SEMANTIC_TOKEN_OPERATOR,
+    SEMANTIC_TOKEN_PARAMETER, SEMANTIC_TOKEN_PROPERTY,
+    SEMANTIC_TOKEN_STRING, SEMANTIC_TOKEN_VARIABLE,
 };
 
-#[test]
-async fn test_semantic_tokens_full() {
-    let fluxscript = r#"package "my-package"
-import "csv"
+const SEMANTIC_TOKENS: &str = r#"import "strings"
 
-myVar = from(bucket: "my-bucket")
-    |> range(start: 30m)
+// Matches hosts.
+option prefix = "a"
 
-csv.from(file: "my.csv")
-    |> filter(fn: (row) => row.field == 0.9)
+f = (r) => r._value > 0.9
+from(bucket: "b")
+    |> range(start: -30m)
+    |> filter(fn: (r) => strings.hasPrefix(v: r.host, prefix: prefix) and f(r: r))
 "#;
+
+fn semantic_tokens_document() -> lsp::TextDocumentIdentifier {
+    lsp::TextDocumentIdentifier {
+        uri: lsp::Url::parse("file:///home/user/file.flux").unwrap(),
+    }
+}
+
+/// Decode the relative positions of semantic tokens, as (line, start, length,
+/// token type, modifiers).
+fn decode_semantic_tokens(
+    tokens: &[lsp::SemanticToken],
+) -> Vec<(u32, u32, u32, u32, u32)> {
+    let mut line = 0;
+    let mut start = 0;
+    tokens
+        .iter()
+        .map(|token| {
+            if token.delta_line > 0 {
+                start = 0;
+            }
+            line += token.delta_line;
+            start += token.delta_start;
+            (
+                line,
+                start,
+                token.length,
+                token.token_type,
+                token.token_modifiers_bitset,
+            )
+        })
+        .collect()
+}
+
+#[test]
+async fn test_semantic_tokens_full() {
     let server = create_server();
-    open_file(&server, fluxscript.to_string(), None).await;
+    open_file(&server, SEMANTIC_TOKENS.to_string(), None).await;
 
     let params = lsp::SemanticTokensParams {
-        text_document: lsp::TextDocumentIdentifier {
-            uri: lsp::Url::parse("file:///home/user/file.flux")
-                .unwrap(),
-        },
+        text_document: semantic_tokens_document(),
         work_done_progress_params: lsp::WorkDoneProgressParams {
             work_done_token: None,
         },
@@ -3159,155 +3423,351 @@ csv.from(file: "my.csv")
     };
 
     let result = server.semantic_tokens_full(params).await.unwrap();
-    assert!(result.is_some());
-
-    let token_result = result.unwrap();
-    if let lsp::SemanticTokensResult::Tokens(tokens) = token_result {
-        let expected = lsp::SemanticTokens {
-            result_id: None,
-            data: vec![
-                // package
-                lsp::SemanticToken {
-                    delta_line: 1,
-                    delta_start: 1,
-                    length: 7,
-                    token_type: SEMANTIC_TOKEN_KEYWORD,
-                    token_modifiers_bitset: 0,
-                },
-                // "my-package"
-                lsp::SemanticToken {
-                    delta_line: 1,
-                    delta_start: 9,
-                    length: 0,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                },
-                // import
-                lsp::SemanticToken {
-                    delta_line: 1,
-                    delta_start: 9,
-                    length: 12,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                },
-                // "csv"
-                lsp::SemanticToken {
-                    delta_line: 2,
-                    delta_start: 8,
-                    length: 5,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                },
-                // "my-bucket"
-                lsp::SemanticToken {
-                    delta_line: 4,
-                    delta_start: 22,
-                    length: 11,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                },
-                // 30m
-                lsp::SemanticToken {
-                    delta_line: 5,
-                    delta_start: 21,
-                    length: 3,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                },
-                // my.csv
-                lsp::SemanticToken {
-                    delta_line: 7,
-                    delta_start: 16,
-                    length: 8,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                },
-                // 0.9
-                lsp::SemanticToken {
-                    delta_line: 8,
-                    delta_start: 41,
-                    length: 3,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                },
-            ],
-        };
-        assert_eq!(expected, tokens)
-    } else {
-        panic!("Result was not a token result");
-    }
+    let tokens = match result {
+        Some(lsp::SemanticTokensResult::Tokens(tokens)) => tokens,
+        _ => panic!("Result was not a token result"),
+    };
+    assert!(tokens.result_id.is_some());
+
+    let declaration = SEMANTIC_TOKEN_MODIFIER_DECLARATION;
+    let default_library = SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY;
+    assert_eq!(
+        vec![
+            // import "strings"
+            (0, 0, 6, SEMANTIC_TOKEN_KEYWORD, 0),
+            (0, 7, 9, SEMANTIC_TOKEN_STRING, 0),
+            // // Matches hosts.
+            (2, 0, 17, SEMANTIC_TOKEN_COMMENT, 0),
+            // option prefix = "a"
+            (3, 0, 6, SEMANTIC_TOKEN_KEYWORD, 0),
+            (3, 7, 6, SEMANTIC_TOKEN_VARIABLE, declaration),
+            (3, 16, 3, SEMANTIC_TOKEN_STRING, 0),
+            // f = (r) => r._value > 0.9
+            (5, 0, 1, SEMANTIC_TOKEN_FUNCTION, declaration),
+            (5, 5, 1, SEMANTIC_TOKEN_PARAMETER, declaration),
+            (5, 8, 2, SEMANTIC_TOKEN_OPERATOR, 0),
+            (5, 11, 1, SEMANTIC_TOKEN_PARAMETER, 0),
+            (5, 13, 6, SEMANTIC_TOKEN_PROPERTY, 0),
+            (5, 20, 1, SEMANTIC_TOKEN_OPERATOR, 0),
+            (5, 22, 3, SEMANTIC_TOKEN_NUMBER, 0),
+            // from(bucket: "b")
+            (6, 0, 4, SEMANTIC_TOKEN_FUNCTION, default_library),
+            (6, 5, 6, SEMANTIC_TOKEN_PARAMETER, 0),
+            (6, 13, 3, SEMANTIC_TOKEN_STRING, 0),
+            // |> range(start: -30m)
+            (7, 4, 2, SEMANTIC_TOKEN_OPERATOR, 0),
+            (7, 7, 5, SEMANTIC_TOKEN_FUNCTION, default_library),
+            (7, 13, 5, SEMANTIC_TOKEN_PARAMETER, 0),
+            (7, 20, 1, SEMANTIC_TOKEN_OPERATOR, 0),
+            (7, 21, 3, SEMANTIC_TOKEN_DURATION, 0),
+            // |> filter(fn: (r) => strings.hasPrefix(v: r.host, prefix: prefix) and f(r: r))
+            (8, 4, 2, SEMANTIC_TOKEN_OPERATOR, 0),
+            (8, 7, 6, SEMANTIC_TOKEN_FUNCTION, default_library),
+            (8, 14, 2, SEMANTIC_TOKEN_PARAMETER, 0),
+            (8, 19, 1, SEMANTIC_TOKEN_PARAMETER, declaration),
+            (8, 22, 2, SEMANTIC_TOKEN_OPERATOR, 0),
+            (8, 25, 7, SEMANTIC_TOKEN_NAMESPACE, 0),
+            (8, 33, 9, SEMANTIC_TOKEN_FUNCTION, default_library),
+            (8, 43, 1, SEMANTIC_TOKEN_PARAMETER, 0),
+            (8, 46, 1, SEMANTIC_TOKEN_PARAMETER, 0),
+            (8, 48, 4, SEMANTIC_TOKEN_PROPERTY, 0),
+            (8, 54, 6, SEMANTIC_TOKEN_PARAMETER, 0),
+            (8, 62, 6, SEMANTIC_TOKEN_VARIABLE, 0),
+            (8, 70, 3, SEMANTIC_TOKEN_OPERATOR, 0),
+            (8, 74, 1, SEMANTIC_TOKEN_FUNCTION, 0),
+            (8, 76, 1, SEMANTIC_TOKEN_PARAMETER, 0),
+            (8, 79, 1, SEMANTIC_TOKEN_PARAMETER, 0),
+        ],
+        decode_semantic_tokens(&tokens.data)
+    );
 }
 
-// A code action for importing a package when an undefined identifier
-// is found.
+/// A range request only has the tokens that start in the range.
 #[test]
-async fn test_code_action_import_insertion() {
-    let fluxscript = r#"sql"#;
+async fn test_semantic_tokens_range() {
     let server = create_server();
-    open_file(&server, fluxscript.to_string(), None).await;
+    open_file(&server, SEMANTIC_TOKENS.to_string(), None).await;
 
-    let params = lsp::CodeActionParams {
-        text_document: lsp::TextDocumentIdentifier {
-            uri: lsp::Url::parse("file:///home/user/file.flux")
-                .unwrap(),
-        },
-        context: lsp::CodeActionContext {
-            diagnostics: vec![lsp::Diagnostic {
-                code: None,
-                code_description: None,
-                data: None,
-                related_information: None,
-                severity: Some(lsp::DiagnosticSeverity::ERROR),
-                source: Some("flux".into()),
-                tags: None,
-                message: "undefined identifier sql".into(),
-                range: lsp::Range {
-                    start: lsp::Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: lsp::Position {
-                        line: 0,
-                        character: 0,
-                    },
-                },
-            }],
-            only: None,
-        },
-        range: lsp::Range {
-            start: lsp::Position {
-                line: 0,
-                character: 2,
+    let result = server
+        .semantic_tokens_range(lsp::SemanticTokensRangeParams {
+            text_document: semantic_tokens_document(),
+            range: lsp::Range {
+                start: lsp::Position::new(6, 0),
+                end: lsp::Position::new(7, 0),
             },
-            end: lsp::Position {
-                line: 0,
-                character: 2,
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
             },
-        },
-        work_done_progress_params: lsp::WorkDoneProgressParams {
-            work_done_token: None,
-        },
-        partial_result_params: lsp::PartialResultParams {
-            partial_result_token: None,
-        },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap();
+    let tokens = match result {
+        Some(lsp::SemanticTokensRangeResult::Tokens(tokens)) => {
+            tokens
+        }
+        _ => panic!("Result was not a token result"),
     };
 
-    let result = server.code_action(params).await.unwrap();
+    // The first token is relative to the start of the document.
+    assert_eq!(
+        vec![
+            (
+                6,
+                0,
+                4,
+                SEMANTIC_TOKEN_FUNCTION,
+                SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY
+            ),
+            (6, 5, 6, SEMANTIC_TOKEN_PARAMETER, 0),
+            (6, 13, 3, SEMANTIC_TOKEN_STRING, 0),
+        ],
+        decode_semantic_tokens(&tokens.data)
+    );
+}
 
-    expect_test::expect![[r#"
-            [
-              {
-                "title": "Import `sql`",
-                "kind": "quickfix",
-                "edit": {
-                  "changes": {
-                    "file:///home/user/file.flux": [
-                      {
-                        "range": {
-                          "start": {
-                            "line": 0,
-                            "character": 0
-                          },
+/// A delta request has the edits from the tokens of the previous request.
+#[test]
+async fn test_semantic_tokens_full_delta() {
+    let server = create_server();
+    open_file(&server, SEMANTIC_TOKENS.to_string(), None).await;
+
+    let previous = match server
+        .semantic_tokens_full(lsp::SemanticTokensParams {
+            text_document: semantic_tokens_document(),
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap()
+    {
+        Some(lsp::SemanticTokensResult::Tokens(tokens)) => tokens,
+        _ => panic!("Result was not a token result"),
+    };
+
+    let changed = SEMANTIC_TOKENS.replace("0.9", "\"0.9\"");
+    server
+        .did_change(lsp::DidChangeTextDocumentParams {
+            text_document: lsp::VersionedTextDocumentIdentifier {
+                uri: semantic_tokens_document().uri,
+                version: 2,
+            },
+            content_changes: vec![
+                lsp::TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: changed,
+                },
+            ],
+        })
+        .await;
+
+    let delta_params =
+        |previous_result_id: String| lsp::SemanticTokensDeltaParams {
+            text_document: semantic_tokens_document(),
+            previous_result_id,
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        };
+    let delta = match server
+        .semantic_tokens_full_delta(delta_params(
+            previous.result_id.clone().unwrap(),
+        ))
+        .await
+        .unwrap()
+    {
+        Some(lsp::SemanticTokensFullDeltaResult::TokensDelta(
+            delta,
+        )) => delta,
+        _ => panic!("Result was not a delta result"),
+    };
+    assert_ne!(previous.result_id, delta.result_id);
+    // The number is now a string.
+    assert_eq!(
+        vec![lsp::SemanticTokensEdit {
+            start: 5 * 12,
+            delete_count: 5,
+            data: Some(vec![lsp::SemanticToken {
+                delta_line: 0,
+                delta_start: 2,
+                length: 5,
+                token_type: SEMANTIC_TOKEN_STRING,
+                token_modifiers_bitset: 0,
+            }]),
+        }],
+        delta.edits
+    );
+
+    // Tokens that aren't the previous ones are sent in full.
+    let result = server
+        .semantic_tokens_full_delta(delta_params("unknown".into()))
+        .await
+        .unwrap();
+    assert!(matches!(
+        result,
+        Some(lsp::SemanticTokensFullDeltaResult::Tokens(_))
+    ));
+}
+
+/// Variables declared in a function are only in its scope, so they don't change
+/// the tokens of the names they shadow after it.
+#[test]
+async fn test_semantic_tokens_function_scope() {
+    let fluxscript = r#"x = () => 1
+f = () => {
+    x = 2
+    return x
+}
+y = x()
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .semantic_tokens_full(lsp::SemanticTokensParams {
+            text_document: semantic_tokens_document(),
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap();
+    let tokens = match result {
+        Some(lsp::SemanticTokensResult::Tokens(tokens)) => tokens,
+        _ => panic!("Result was not a token result"),
+    };
+
+    let tokens = decode_semantic_tokens(&tokens.data);
+    assert!(tokens.contains(&(
+        3,
+        11,
+        1,
+        SEMANTIC_TOKEN_VARIABLE,
+        SEMANTIC_TOKEN_MODIFIER_READONLY
+    )));
+    assert!(tokens.contains(&(5, 4, 1, SEMANTIC_TOKEN_FUNCTION, 0)));
+}
+
+/// Deprecated stdlib functions have the deprecated modifier.
+#[test]
+async fn test_semantic_tokens_deprecated() {
+    let fluxscript = r#"import "experimental"
+
+experimental.subDuration(d: 1h, from: now())
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .semantic_tokens_full(lsp::SemanticTokensParams {
+            text_document: semantic_tokens_document(),
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap();
+    let tokens = match result {
+        Some(lsp::SemanticTokensResult::Tokens(tokens)) => tokens,
+        _ => panic!("Result was not a token result"),
+    };
+
+    assert!(decode_semantic_tokens(&tokens.data).contains(&(
+        2,
+        13,
+        11,
+        SEMANTIC_TOKEN_FUNCTION,
+        SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY
+            | SEMANTIC_TOKEN_MODIFIER_DEPRECATED
+    )));
+}
+
+// A code action for importing a package when an undefined identifier
+// is found.
+#[test]
+async fn test_code_action_import_insertion() {
+    let fluxscript = r#"sql"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CodeActionParams {
+        text_document: lsp::TextDocumentIdentifier {
+            uri: lsp::Url::parse("file:///home/user/file.flux")
+                .unwrap(),
+        },
+        context: lsp::CodeActionContext {
+            diagnostics: vec![lsp::Diagnostic {
+                code: None,
+                code_description: None,
+                data: None,
+                related_information: None,
+                severity: Some(lsp::DiagnosticSeverity::ERROR),
+                source: Some("flux".into()),
+                tags: None,
+                message: "undefined identifier sql".into(),
+                range: lsp::Range {
+                    start: lsp::Position {
+                        line: 0,
+                        character: 0,
+                    },
+                    end: lsp::Position {
+                        line: 0,
+                        character: 0,
+                    },
+                },
+            }],
+            only: None,
+        },
+        range: lsp::Range {
+            start: lsp::Position {
+                line: 0,
+                character: 2,
+            },
+            end: lsp::Position {
+                line: 0,
+                character: 2,
+            },
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+    };
+
+    let result = server.code_action(params).await.unwrap();
+
+    expect_test::expect![[r#"
+            [
+              {
+                "title": "Import `sql`",
+                "kind": "quickfix",
+                "edit": {
+                  "changes": {
+                    "file:///home/user/file.flux": [
+                      {
+                        "range": {
+                          "start": {
+                            "line": 0,
+                            "character": 0
+                          },
                           "end": {
                             "line": 0,
                             "character": 0
@@ -3882,3 +4342,3014 @@ async fn workspace_state_buckets() {
             .collect();
     assert_eq!(expected, buckets);
 }
+
+fn code_action_params(
+    position: lsp::Position,
+) -> lsp::CodeActionParams {
+    lsp::CodeActionParams {
+        text_document: lsp::TextDocumentIdentifier {
+            uri: lsp::Url::parse("file:///home/user/file.flux")
+                .unwrap(),
+        },
+        context: lsp::CodeActionContext {
+            diagnostics: vec![],
+            only: None,
+        },
+        range: lsp::Range {
+            start: position,
+            end: position,
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+    }
+}
+
+/// Apply the edits of a code action to `source`, returning the new source.
+fn apply_code_action(
+    source: &str,
+    action: &lsp::CodeActionOrCommand,
+) -> String {
+    let action = match action {
+        lsp::CodeActionOrCommand::CodeAction(action) => action,
+        lsp::CodeActionOrCommand::Command(command) => {
+            panic!("Expected a code action, got {:?}", command)
+        }
+    };
+    let mut edits = action
+        .edit
+        .as_ref()
+        .unwrap()
+        .changes
+        .as_ref()
+        .unwrap()
+        .values()
+        .flatten()
+        .cloned()
+        .collect::<Vec<lsp::TextEdit>>();
+    edits.sort_by(|a, b| b.range.start.cmp(&a.range.start));
+
+    let mut result = source.to_string();
+    for edit in edits {
+        let start = crate::lsp::offset_of(&result, &edit.range.start)
+            .unwrap();
+        let end =
+            crate::lsp::offset_of(&result, &edit.range.end).unwrap();
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
+#[test]
+async fn test_code_action_inline_variable() {
+    let fluxscript = r#"threshold = 10 * 2
+
+from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._value > threshold)
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(0, 0)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(1, result.len());
+    assert_eq!(
+        r#"
+from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._value > (10 * 2))
+"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+/// Inlining isn't offered when another binding of the same name would make
+/// the references ambiguous.
+#[test]
+async fn test_code_action_inline_variable_shadowed() {
+    let fluxscript = r#"x = 1
+f = (x) => x + 1
+y = x
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(0, 0)))
+        .await
+        .unwrap();
+
+    assert!(result.is_none());
+}
+
+/// A piped init is parenthesized when a member of it is read.
+#[test]
+async fn test_code_action_inline_variable_pipe_member() {
+    let fluxscript = r#"last = from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> findRecord(fn: (key) => true, idx: 0)
+value = last._value
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(0, 0)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(1, result.len());
+    assert_eq!(
+        r#"value = (from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> findRecord(fn: (key) => true, idx: 0))._value
+"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+/// Requests for diagnostics are answered with a list, even when there is
+/// nothing to offer.
+#[test]
+async fn test_code_action_diagnostics_empty() {
+    let fluxscript = r#"x = 1
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let mut params = code_action_params(lsp::Position::new(0, 4));
+    params.context.diagnostics = vec![lsp::Diagnostic {
+        range: lsp::Range::new(
+            lsp::Position::new(0, 4),
+            lsp::Position::new(0, 5),
+        ),
+        message: "unrelated".into(),
+        ..lsp::Diagnostic::default()
+    }];
+    let result = server.code_action(params).await.unwrap();
+
+    assert_eq!(Some(vec![]), result);
+}
+
+/// Imports are only offered for packages whose name contains the undefined
+/// identifier, not for every package it fuzzy matches.
+#[test]
+async fn test_code_action_import_no_fuzzy_match() {
+    let fluxscript = r#"strngs"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let mut params = code_action_params(lsp::Position::new(0, 2));
+    params.context.diagnostics = vec![lsp::Diagnostic {
+        range: lsp::Range::new(
+            lsp::Position::new(0, 0),
+            lsp::Position::new(0, 6),
+        ),
+        message: "undefined identifier strngs".into(),
+        ..lsp::Diagnostic::default()
+    }];
+    let result = server.code_action(params).await.unwrap().unwrap();
+
+    assert!(!result.iter().any(|action| match action {
+        lsp::CodeActionOrCommand::CodeAction(action) => {
+            action.title.starts_with("Import")
+        }
+        lsp::CodeActionOrCommand::Command(_) => false,
+    }));
+}
+
+#[test]
+async fn test_code_action_inline_function() {
+    let fluxscript = r#"double = (v) => v * 2
+
+x = double(v: 1 + 2)
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(2, 5)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(1, result.len());
+    assert_eq!(
+        r#"double = (v) => v * 2
+
+x = (1 + 2) * 2
+"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+#[test]
+async fn test_code_action_merge_filters() {
+    let fluxscript = r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu")
+    |> filter(fn: (r) => r._field == "usage_user" or r._field == "usage_system")
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(3, 10)))
+        .await
+        .unwrap()
+        .unwrap();
+    let action = result
+        .iter()
+        .find(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                action.title == "Merge with previous `filter`"
+            }
+            _ => false,
+        })
+        .unwrap();
+
+    assert_eq!(
+        r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu" and (r._field == "usage_user" or r._field == "usage_system"))
+"#,
+        apply_code_action(fluxscript, action)
+    );
+}
+
+#[test]
+async fn test_code_action_split_filter() {
+    let fluxscript = r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu" and r._field == "usage_user")
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(2, 10)))
+        .await
+        .unwrap()
+        .unwrap();
+    let action = result
+        .iter()
+        .find(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                action.title == "Split `filter` predicate"
+            }
+            _ => false,
+        })
+        .unwrap();
+
+    assert_eq!(
+        r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu")
+    |> filter(fn: (r) => r._field == "usage_user")
+"#,
+        apply_code_action(fluxscript, action)
+    );
+}
+
+#[test]
+async fn test_code_action_move_pipe_stage() {
+    let fluxscript = r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu")
+    |> limit(n: 10)
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(3, 9)))
+        .await
+        .unwrap()
+        .unwrap();
+    let titles: Vec<String> = result
+        .iter()
+        .filter_map(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                Some(action.title.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    // The last stage can't be moved down.
+    assert_eq!(
+        vec!["Move `limit` up in pipeline".to_string()],
+        titles
+    );
+
+    assert_eq!(
+        r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> limit(n: 10)
+    |> filter(fn: (r) => r._measurement == "cpu")
+"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+#[test]
+async fn test_code_action_concatenation_to_interpolation() {
+    let fluxscript = r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> map(fn: (r) => ({r with label: "host=" + r.host + ", value=" + string(v: r._value)}))
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(2, 52)))
+        .await
+        .unwrap()
+        .unwrap();
+    let action = result
+        .iter()
+        .find(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                action.title == "Convert to string interpolation"
+            }
+            _ => false,
+        })
+        .unwrap();
+
+    assert_eq!(
+        r#"from(bucket: "my-bucket")
+    |> range(start: -1h)
+    |> map(fn: (r) => ({r with label: "host=${r.host}, value=${r._value}"}))
+"#,
+        apply_code_action(fluxscript, action)
+    );
+}
+
+#[test]
+async fn test_code_action_interpolation_to_concatenation() {
+    let fluxscript = r#"count = 10
+label = "count=${string(v: count)}, unit=${"seconds"}"
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .code_action(code_action_params(lsp::Position::new(1, 12)))
+        .await
+        .unwrap()
+        .unwrap();
+    let action = result
+        .iter()
+        .find(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                action.title == "Convert to string concatenation"
+            }
+            _ => false,
+        })
+        .unwrap();
+
+    assert_eq!(
+        r#"count = 10
+label = "count=" + string(v: count) + ", unit=" + "seconds"
+"#,
+        apply_code_action(fluxscript, action)
+    );
+}
+
+#[test]
+async fn test_interpolation_completion() {
+    let fluxscript = r#"host = "localhost"
+port = 8086
+f = (scheme) => "${scheme}://${h}"
+                            // ^
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+
+    let result = server.completion(params).await.unwrap().unwrap();
+    let labels: Vec<String> = match result {
+        lsp::CompletionResponse::List(list) => {
+            list.items.into_iter().map(|item| item.label).collect()
+        }
+        lsp::CompletionResponse::Array(items) => {
+            items.into_iter().map(|item| item.label).collect()
+        }
+    };
+
+    assert_eq!(
+        vec![
+            "host".to_string(),
+            "port".to_string(),
+            "scheme".to_string()
+        ],
+        labels
+    );
+}
+
+#[test]
+async fn test_bucket_completion() {
+    let fluxscript = r#"from(bucket: "my")
+            // ^
+"#;
+    let server = create_server();
+    server.did_change_configuration(lsp::DidChangeConfigurationParams {
+        settings: json!({"settings": {"buckets": ["my-bucket", "your-bucket"]}})
+    }).await;
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+
+    let result = server.completion(params).await.unwrap().unwrap();
+
+    expect![[r#"
+        {
+          "isIncomplete": true,
+          "items": [
+            {
+              "label": "my-bucket",
+              "kind": 12,
+              "detail": "Bucket",
+              "sortText": "my-bucket",
+              "filterText": "my-bucket",
+              "textEdit": {
+                "range": {
+                  "start": {
+                    "line": 0,
+                    "character": 14
+                  },
+                  "end": {
+                    "line": 0,
+                    "character": 16
+                  }
+                },
+                "newText": "my-bucket"
+              }
+            },
+            {
+              "label": "your-bucket",
+              "kind": 12,
+              "detail": "Bucket",
+              "sortText": "your-bucket",
+              "filterText": "your-bucket",
+              "textEdit": {
+                "range": {
+                  "start": {
+                    "line": 0,
+                    "character": 14
+                  },
+                  "end": {
+                    "line": 0,
+                    "character": 16
+                  }
+                },
+                "newText": "your-bucket"
+              }
+            }
+          ]
+        }"#]]
+    .assert_eq(&serde_json::to_string_pretty(&result).unwrap());
+}
+
+#[test]
+async fn test_bucket_hover() {
+    let fluxscript = r#"from(bucket: "my-buckt")"#;
+    let server = create_server();
+    server
+        .did_change_configuration(lsp::DidChangeConfigurationParams {
+            settings: json!({"settings": {"buckets": ["my-bucket"]}}),
+        })
+        .await;
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::HoverParams {
+        text_document_position_params:
+            lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: lsp::Url::parse(
+                        "file:///home/user/file.flux",
+                    )
+                    .unwrap(),
+                },
+                position: lsp::Position::new(0, 16),
+            },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+    };
+
+    let result = server.hover(params).await.unwrap().unwrap();
+
+    assert_eq!(
+        lsp::HoverContents::Scalar(lsp::MarkedString::String(
+            "Bucket `my-buckt` is not a known bucket".into()
+        )),
+        result.contents
+    );
+}
+
+#[test]
+async fn test_code_action_unknown_bucket() {
+    let fluxscript = r#"from(bucket: "my-buckt")"#;
+    let server = create_server();
+    server
+        .did_change_configuration(lsp::DidChangeConfigurationParams {
+            settings: json!({"settings": {"buckets": ["my-bucket"]}}),
+        })
+        .await;
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let diagnostics = server
+        .compute_diagnostics(
+            &lsp::Url::parse("file:///home/user/file.flux").unwrap(),
+        )
+        .into_values()
+        .flatten()
+        .collect::<Vec<lsp::Diagnostic>>();
+    assert_eq!(1, diagnostics.len());
+
+    let mut params = code_action_params(lsp::Position::new(0, 16));
+    params.context.diagnostics = diagnostics;
+    let result = server.code_action(params).await.unwrap().unwrap();
+
+    assert_eq!(
+        r#"from(bucket: "my-bucket")"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+/// A column removed by an earlier stage is reported, with quick fixes replacing it
+/// with the known columns, closest first.
+#[test]
+async fn test_code_action_unknown_column() {
+    let fluxscript = r#"from(bucket: "telegraf")
+  |> keep(columns: ["_time", "_value", "host"])
+  |> filter(fn: (r) => r.hots == "a")"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let diagnostics = server
+        .compute_diagnostics(
+            &lsp::Url::parse("file:///home/user/file.flux").unwrap(),
+        )
+        .into_values()
+        .flatten()
+        .collect::<Vec<lsp::Diagnostic>>();
+    assert_eq!(1, diagnostics.len());
+
+    let mut params = code_action_params(lsp::Position::new(2, 25));
+    params.context.diagnostics = diagnostics;
+    let result = server.code_action(params).await.unwrap().unwrap();
+
+    let titles: Vec<String> = result
+        .iter()
+        .filter_map(|action| match action {
+            lsp::CodeActionOrCommand::CodeAction(action) => {
+                Some(action.title.clone())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            "Replace with column `host`",
+            "Replace with column `_time`",
+            "Replace with column `_value`",
+        ],
+        titles[..3].to_vec()
+    );
+    assert_eq!(
+        r#"from(bucket: "telegraf")
+  |> keep(columns: ["_time", "_value", "host"])
+  |> filter(fn: (r) => r.host == "a")"#,
+        apply_code_action(fluxscript, &result[0])
+    );
+}
+
+/// A schema provider standing in for the client, which answers schema requests
+/// from InfluxDB.
+struct MockSchemaProvider;
+
+#[async_trait::async_trait]
+impl schema::SchemaProvider for MockSchemaProvider {
+    async fn schema(
+        &self,
+        bucket: &str,
+    ) -> Option<schema::BucketSchema> {
+        if bucket != "telegraf" {
+            return None;
+        }
+        Some(
+            serde_json::from_value(json!({
+                "measurements": {
+                    "cpu": {
+                        "fields": ["usage_user", "usage_system"],
+                        "tags": {"host": ["server01", "server02"], "cpu": ["cpu-total"]},
+                    },
+                    "mem": {
+                        "fields": ["used_percent"],
+                        "tags": {"host": ["server03"]},
+                    },
+                }
+            }))
+            .unwrap(),
+        )
+    }
+}
+
+async fn schema_completion_labels(fluxscript: &str) -> Vec<String> {
+    let server = create_server();
+    server
+        .schema
+        .set_provider(Some(Arc::new(MockSchemaProvider)));
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => {
+            list.items.into_iter().map(|item| item.label).collect()
+        }
+        _ => vec![],
+    }
+}
+
+#[test]
+async fn test_measurement_completion() {
+    let labels = schema_completion_labels(
+        r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "")
+                                         // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["cpu".to_string(), "mem".to_string()], labels);
+}
+
+#[test]
+async fn test_field_completion() {
+    let labels = schema_completion_labels(
+        r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "cpu")
+    |> filter(fn: (r) => r._field == "")
+                                   // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec!["usage_system".to_string(), "usage_user".to_string()],
+        labels
+    );
+}
+
+#[test]
+async fn test_tag_key_completion() {
+    let labels = schema_completion_labels(
+        r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "mem")
+    |> filter(fn: (r) => r.)
+                       // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["host".to_string()], labels);
+}
+
+#[test]
+async fn test_tag_value_completion() {
+    let labels = schema_completion_labels(
+        r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r.host == "")
+                                 // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec![
+            "server01".to_string(),
+            "server02".to_string(),
+            "server03".to_string()
+        ],
+        labels
+    );
+}
+
+/// Complete measurements with a client that answers schema requests,
+/// returning the completion labels and the buckets requested.
+async fn client_schema_completion(
+    initialization_options: serde_json::Value,
+) -> (Vec<String>, Vec<String>) {
+    use futures::StreamExt;
+    use tower_service::Service;
+
+    let fluxscript = r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "")
+                                         // ^
+"#;
+    let (service, mut messages) =
+        lspower::LspService::new(|client| {
+            LspServer::new(Some(client))
+        });
+    let service = Arc::new(Mutex::new(service));
+
+    // The client answers schema requests, and ignores other messages.
+    let requests: Arc<Mutex<Vec<String>>> = Arc::default();
+    let client_service = service.clone();
+    let client_requests = requests.clone();
+    async_std::task::spawn(async move {
+        while let Some(message) = messages.next().await {
+            let message = serde_json::to_value(&message).unwrap();
+            if message["method"] != "flux/schema" {
+                continue;
+            }
+            client_requests.lock().unwrap().push(
+                message["params"]["bucket"].as_str().unwrap().into(),
+            );
+            let response: lspower::jsonrpc::Incoming =
+                serde_json::from_value(json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "result": {"measurements": {"cpu": {}, "mem": {}}},
+                }))
+                .unwrap();
+            let response =
+                client_service.lock().unwrap().call(response);
+            response.await.unwrap();
+        }
+    });
+
+    let call = |message: serde_json::Value| {
+        let message: lspower::jsonrpc::Incoming =
+            serde_json::from_value(message).unwrap();
+        service.lock().unwrap().call(message)
+    };
+    call(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "capabilities": {},
+            "initializationOptions": initialization_options,
+        },
+    }))
+    .await
+    .unwrap();
+    call(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {"textDocument": {
+            "uri": "file:///home/user/file.flux",
+            "languageId": "flux",
+            "version": 1,
+            "text": fluxscript,
+        }},
+    }))
+    .await
+    .unwrap();
+    let response = call(json!({
+        "jsonrpc": "2.0",
+        "id": 2,
+        "method": "textDocument/completion",
+        "params": {
+            "textDocument": {"uri": "file:///home/user/file.flux"},
+            "position": position_of(fluxscript),
+        },
+    }))
+    .await
+    .unwrap()
+    .unwrap();
+
+    let response = serde_json::to_value(&response).unwrap();
+    let labels: Vec<String> = response["result"]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect();
+    let requests = requests.lock().unwrap().clone();
+    (labels, requests)
+}
+
+/// Schemas come from the client through `flux/schema` requests when the
+/// workspace has no schema file.
+#[test]
+async fn test_measurement_completion_from_client() {
+    let (labels, requests) =
+        client_schema_completion(json!({"schemaRequests": true}))
+            .await;
+
+    assert_eq!(vec!["cpu", "mem"], labels);
+    assert_eq!(vec!["telegraf".to_string()], requests);
+}
+
+/// Clients are only sent schema requests if they said they answer them.
+#[test]
+async fn test_measurement_completion_without_client_schema() {
+    let (labels, requests) =
+        client_schema_completion(json!({})).await;
+
+    assert!(!labels.contains(&"cpu".to_string()));
+    assert!(requests.is_empty());
+}
+
+/// Schema files created after initialization are read, and cached schemas
+/// are dropped when the schema file changes.
+#[test]
+async fn test_schema_file_change() {
+    let folder = TempFolder::new("flux-lsp-schema-change-test");
+    let schema_file = folder.join(schema::SCHEMA_FILE);
+    let write_measurement = |measurement: &str| {
+        std::fs::write(
+            &schema_file,
+            json!({"buckets": {"telegraf": {"measurements": {measurement: {}}}}})
+                .to_string(),
+        )
+        .unwrap();
+    };
+
+    let fluxscript = r#"from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r._measurement == "")
+                                         // ^
+"#;
+    let server = create_server();
+    let params = lsp::InitializeParams {
+        capabilities: lsp::ClientCapabilities {
+            workspace: None,
+            text_document: None,
+            window: None,
+            general: None,
+            experimental: None,
+        },
+        client_info: None,
+        initialization_options: None,
+        locale: None,
+        process_id: None,
+        root_path: None,
+        root_uri: Some(
+            lsp::Url::from_directory_path(&*folder).unwrap(),
+        ),
+        trace: None,
+        workspace_folders: None,
+    };
+    server.initialize(params).await.unwrap();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let completion_labels = || async {
+        let params = lsp::CompletionParams {
+            text_document_position: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: lsp::Url::parse(
+                        "file:///home/user/file.flux",
+                    )
+                    .unwrap(),
+                },
+                position: position_of(fluxscript),
+            },
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+            context: None,
+        };
+        match server.completion(params).await.unwrap() {
+            Some(lsp::CompletionResponse::List(list)) => list
+                .items
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<String>>(),
+            _ => vec![],
+        }
+    };
+    let schema_file_changed = |typ: lsp::FileChangeType| {
+        server.did_change_watched_files(
+            lsp::DidChangeWatchedFilesParams {
+                changes: vec![lsp::FileEvent {
+                    uri: lsp::Url::from_file_path(&schema_file)
+                        .unwrap(),
+                    typ,
+                }],
+            },
+        )
+    };
+    assert!(!completion_labels().await.contains(&"cpu".to_string()));
+
+    write_measurement("cpu");
+    schema_file_changed(lsp::FileChangeType::CREATED).await;
+    assert_eq!(vec!["cpu".to_string()], completion_labels().await);
+
+    write_measurement("disk");
+    schema_file_changed(lsp::FileChangeType::CHANGED).await;
+    assert_eq!(vec!["disk".to_string()], completion_labels().await);
+}
+
+async fn member_completion_items(
+    fluxscript: &str,
+) -> Vec<(String, Option<String>)> {
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: Some(lsp::CompletionContext {
+            trigger_kind:
+                lsp::CompletionTriggerKind::TRIGGER_CHARACTER,
+            trigger_character: Some(".".to_string()),
+        }),
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => list
+            .items
+            .into_iter()
+            .map(|item| (item.label, item.detail))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[test]
+async fn test_nested_record_member_completion() {
+    let items = member_completion_items(
+        r#"config = {server: {host: "localhost", port: 8086}}
+config.server.
+          // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec![
+            ("host".to_string(), Some("string".to_string())),
+            ("port".to_string(), Some("int".to_string())),
+        ],
+        items
+    );
+}
+
+#[test]
+async fn test_indexed_record_member_completion() {
+    let items = member_completion_items(
+        r#"hosts = [{name: "a", up: true}]
+hosts[0].
+     // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec![
+            ("name".to_string(), Some("string".to_string())),
+            ("up".to_string(), Some("bool".to_string())),
+        ],
+        items
+    );
+}
+
+#[test]
+async fn test_call_result_member_completion() {
+    let items = member_completion_items(
+        r#"server = () => ({host: "localhost"})
+server().
+     // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec![("host".to_string(), Some("string".to_string()))],
+        items
+    );
+}
+
+#[test]
+async fn test_completion_resolve() {
+    let fluxscript = r#"import "strings"
+
+strings.
+    // ^
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: Some(lsp::CompletionContext {
+            trigger_kind:
+                lsp::CompletionTriggerKind::TRIGGER_CHARACTER,
+            trigger_character: Some(".".to_string()),
+        }),
+    };
+
+    let item = match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => list
+            .items
+            .into_iter()
+            .find(|item| item.label == "toUpper")
+            .unwrap(),
+        _ => unreachable!(),
+    };
+    // Documentation is only added once the item is resolved.
+    assert_eq!(None, item.documentation);
+
+    let resolved = server.completion_resolve(item).await.unwrap();
+
+    match resolved.documentation {
+        Some(lsp::Documentation::String(documentation)) => {
+            assert!(documentation.starts_with(
+                "```flux\ntoUpper: (v: string) => string\n```"
+            ));
+            assert!(documentation.contains("**Parameters**"));
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+async fn test_completion_resolve_without_documentation() {
+    let server = create_server();
+    let item = lsp::CompletionItem {
+        label: "myFunction".to_string(),
+        ..lsp::CompletionItem::default()
+    };
+
+    let resolved =
+        server.completion_resolve(item.clone()).await.unwrap();
+
+    assert_eq!(item, resolved);
+}
+
+async fn package_function_completion(
+    fluxscript: &str,
+    label: &str,
+) -> Option<lsp::CompletionItem> {
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: Some(lsp::CompletionContext {
+            trigger_kind: lsp::CompletionTriggerKind::INVOKED,
+            trigger_character: None,
+        }),
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => {
+            list.items.into_iter().find(|item| item.label == label)
+        }
+        _ => None,
+    }
+}
+
+/// Completing a function from a package that isn't imported adds the import.
+#[test]
+async fn test_package_function_completion_adds_import() {
+    let item = package_function_completion(
+        r#"package main
+
+toUpp
+// ^
+"#,
+        "strings.toUpper",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(Some("strings.toUpper".to_string()), item.insert_text);
+    assert_eq!(
+        Some(vec![lsp::TextEdit {
+            range: lsp::Range {
+                start: lsp::Position {
+                    line: 1,
+                    character: 0,
+                },
+                end: lsp::Position {
+                    line: 1,
+                    character: 0,
+                },
+            },
+            new_text: "import \"strings\"\n".to_string(),
+        }]),
+        item.additional_text_edits
+    );
+}
+
+/// Functions from packages that are already imported use the import's alias,
+/// and don't add another import.
+#[test]
+async fn test_package_function_completion_with_alias() {
+    let item = package_function_completion(
+        r#"import str "strings"
+
+toUpp
+// ^
+"#,
+        "str.toUpper",
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(Some("str.toUpper".to_string()), item.insert_text);
+    assert_eq!(None, item.additional_text_edits);
+}
+
+/// A package isn't imported when its name is already taken by another import.
+#[test]
+async fn test_package_function_completion_name_conflict() {
+    let item = package_function_completion(
+        r#"import strings "regexp"
+
+toUpp
+// ^
+"#,
+        "strings.toUpper",
+    )
+    .await;
+
+    assert_eq!(None, item);
+}
+
+async fn ranked_completion_labels(fluxscript: &str) -> Vec<String> {
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: Some(lsp::CompletionContext {
+            trigger_kind: lsp::CompletionTriggerKind::INVOKED,
+            trigger_character: None,
+        }),
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => {
+            assert!(list.is_incomplete);
+            list.items.into_iter().map(|item| item.label).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Local bindings rank above prelude functions that match as well.
+#[test]
+async fn test_completion_ranks_locals_first() {
+    let labels = ranked_completion_labels(
+        r#"container = 1
+contai
+  // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(Some("container"), labels.first().map(String::as_str));
+    assert!(labels.iter().any(|label| label == "contains"));
+}
+
+/// Completion matches subsequences, e.g. the start of each word of a name.
+#[test]
+async fn test_completion_matches_subsequences() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggW
+     // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        Some("aggregateWindow"),
+        labels.first().map(String::as_str)
+    );
+}
+
+/// Bindings with the type expected by a call argument rank first.
+#[test]
+async fn test_completion_ranks_expected_type_first() {
+    let labels = ranked_completion_labels(
+        r#"title = "threshold"
+total = 1
+f = (n) => n + 1
+f(n: t)
+  // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec!["total", "title"],
+        labels
+            .iter()
+            .take(2)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+    );
+}
+
+/// After `|>`, only functions that take piped tables are completed, including
+/// user functions.
+#[test]
+async fn test_pipe_completion() {
+    let labels = ranked_completion_labels(
+        r#"countRows = (tables=<-) => tables |> count()
+from(bucket: "telegraf")
+  |> co
+   // ^
+"#,
+    )
+    .await;
+
+    assert!(labels.iter().any(|label| label == "count"));
+    assert!(labels.iter().any(|label| label == "covariance"));
+    assert!(labels.iter().any(|label| label == "countRows"));
+    assert!(!labels.iter().any(|label| label == "contains"));
+    assert!(!labels.iter().any(|label| label == "cov"));
+}
+
+/// Functions that take piped tables aren't called outside of a pipe.
+#[test]
+async fn test_plain_call_completion() {
+    let labels = ranked_completion_labels(
+        r#"x = co()
+  // ^
+"#,
+    )
+    .await;
+
+    assert!(labels.iter().any(|label| label == "contains"));
+    assert!(labels.iter().any(|label| label == "cov"));
+    assert!(!labels.iter().any(|label| label == "count"));
+}
+
+/// Functions passed as values aren't filtered by pipe position, e.g. the
+/// aggregate of `aggregateWindow`.
+#[test]
+async fn test_value_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1m, fn: me)
+                                  // ^
+"#,
+    )
+    .await;
+
+    assert!(labels.iter().any(|label| label == "mean"));
+}
+
+/// Package members are filtered by pipe position too.
+#[test]
+async fn test_pipe_member_completion() {
+    let labels = ranked_completion_labels(
+        r#"import "sql"
+
+from(bucket: "telegraf")
+  |> sql.t()
+      // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["to"], labels);
+}
+
+/// After the colon of a duration parameter, common durations are completed.
+#[test]
+async fn test_duration_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: )
+                        // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec!["10s", "1m", "5m", "15m", "1h", "6h", "1d", "1w"],
+        labels[..8].to_vec()
+    );
+}
+
+/// After `fn:`, a function literal is completed first, then the functions that can
+/// be passed, e.g. the aggregates for `aggregateWindow`.
+#[test]
+async fn test_function_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1m, fn: )
+                                // ^
+"#,
+    )
+    .await;
+
+    assert_eq!("(tables=<-, column) => ...", labels[0]);
+    assert!(labels.iter().any(|label| label == "mean"));
+    assert!(labels.iter().any(|label| label == "sum"));
+    assert!(!labels.iter().any(|label| label == "filter"));
+    assert!(!labels.iter().any(|label| label == "now"));
+}
+
+/// A partly typed duration argument completes the durations it matches.
+#[test]
+async fn test_partial_duration_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1)
+                         // ^
+"#,
+    )
+    .await;
+
+    assert!(labels.iter().any(|label| label == "1m"));
+    assert!(labels.iter().any(|label| label == "1h"));
+    assert!(!labels.iter().any(|label| label == "5m"));
+}
+
+/// A partly typed function argument completes the functions it matches that
+/// can be passed.
+#[test]
+async fn test_partial_function_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1m, fn: me)
+                                  // ^
+"#,
+    )
+    .await;
+
+    assert!(labels.iter().any(|label| label == "mean"));
+    assert!(labels.iter().any(|label| label == "median"));
+    assert!(!labels.iter().any(|label| label == "sum"));
+    assert!(!labels.iter().any(|label| label == "time"));
+}
+
+/// Parameters that only accept some strings complete those strings, quoted.
+#[test]
+async fn test_enum_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> group(columns: ["host"], mode:)
+                               // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec![r#""by""#, r#""except""#], labels);
+}
+
+/// Inside the string of a parameter that only accepts some strings, those strings
+/// are completed.
+#[test]
+async fn test_enum_argument_string_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1m, fn: mean, timeSrc: "")
+                                                // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["_start", "_stop", "_time"], labels);
+}
+
+/// Boolean parameters complete `true` and `false`.
+#[test]
+async fn test_bool_argument_completion() {
+    let labels = ranked_completion_labels(
+        r#"from(bucket: "telegraf")
+  |> aggregateWindow(every: 1m, fn: mean, createEmpty: )
+                                                   // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["true", "false"], labels[..2].to_vec());
+}
+
+async fn ranked_column_labels(fluxscript: &str) -> Vec<String> {
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => {
+            let mut items = list.items;
+            items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+            items
+                .into_iter()
+                .map(|item| item.insert_text.unwrap_or(item.label))
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The columns of a pipeline are inferred from its source and stages, and complete
+/// the strings of a `columns` array.
+#[test]
+async fn test_pipeline_column_completion() {
+    let labels = ranked_column_labels(
+        r#"from(bucket: "telegraf")
+  |> range(start: -1h)
+  |> drop(columns: ["_start", "_stop"])
+  |> duplicate(column: "_value", as: "value")
+  |> rename(columns: {_field: "field"})
+  |> keep(columns: ["_time", ""])
+                          // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec!["_value", "field", "_measurement", "value"],
+        labels
+    );
+}
+
+/// Columns of `array.from` rows, changed by `map`, complete the columns of `sort`.
+#[test]
+async fn test_array_pipeline_column_completion() {
+    let labels = ranked_column_labels(
+        r#"import "array"
+
+array.from(rows: [{host: "a", value: 1}])
+  |> map(fn: (r) => ({r with doubled: r.value * 2}))
+  |> sort(columns: [])
+                // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec![r#""host""#, r#""value""#, r#""doubled""#],
+        labels
+    );
+}
+
+/// Pipelines are followed through variables, and `pivot` replaces the columns it
+/// pivots.
+#[test]
+async fn test_variable_pipeline_column_completion() {
+    let labels = ranked_column_labels(
+        r#"data = from(bucket: "telegraf")
+  |> pivot(rowKey: ["_time"], columnKey: ["_field"], valueColumn: "_value")
+
+data
+  |> group(columns: [""])
+                  // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(
+        vec!["_time", "_measurement", "_start", "_stop"],
+        labels
+    );
+}
+
+/// The fields of a record parameter in a later stage are the columns of the
+/// pipeline.
+#[test]
+async fn test_pipeline_record_completion() {
+    let labels = ranked_column_labels(
+        r#"import "csv"
+
+csv.from(csv: "
+#datatype,string,long,dateTime:RFC3339,double,string
+#group,false,false,false,false,true
+#default,_result,,,,
+,result,table,_time,_value,host
+")
+  |> filter(fn: (r) => r.)
+                     // ^
+"#,
+    )
+    .await;
+
+    assert_eq!(vec!["_time", "_value", "host"], labels);
+}
+
+async fn completion_items(
+    fluxscript: &str,
+) -> Vec<lsp::CompletionItem> {
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+
+    match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => list.items,
+        _ => vec![],
+    }
+}
+
+/// The known options are completed after `option`, with a snippet of their value,
+/// importing the package of package options.
+#[test]
+async fn test_option_statement_completion() {
+    let items = completion_items(
+        r#"option ta
+     // ^
+"#,
+    )
+    .await;
+
+    let labels: Vec<&str> =
+        items.iter().map(|item| item.label.as_str()).collect();
+    assert_eq!(
+        vec!["now", "task", "location", "influxdb.defaultURL"],
+        labels
+    );
+    assert_eq!(
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(0, 7),
+                lsp::Position::new(0, 9)
+            ),
+            new_text: r#"task = {name: "${1:name}", every: ${2:1h}}"#
+                .into(),
+        })),
+        items[1].text_edit
+    );
+    assert_eq!(
+        Some(vec![lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(0, 0),
+                lsp::Position::new(0, 0)
+            ),
+            new_text: "import \"influxdata/influxdb\"\n".into(),
+        }]),
+        items[3].additional_text_edits
+    );
+}
+
+/// The fields of `option task` that aren't set are completed, in order.
+#[test]
+async fn test_option_task_record_completion() {
+    let items = completion_items(
+        r#"option task = {name: "downsample", }
+                               // ^
+"#,
+    )
+    .await;
+
+    let fields: Vec<(String, Option<String>)> = items
+        .into_iter()
+        .map(|item| (item.label, item.detail))
+        .collect();
+    assert_eq!(
+        vec![
+            ("every".to_string(), Some("duration".to_string())),
+            ("cron".to_string(), Some("string".to_string())),
+            ("offset".to_string(), Some("duration".to_string())),
+            ("concurrency".to_string(), Some("int".to_string())),
+            ("retry".to_string(), Some("int".to_string())),
+        ],
+        fields
+    );
+}
+
+/// Hovering the name of an option explains what reads it.
+#[test]
+async fn test_option_hover() {
+    let fluxscript =
+        r#"option task = {name: "downsample", every: 1h}"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .hover(hover_params(lsp::Position::new(0, 9)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let value = match result.contents {
+        lsp::HoverContents::Scalar(lsp::MarkedString::String(
+            value,
+        )) => value,
+        contents => {
+            panic!("Unexpected hover contents {:?}", contents)
+        }
+    };
+    assert!(value.starts_with("```flux\noption task\n```"));
+    assert!(value.contains("Read by the InfluxDB task scheduler"));
+    assert_eq!(
+        Some(lsp::Range::new(
+            lsp::Position::new(0, 7),
+            lsp::Position::new(0, 11)
+        )),
+        result.range
+    );
+}
+
+/// Query skeletons are completed at the start of a statement, importing the
+/// packages they use.
+#[test]
+async fn test_snippet_completion() {
+    let items = completion_items(
+        r#"x = 1
+    dea
+   // ^
+"#,
+    )
+    .await;
+
+    let item = items
+        .iter()
+        .find(|item| item.label == "deadman")
+        .expect("no deadman snippet");
+    assert_eq!(Some(lsp::CompletionItemKind::SNIPPET), item.kind);
+    assert_eq!(
+        Some(lsp::InsertTextFormat::SNIPPET),
+        item.insert_text_format
+    );
+    assert!(item
+        .insert_text
+        .as_ref()
+        .unwrap()
+        .contains("monitor.deadman("));
+    assert_eq!(
+        Some(vec![lsp::TextEdit {
+            range: lsp::Range::default(),
+            new_text: "import \"experimental\"\nimport \"influxdata/influxdb/monitor\"\n".into(),
+        }]),
+        item.additional_text_edits
+    );
+}
+
+/// Snippets aren't offered inside an expression.
+#[test]
+async fn test_snippet_completion_not_in_expression() {
+    let items = completion_items(
+        r#"x = dea
+   // ^
+"#,
+    )
+    .await;
+
+    assert!(!items.iter().any(|item| {
+        item.kind == Some(lsp::CompletionItemKind::SNIPPET)
+    }));
+}
+
+/// Teams can register their own snippets with the `snippets` initialization option.
+#[test]
+async fn test_snippet_completion_initialization_options() {
+    let fluxscript = r#"    tea
+   // ^
+"#;
+    let server = create_server();
+    let params = lsp::InitializeParams {
+        capabilities: lsp::ClientCapabilities {
+            workspace: None,
+            text_document: None,
+            window: None,
+            general: None,
+            experimental: None,
+        },
+        client_info: None,
+        initialization_options: Some(json!({
+            "snippets": {
+                "Team CPU": {
+                    "prefix": "teamcpu",
+                    "body": [
+                        "from(bucket: \"team\")",
+                        "  |> range(start: ${1:-1h})",
+                    ],
+                    "description": "CPU usage of the team hosts",
+                },
+            },
+        })),
+        locale: None,
+        process_id: None,
+        root_path: None,
+        root_uri: None,
+        trace: None,
+        workspace_folders: None,
+    };
+    server.initialize(params).await.unwrap();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let params = lsp::CompletionParams {
+        text_document_position: lsp::TextDocumentPositionParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            position: position_of(fluxscript),
+        },
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+        context: None,
+    };
+    let items = match server.completion(params).await.unwrap() {
+        Some(lsp::CompletionResponse::List(list)) => list.items,
+        _ => vec![],
+    };
+
+    let item = items
+        .iter()
+        .find(|item| item.label == "teamcpu")
+        .expect("no user snippet");
+    assert_eq!(
+        Some("CPU usage of the team hosts".to_string()),
+        item.detail
+    );
+    assert_eq!(
+        Some(
+            "from(bucket: \"team\")\n  |> range(start: ${1:-1h})"
+                .to_string()
+        ),
+        item.insert_text
+    );
+}
+
+/// Postfix templates after a pipeline replace the whole pipeline.
+#[test]
+async fn test_postfix_pipeline_completion() {
+    let items = completion_items(
+        r#"from(bucket: "b")
+  |> range(start: -1h).yi
+                     // ^
+"#,
+    )
+    .await;
+
+    let item = items
+        .iter()
+        .find(|item| item.label == "yield")
+        .expect("no yield template");
+    assert_eq!(Some(lsp::CompletionItemKind::SNIPPET), item.kind);
+    assert_eq!(
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(0, 0),
+                lsp::Position::new(1, 25)
+            ),
+            new_text: "from(bucket: \"b\")\n  |> range(start: -1h)\n  |> yield(name: \"$1\")".into(),
+        })),
+        item.text_edit
+    );
+    assert!(items.iter().any(|item| item.label == "filter"));
+}
+
+/// `.if` is only completed after a boolean expression.
+#[test]
+async fn test_postfix_bool_completion() {
+    let items = completion_items(
+        r#"ok = 1 > 0
+ok.i
+// ^
+"#,
+    )
+    .await;
+
+    let labels: Vec<&str> = items
+        .iter()
+        .filter(|item| {
+            item.kind == Some(lsp::CompletionItemKind::SNIPPET)
+        })
+        .map(|item| item.label.as_str())
+        .collect();
+    assert_eq!(vec!["var", "if"], labels);
+
+    let item = items.iter().find(|item| item.label == "if").unwrap();
+    assert_eq!(
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(1, 0),
+                lsp::Position::new(1, 4)
+            ),
+            new_text: "if ok then $1 else $2".into(),
+        })),
+        item.text_edit
+    );
+}
+
+/// The receiver of a postfix template is escaped in the snippet, so the
+/// interpolation of a string isn't read as a snippet variable.
+#[test]
+async fn test_postfix_interpolated_string_completion() {
+    let items = completion_items(
+        r#"host = "a"
+"${host}".va
+       // ^
+"#,
+    )
+    .await;
+
+    let item = items
+        .iter()
+        .find(|item| item.label == "var")
+        .expect("no var template");
+    assert_eq!(
+        Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(1, 0),
+                lsp::Position::new(1, 12)
+            ),
+            new_text: r#"${1:name} = "\${host\}""#.into(),
+        })),
+        item.text_edit
+    );
+}
+
+fn hover_value(hover: lsp::Hover) -> String {
+    match hover.contents {
+        lsp::HoverContents::Scalar(lsp::MarkedString::String(
+            value,
+        )) => value,
+        contents => {
+            panic!("Unexpected hover contents {:?}", contents)
+        }
+    }
+}
+
+/// Hovering a stdlib function shows its documentation.
+#[test]
+async fn test_stdlib_function_hover() {
+    let fluxscript = r#"import str "strings"
+
+from(bucket: "b")
+  |> aggregateWindow(every: 1m, fn: mean)
+  |> map(fn: (r) => ({r with host: str.trim(v: r.host, cutset: ".")}))
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(3, 8)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        Some(lsp::Range::new(
+            lsp::Position::new(3, 5),
+            lsp::Position::new(3, 20)
+        )),
+        hover.range
+    );
+    let value = hover_value(hover);
+    assert!(value.starts_with("```flux\naggregateWindow: "));
+    assert!(value.contains("| `every` |"));
+    assert!(value.contains("**Returns**: "));
+    assert!(value.contains("[Documentation](https://docs.influxdata.com/flux/v0.x/stdlib/universe/aggregatewindow/)"));
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(4, 40)))
+        .await
+        .unwrap()
+        .unwrap();
+    let value = hover_value(hover);
+    assert!(value.starts_with(
+        "```flux\nstrings.trim: (cutset: string, v: string) => string\n```"
+    ));
+}
+
+/// Hovering an import path shows the package overview and its members.
+#[test]
+async fn test_import_path_hover() {
+    let fluxscript = r#"import "strings""#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(0, 10)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let value = hover_value(hover);
+    assert!(value.starts_with("```flux\nimport \"strings\"\n```"));
+    assert!(value.contains("**Members**"));
+    assert!(value.contains("- `trim`: strings.trim() removes"));
+}
+
+const DOCUMENTED_FUNCTION: &str = r#"// Convert a temperature to Fahrenheit.
+// @param c The temperature in Celsius.
+toFahrenheit = (c) => c * 9.0 / 5.0 + 32.0
+"#;
+
+/// Hovering a user function shows its doc comment with its type.
+#[test]
+async fn test_doc_comment_hover() {
+    let fluxscript =
+        format!("{}toFahrenheit(c: 1.0)\n", DOCUMENTED_FUNCTION);
+    let server = create_server();
+    open_file(&server, fluxscript, None).await;
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(3, 3)))
+        .await
+        .unwrap()
+        .unwrap();
+
+    expect![[r#"
+        ```flux
+        toFahrenheit: (c: float) => float
+        ```
+
+        Convert a temperature to Fahrenheit.
+
+        **Parameters**
+
+        - `c`: The temperature in Celsius."#]]
+    .assert_eq(&hover_value(hover));
+}
+
+/// Parameters and variables of functions that shadow a documented top-level
+/// binding aren't hovered with its doc comment, and doc comments inside
+/// functions don't document top-level bindings.
+#[test]
+async fn test_doc_comment_hover_shadowed() {
+    let fluxscript = format!(
+        r#"{}convert = (toFahrenheit) => toFahrenheit
+f = () => {{
+    // The local x.
+    x = 1
+    return x
+}}
+x = 2
+y = x
+"#,
+        DOCUMENTED_FUNCTION
+    );
+    let server = create_server();
+    open_file(&server, fluxscript, None).await;
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(3, 32)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!hover_value(hover).contains("Convert a temperature"));
+
+    let hover = server
+        .hover(hover_params(lsp::Position::new(10, 4)))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!hover_value(hover).contains("The local x."));
+}
+
+/// Completing a user function documents it with its doc comment.
+#[test]
+async fn test_doc_comment_completion() {
+    let items = completion_items(&format!(
+        "{}toFah\n// ^\n",
+        DOCUMENTED_FUNCTION
+    ))
+    .await;
+
+    let item = items
+        .iter()
+        .find(|item| item.label == "toFahrenheit")
+        .unwrap();
+    assert_eq!(
+        Some(lsp::Documentation::String(
+            "Convert a temperature to Fahrenheit.\n\n**Parameters**\n\n- `c`: The temperature in Celsius.".into()
+        )),
+        item.documentation
+    );
+}
+
+/// Signature help for a user function documents it and its parameters.
+#[test]
+async fn test_doc_comment_signature_help() {
+    let fluxscript =
+        format!("{}toFahrenheit(c: 1.0)\n", DOCUMENTED_FUNCTION);
+    let server = create_server();
+    open_file(&server, fluxscript, None).await;
+
+    let params = lsp::SignatureHelpParams {
+        context: None,
+        text_document_position_params:
+            lsp::TextDocumentPositionParams::new(
+                lsp::TextDocumentIdentifier::new(
+                    lsp::Url::parse("file:///home/user/file.flux")
+                        .unwrap(),
+                ),
+                lsp::Position::new(3, 20),
+            ),
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+    };
+
+    let result =
+        server.signature_help(params).await.unwrap().unwrap();
+
+    let signature = &result.signatures[0];
+    assert_eq!("toFahrenheit(c: float) => float", signature.label);
+    assert_eq!(
+        Some(vec![lsp::ParameterInformation {
+            label: lsp::ParameterLabel::Simple("c: float".into()),
+            documentation: Some(lsp::Documentation::String(
+                "The temperature in Celsius.".into()
+            )),
+        }]),
+        signature.parameters
+    );
+}
+
+/// The documentation of a user function is markdown for clients that can
+/// render it in signature help.
+#[test]
+async fn test_doc_comment_signature_help_with_markdown() {
+    let fluxscript =
+        format!("{}toFahrenheit(c: 1.0)\n", DOCUMENTED_FUNCTION);
+    let server = create_server();
+    let params = lsp::InitializeParams {
+        capabilities: lsp::ClientCapabilities {
+            workspace: None,
+            text_document: Some(
+                lsp::TextDocumentClientCapabilities {
+                    signature_help: Some(
+                        lsp::SignatureHelpClientCapabilities {
+                            signature_information: Some(
+                                lsp::SignatureInformationSettings {
+                                    documentation_format: Some(vec![
+                                        lsp::MarkupKind::Markdown,
+                                    ]),
+                                    ..Default::default()
+                                },
+                            ),
+                            ..Default::default()
+                        },
+                    ),
+                    ..Default::default()
+                },
+            ),
+            window: None,
+            general: None,
+            experimental: None,
+        },
+        client_info: None,
+        initialization_options: None,
+        locale: None,
+        process_id: None,
+        root_path: None,
+        root_uri: None,
+        trace: None,
+        workspace_folders: None,
+    };
+    server.initialize(params).await.unwrap();
+    open_file(&server, fluxscript, None).await;
+
+    let result = server
+        .signature_help(signature_help_params(lsp::Position::new(
+            3, 20,
+        )))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let signature = &result.signatures[0];
+    assert_eq!(
+        Some(lsp::Documentation::MarkupContent(lsp::MarkupContent {
+            kind: lsp::MarkupKind::Markdown,
+            value: "The temperature in Celsius.".into(),
+        })),
+        signature.parameters.as_ref().unwrap()[0].documentation
+    );
+}
+
+fn signature_help_params(
+    position: lsp::Position,
+) -> lsp::SignatureHelpParams {
+    lsp::SignatureHelpParams {
+        context: None,
+        text_document_position_params:
+            lsp::TextDocumentPositionParams::new(
+                lsp::TextDocumentIdentifier::new(
+                    lsp::Url::parse("file:///home/user/file.flux")
+                        .unwrap(),
+                ),
+                position,
+            ),
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+    }
+}
+
+/// Signature help resolves the package of an aliased import, and the active
+/// parameter follows the cursor through the named arguments.
+#[test]
+async fn test_signature_help_aliased_import() {
+    let fluxscript = r#"import s "strings"
+s.trim(v: "a", )
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .signature_help(signature_help_params(lsp::Position::new(
+            1, 9,
+        )))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        "trim(cutset: $cutset , v: $v)",
+        result.signatures[0].label
+    );
+    assert_eq!(Some(0), result.active_signature);
+    assert_eq!(Some(1), result.active_parameter);
+
+    // After a comma, the next argument to write is active.
+    let result = server
+        .signature_help(signature_help_params(lsp::Position::new(
+            1, 14,
+        )))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(0), result.active_signature);
+    assert_eq!(Some(0), result.active_parameter);
+}
+
+/// Signature help for a package with a nested path, e.g. `influxdata/influxdb/schema`.
+#[test]
+async fn test_signature_help_nested_package() {
+    let fluxscript = r#"import "influxdata/influxdb/schema"
+schema.measurements(bucket: "b")
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let result = server
+        .signature_help(signature_help_params(lsp::Position::new(
+            1, 30,
+        )))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(result.signatures.iter().all(|signature| signature
+        .label
+        .starts_with("measurements(")));
+    let active =
+        &result.signatures[result.active_signature.unwrap() as usize];
+    assert_eq!(
+        lsp::ParameterLabel::Simple("$bucket".into()),
+        active.parameters.as_ref().unwrap()
+            [result.active_parameter.unwrap() as usize]
+            .label
+    );
+}
+
+const INLAY_HINTS: &str = r#"import "array"
+
+x = 1
+rows = array.from(rows: [{a: 1}])
+    |> filter(fn: (r) => r.a > x)
+"#;
+
+fn inlay_hint_params(
+    range: lsp::Range,
+) -> inlay_hints::InlayHintParams {
+    inlay_hints::InlayHintParams {
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        text_document: lsp::TextDocumentIdentifier {
+            uri: lsp::Url::parse("file:///home/user/file.flux")
+                .unwrap(),
+        },
+        range,
+    }
+}
+
+fn whole_file() -> lsp::Range {
+    lsp::Range {
+        start: lsp::Position::new(0, 0),
+        end: lsp::Position::new(5, 0),
+    }
+}
+
+/// Inlay hints show the types of variables and the stream after each pipe stage.
+#[test]
+async fn test_inlay_hints() {
+    let server = create_server();
+    open_file(&server, INLAY_HINTS.to_string(), None).await;
+
+    let hints = server
+        .inlay_hint(inlay_hint_params(whole_file()))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let x = hints
+        .iter()
+        .find(|hint| hint.position == lsp::Position::new(2, 1))
+        .unwrap();
+    assert_eq!(": int", x.label);
+    assert_eq!(Some(inlay_hints::InlayHintKind::TYPE), x.kind);
+
+    let rows = hints
+        .iter()
+        .find(|hint| hint.position == lsp::Position::new(3, 4))
+        .unwrap();
+    assert_eq!(": stream[{a: int}]", rows.label);
+
+    let filter = hints
+        .iter()
+        .find(|hint| hint.position == lsp::Position::new(4, 33))
+        .unwrap();
+    assert_eq!("stream[{a: int}]", filter.label);
+    assert_eq!(Some(true), filter.padding_left);
+}
+
+/// Only the hints in the requested range are returned.
+#[test]
+async fn test_inlay_hints_range() {
+    let server = create_server();
+    open_file(&server, INLAY_HINTS.to_string(), None).await;
+
+    let hints = server
+        .inlay_hint(inlay_hint_params(lsp::Range {
+            start: lsp::Position::new(2, 0),
+            end: lsp::Position::new(2, 5),
+        }))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        vec![lsp::Position::new(2, 1)],
+        hints.iter().map(|hint| hint.position).collect::<Vec<_>>()
+    );
+}
+
+/// Each kind of hint can be turned off, and long types are truncated, with the settings.
+#[test]
+async fn test_inlay_hints_settings() {
+    let server = create_server();
+    open_file(&server, INLAY_HINTS.to_string(), None).await;
+
+    server
+        .did_change_configuration(lsp::DidChangeConfigurationParams {
+            settings: json!({"settings": {"inlayHints": {"variableTypes": false, "maxLength": 10}}}),
+        })
+        .await;
+
+    let hints = server
+        .inlay_hint(inlay_hint_params(whole_file()))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Only the parameter of the filter function is left.
+    assert!(hints
+        .iter()
+        .filter(|hint| hint.label.starts_with(": "))
+        .all(|hint| hint.position.line == 4));
+    let filter = hints
+        .iter()
+        .find(|hint| hint.position == lsp::Position::new(4, 33))
+        .unwrap();
+    assert_eq!("stream[{a:…", filter.label);
+    assert_eq!(Some("stream[{a: int}]".to_string()), filter.tooltip);
+}
+
+/// Inlay hints are requested with the method of the LSP spec.
+#[test]
+async fn test_inlay_hints_request() {
+    let server = create_server();
+    open_file(&server, INLAY_HINTS.to_string(), None).await;
+
+    let result = server
+        .request_else(
+            "textDocument/inlayHint",
+            Some(json!({
+                "textDocument": {"uri": "file:///home/user/file.flux"},
+                "range": {"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 5}},
+            })),
+        )
+        .await
+        .unwrap();
+
+    expect![[r#"
+        [
+          {
+            "kind": 1,
+            "label": ": int",
+            "position": {
+              "character": 1,
+              "line": 2
+            }
+          }
+        ]"#]]
+    .assert_eq(&serde_json::to_string_pretty(&result).unwrap());
+}
+
+fn workspace_symbol_params(
+    query: &str,
+) -> lsp::WorkspaceSymbolParams {
+    lsp::WorkspaceSymbolParams {
+        query: query.to_string(),
+        work_done_progress_params: lsp::WorkDoneProgressParams {
+            work_done_token: None,
+        },
+        partial_result_params: lsp::PartialResultParams {
+            partial_result_token: None,
+        },
+    }
+}
+
+#[test]
+async fn test_workspace_symbol() {
+    let fluxscript = r#"import "strings"
+
+option now = () => 2022-01-01T00:00:00Z
+option task = {name: "cpu", every: 1h}
+
+cpuUsage = (host) => from(bucket: "telegraf")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r.host == host)
+
+cpuLimit = 90
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let symbols = server
+        .symbol(workspace_symbol_params("cpu"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let names: Vec<(&str, lsp::SymbolKind)> = symbols
+        .iter()
+        .map(|symbol| (symbol.name.as_str(), symbol.kind))
+        .collect();
+    assert_eq!(
+        vec![
+            ("cpuUsage", lsp::SymbolKind::FUNCTION),
+            ("cpuLimit", lsp::SymbolKind::VARIABLE),
+        ],
+        names
+    );
+    assert_eq!(
+        lsp::Location {
+            uri: lsp::Url::parse("file:///home/user/file.flux")
+                .unwrap(),
+            range: lsp::Range {
+                start: lsp::Position::new(5, 0),
+                end: lsp::Position::new(7, 40),
+            },
+        },
+        symbols[0].location
+    );
+
+    // Options are matched too, and letters can be skipped.
+    let symbols = server
+        .symbol(workspace_symbol_params("nw"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("now", symbols[0].name);
+    assert_eq!(lsp::SymbolKind::PROPERTY, symbols[0].kind);
+
+    assert_eq!(
+        None,
+        server.symbol(workspace_symbol_params("xyz")).await.unwrap()
+    );
+}
+
+/// A directory of its own in the temporary directory, removed when it's
+/// dropped, so tests and concurrent test runs don't share files.
+struct TempFolder(std::path::PathBuf);
+
+impl TempFolder {
+    fn new(name: &str) -> Self {
+        static COUNT: std::sync::atomic::AtomicUsize =
+            std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl std::ops::Deref for TempFolder {
+    type Target = std::path::Path;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for TempFolder {
+    fn drop(&mut self) {
+        // Symlinks are removed, not followed.
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Files of the workspace folders are indexed, skipping hidden and
+/// symlinked directories, and open documents are searched as they are in
+/// the editor.
+#[test]
+async fn test_workspace_symbol_folders() {
+    let folder = TempFolder::new("flux-lsp-symbol-test");
+    std::fs::create_dir_all(folder.join("lib")).unwrap();
+    std::fs::create_dir_all(folder.join(".git")).unwrap();
+    std::fs::write(
+        folder.join("lib").join("hosts.flux"),
+        "package hosts\n\nhostName = \"server01\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        folder.join(".git").join("old.flux"),
+        "hostOld = 1\n",
+    )
+    .unwrap();
+    std::fs::write(folder.join("main.flux"), "hostCount = 1\n")
+        .unwrap();
+    // A link back up the tree isn't followed.
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(&*folder, folder.join("loop"))
+        .unwrap();
+
+    let server = create_server();
+    let params = lsp::InitializeParams {
+        capabilities: lsp::ClientCapabilities {
+            workspace: None,
+            text_document: None,
+            window: None,
+            general: None,
+            experimental: None,
+        },
+        client_info: None,
+        initialization_options: None,
+        locale: None,
+        process_id: None,
+        root_path: None,
+        root_uri: Some(
+            lsp::Url::from_directory_path(&*folder).unwrap(),
+        ),
+        trace: None,
+        workspace_folders: None,
+    };
+    server.initialize(params).await.unwrap();
+    server.index_workspace().join().unwrap();
+
+    let main =
+        lsp::Url::from_file_path(folder.join("main.flux")).unwrap();
+    open_file(
+        &server,
+        "hostTotal = 2\n".to_string(),
+        Some(main.as_str()),
+    )
+    .await;
+
+    let symbols = server
+        .symbol(workspace_symbol_params("host"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut names: Vec<(&str, Option<&str>)> = symbols
+        .iter()
+        .map(|symbol| {
+            (symbol.name.as_str(), symbol.container_name.as_deref())
+        })
+        .collect();
+    names.sort();
+    assert_eq!(
+        vec![("hostName", Some("hosts")), ("hostTotal", None)],
+        names
+    );
+}
+
+#[test]
+async fn test_workspace_symbol_stdlib() {
+    let server = create_server();
+    open_file(&server, "trimmed = 1\n".to_string(), None).await;
+
+    let symbols = server
+        .symbol(workspace_symbol_params("strings.trim"))
+        .await
+        .unwrap();
+    assert_eq!(None, symbols);
+
+    server
+        .did_change_configuration(lsp::DidChangeConfigurationParams {
+            settings: json!({"settings": {"workspaceSymbols": {"stdlib": true}}}),
+        })
+        .await;
+
+    let symbols = server
+        .symbol(workspace_symbol_params("strings.trim"))
+        .await
+        .unwrap()
+        .unwrap();
+    let trim = symbols
+        .iter()
+        .find(|symbol| symbol.name == "strings.trim")
+        .expect("no stdlib symbol");
+    assert_eq!(lsp::SymbolKind::FUNCTION, trim.kind);
+    assert_eq!(Some("strings".to_string()), trim.container_name);
+    assert_eq!(
+        "https://docs.influxdata.com/flux/v0.x/stdlib/strings/trim/",
+        trim.location.uri.as_str()
+    );
+
+    // Functions of the prelude are named as they are called.
+    let symbols = server
+        .symbol(workspace_symbol_params("aggregateWindow"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!("aggregateWindow", symbols[0].name);
+}
+
+const CALL_HIERARCHY_HELPERS: &str = r#"toF = (c) => c * 9.0 / 5.0 + 32.0
+
+convert = (tables=<-) => tables
+    |> map(fn: (r) => ({r with _value: toF(c: r._value)}))
+"#;
+
+const CALL_HIERARCHY: &str = r#"hot = (tables=<-) => tables
+    |> convert()
+    |> filter(fn: (r) => r._value > toF(c: 30.0))
+
+from(bucket: "weather")
+    |> range(start: -1h)
+    |> convert()
+    |> yield(name: "fahrenheit")
+"#;
+
+/// Open the call hierarchy script, with its helpers in another file of the
+/// package.
+async fn open_call_hierarchy(server: &LspServer) {
+    open_file(
+        server,
+        CALL_HIERARCHY_HELPERS.to_string(),
+        Some("file:///home/user/helpers.flux"),
+    )
+    .await;
+    open_file(server, CALL_HIERARCHY.to_string(), None).await;
+}
+
+async fn prepare_call_hierarchy(
+    server: &LspServer,
+    position: lsp::Position,
+) -> Option<Vec<lsp::CallHierarchyItem>> {
+    server
+        .prepare_call_hierarchy(lsp::CallHierarchyPrepareParams {
+            text_document_position_params:
+                lsp::TextDocumentPositionParams {
+                    text_document: lsp::TextDocumentIdentifier {
+                        uri: lsp::Url::parse(
+                            "file:///home/user/file.flux",
+                        )
+                        .unwrap(),
+                    },
+                    position,
+                },
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+        })
+        .await
+        .unwrap()
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> lsp::Range {
+    lsp::Range {
+        start: lsp::Position::new(start.0, start.1),
+        end: lsp::Position::new(end.0, end.1),
+    }
+}
+
+/// Functions are prepared from their calls, even in other files of the
+/// package.
+#[test]
+async fn test_prepare_call_hierarchy() {
+    let server = create_server();
+    open_call_hierarchy(&server).await;
+
+    let items =
+        prepare_call_hierarchy(&server, lsp::Position::new(1, 9))
+            .await
+            .unwrap();
+
+    assert_eq!(1, items.len());
+    let item = &items[0];
+    assert_eq!("convert", item.name);
+    assert_eq!(lsp::SymbolKind::FUNCTION, item.kind);
+    assert_eq!(
+        lsp::Url::parse("file:///home/user/helpers.flux").unwrap(),
+        item.uri
+    );
+    assert_eq!(range((2, 0), (2, 7)), item.selection_range);
+
+    // Builtin functions aren't part of the hierarchy.
+    assert_eq!(
+        None,
+        prepare_call_hierarchy(&server, lsp::Position::new(5, 9))
+            .await
+    );
+}
+
+#[test]
+async fn test_call_hierarchy_incoming_calls() {
+    let server = create_server();
+    open_call_hierarchy(&server).await;
+
+    let item =
+        prepare_call_hierarchy(&server, lsp::Position::new(1, 9))
+            .await
+            .unwrap()
+            .remove(0);
+    let mut calls = server
+        .incoming_calls(lsp::CallHierarchyIncomingCallsParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    calls.sort_by(|a, b| a.from.name.cmp(&b.from.name));
+
+    let calls: Vec<(&str, lsp::SymbolKind, Vec<lsp::Range>)> = calls
+        .iter()
+        .map(|call| {
+            (
+                call.from.name.as_str(),
+                call.from.kind,
+                call.from_ranges.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "fahrenheit",
+                lsp::SymbolKind::EVENT,
+                vec![range((6, 7), (6, 14))]
+            ),
+            (
+                "hot",
+                lsp::SymbolKind::FUNCTION,
+                vec![range((1, 7), (1, 14))]
+            ),
+        ],
+        calls
+    );
+}
+
+#[test]
+async fn test_call_hierarchy_outgoing_calls() {
+    let server = create_server();
+    open_call_hierarchy(&server).await;
+
+    let item =
+        prepare_call_hierarchy(&server, lsp::Position::new(0, 1))
+            .await
+            .unwrap()
+            .remove(0);
+    assert_eq!("hot", item.name);
+    let calls = server
+        .outgoing_calls(lsp::CallHierarchyOutgoingCallsParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    // `filter` is called too, but only functions of the package are part of
+    // the hierarchy.
+    let calls: Vec<(&str, &str, Vec<lsp::Range>)> = calls
+        .iter()
+        .map(|call| {
+            (
+                call.to.name.as_str(),
+                call.to.uri.as_str(),
+                call.from_ranges.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "convert",
+                "file:///home/user/helpers.flux",
+                vec![range((1, 7), (1, 14))]
+            ),
+            (
+                "toF",
+                "file:///home/user/helpers.flux",
+                vec![range((2, 36), (2, 39))]
+            ),
+        ],
+        calls
+    );
+}
+
+/// Every top-level expression calls functions, not only pipelines, and the
+/// parameters of a function that shadow a function of the package aren't
+/// calls of it.
+#[test]
+async fn test_call_hierarchy_expressions_and_shadowing() {
+    let server = create_server();
+    let fluxscript = r#"helper = (x=1) => x + 1
+
+shadow = (helper) => helper(x: 2)
+
+helper(x: 1)
+[helper()]
+"#;
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    assert_eq!(
+        None,
+        prepare_call_hierarchy(&server, lsp::Position::new(2, 22))
+            .await
+    );
+
+    let item =
+        prepare_call_hierarchy(&server, lsp::Position::new(0, 1))
+            .await
+            .unwrap()
+            .remove(0);
+    assert_eq!("helper", item.name);
+    let mut calls = server
+        .incoming_calls(lsp::CallHierarchyIncomingCallsParams {
+            item,
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    calls.sort_by(|a, b| a.from.name.cmp(&b.from.name));
+
+    let calls: Vec<(&str, lsp::SymbolKind, Vec<lsp::Range>)> = calls
+        .iter()
+        .map(|call| {
+            (
+                call.from.name.as_str(),
+                call.from.kind,
+                call.from_ranges.clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "[helper()]",
+                lsp::SymbolKind::EVENT,
+                vec![range((5, 1), (5, 7))]
+            ),
+            (
+                "helper(x: 1)",
+                lsp::SymbolKind::EVENT,
+                vec![range((4, 0), (4, 6))]
+            ),
+        ],
+        calls
+    );
+}
+
+/// The ranges of a selection range, from the innermost outwards.
+fn flatten_selection_range(
+    selection: &lsp::SelectionRange,
+) -> Vec<lsp::Range> {
+    let mut ranges = vec![selection.range];
+    let mut parent = selection.parent.as_deref();
+    while let Some(selection) = parent {
+        ranges.push(selection.range);
+        parent = selection.parent.as_deref();
+    }
+    ranges
+}
+
+#[test]
+async fn test_selection_range() {
+    let fluxscript = r#"from(bucket: "b")
+    |> range(start: -1h)
+    |> filter(fn: (r) => r.host == "a")
+"#;
+    let server = create_server();
+    open_file(&server, fluxscript.to_string(), None).await;
+
+    let selections = server
+        .selection_range(lsp::SelectionRangeParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::parse("file:///home/user/file.flux")
+                    .unwrap(),
+            },
+            positions: vec![
+                lsp::Position::new(2, 28),
+                lsp::Position::new(1, 9),
+            ],
+            work_done_progress_params: lsp::WorkDoneProgressParams {
+                work_done_token: None,
+            },
+            partial_result_params: lsp::PartialResultParams {
+                partial_result_token: None,
+            },
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(2, selections.len());
+
+    // The identifier, member expression, comparison, function, argument,
+    // stage and pipeline, then the file.
+    let ranges = flatten_selection_range(&selections[0]);
+    assert_eq!(
+        vec![
+            range((2, 27), (2, 31)),
+            range((2, 25), (2, 31)),
+            range((2, 25), (2, 38)),
+            range((2, 18), (2, 38)),
+            range((2, 14), (2, 38)),
+            range((2, 7), (2, 39)),
+            range((0, 0), (2, 39)),
+        ],
+        ranges[..7]
+    );
+
+    // The call of the stage, and the pipeline up to the stage.
+    let ranges = flatten_selection_range(&selections[1]);
+    assert_eq!(
+        vec![
+            range((1, 7), (1, 12)),
+            range((1, 7), (1, 24)),
+            range((0, 0), (1, 24)),
+            range((0, 0), (2, 39)),
+        ],
+        ranges[..4]
+    );
+}
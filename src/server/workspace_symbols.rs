@@ -0,0 +1,274 @@
+#![allow(deprecated)]
+/// Symbol search across the flux files of the workspace.
+///
+/// The open documents are searched as they are in the editor, and the other
+/// flux files of the workspace folders are indexed once the server is
+/// initialized. There is no file system to index in the browser, so only the
+/// open documents are searched there.
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use flux::ast;
+use lspower::lsp;
+use serde::{Deserialize, Serialize};
+
+use crate::lang;
+
+/// The most symbols returned for a query.
+const MAX_SYMBOLS: usize = 256;
+
+/// Directories that are not searched for flux files, besides hidden ones.
+#[cfg(not(target_arch = "wasm32"))]
+const IGNORED_DIRECTORIES: &[&str] =
+    &["node_modules", "target", "vendor"];
+
+/// The symbols of the files of the workspace, by url.
+pub type Index = HashMap<lsp::Url, Vec<lsp::SymbolInformation>>;
+
+/// Which symbols are searched, from the `workspaceSymbols` settings, e.g.
+///
+/// ```json
+/// {"settings": {"workspaceSymbols": {"stdlib": true}}}
+/// ```
+#[derive(
+    Clone, Debug, Default, Deserialize, PartialEq, Serialize,
+)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WorkspaceSymbolSettings {
+    /// Also search the functions of the stdlib.
+    pub stdlib: bool,
+}
+
+/// Index the flux files of a directory and its subdirectories. Symlinked
+/// directories are not followed, so links back up the tree can't loop.
+#[cfg(not(target_arch = "wasm32"))]
+fn index_directory(directory: &Path, index: &mut Index) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::error!("{}: {}", directory.display(), err);
+            return;
+        }
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(err) => {
+                log::error!("{}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if file_type.is_dir() {
+            if !name.starts_with('.')
+                && !IGNORED_DIRECTORIES.contains(&name)
+            {
+                index_directory(&path, index);
+            }
+        } else if path.extension().map_or(false, |ext| ext == "flux")
+        {
+            if let Some((uri, symbols)) = index_file(&path) {
+                index.insert(uri, symbols);
+            }
+        }
+    }
+}
+
+/// Read the symbols of a flux file on disk.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn index_file(
+    path: &Path,
+) -> Option<(lsp::Url, Vec<lsp::SymbolInformation>)> {
+    let uri = lsp::Url::from_file_path(path).ok()?;
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let file = flux::parser::parse_string(
+                uri.to_string(),
+                &contents,
+            );
+            let symbols = file_symbols(&uri, &file);
+            Some((uri, symbols))
+        }
+        Err(err) => {
+            log::error!("{}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Index the flux files in the workspace folders and their subdirectories.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn index(folders: &[lsp::Url]) -> Index {
+    let mut index = Index::new();
+    for folder in folders
+        .iter()
+        .filter_map(|folder| folder.to_file_path().ok())
+    {
+        index_directory(&folder, &mut index);
+    }
+    index
+}
+
+fn symbol(
+    name: String,
+    kind: lsp::SymbolKind,
+    location: lsp::Location,
+    container_name: Option<String>,
+) -> lsp::SymbolInformation {
+    lsp::SymbolInformation {
+        name,
+        kind,
+        tags: None,
+        deprecated: None,
+        location,
+        container_name,
+    }
+}
+
+/// The variables, functions and options declared at the top level of a file.
+/// The container of a symbol is the package of the file.
+pub fn file_symbols(
+    uri: &lsp::Url,
+    file: &ast::File,
+) -> Vec<lsp::SymbolInformation> {
+    let package = file
+        .package
+        .as_ref()
+        .map(|package| package.name.name.clone());
+    let location = |base: &ast::BaseNode| lsp::Location {
+        uri: uri.clone(),
+        range: base.location.clone().into(),
+    };
+    file.body
+        .iter()
+        .filter_map(|stmt| {
+            let (name, kind, base) = match stmt {
+                ast::Statement::Variable(assign) => (
+                    assign.id.name.clone(),
+                    match &assign.init {
+                        ast::Expression::Function(_) => {
+                            lsp::SymbolKind::FUNCTION
+                        }
+                        _ => lsp::SymbolKind::VARIABLE,
+                    },
+                    &assign.base,
+                ),
+                ast::Statement::Option(opt) => (
+                    match &opt.assignment {
+                        ast::Assignment::Variable(assign) => {
+                            assign.id.name.clone()
+                        }
+                        ast::Assignment::Member(assign) => {
+                            let property =
+                                match &assign.member.property {
+                                    ast::PropertyKey::Identifier(
+                                        key,
+                                    ) => key.name.clone(),
+                                    ast::PropertyKey::StringLit(
+                                        key,
+                                    ) => key.value.clone(),
+                                };
+                            match &assign.member.object {
+                                ast::Expression::Identifier(
+                                    object,
+                                ) => {
+                                    format!(
+                                        "{}.{}",
+                                        object.name, property
+                                    )
+                                }
+                                _ => property,
+                            }
+                        }
+                    },
+                    lsp::SymbolKind::PROPERTY,
+                    &opt.base,
+                ),
+                ast::Statement::Builtin(builtin) => (
+                    builtin.id.name.clone(),
+                    match builtin.ty.monotype {
+                        ast::MonoType::Function(_) => {
+                            lsp::SymbolKind::FUNCTION
+                        }
+                        _ => lsp::SymbolKind::VARIABLE,
+                    },
+                    &builtin.base,
+                ),
+                _ => return None,
+            };
+            Some(symbol(name, kind, location(base), package.clone()))
+        })
+        .collect()
+}
+
+/// The functions of the stdlib, located at their documentation. The functions
+/// of the prelude are named as they are called, and the others with their
+/// package, e.g. `strings.trim`.
+pub fn stdlib_symbols() -> Vec<lsp::SymbolInformation> {
+    let prelude =
+        lang::UNIVERSE.functions().into_iter().map(|function| {
+            (
+                function.name.clone(),
+                lang::UNIVERSE.path.clone(),
+                function,
+            )
+        });
+    let packages = lang::STDLIB
+        .packages()
+        .filter(|package| package.path != "universe")
+        .flat_map(|package| {
+            package
+                .functions()
+                .into_iter()
+                .map(move |function| {
+                    (
+                        format!("{}.{}", package.name, function.name),
+                        package.path.clone(),
+                        function,
+                    )
+                })
+                .collect::<Vec<_>>()
+        });
+    prelude
+        .chain(packages)
+        .filter_map(|(name, path, function)| {
+            let uri = lsp::Url::parse(&function.link()).ok()?;
+            Some(symbol(
+                name,
+                lsp::SymbolKind::FUNCTION,
+                lsp::Location {
+                    uri,
+                    range: lsp::Range::default(),
+                },
+                Some(path),
+            ))
+        })
+        .collect()
+}
+
+/// The symbols that fuzzy match a query, best matches first.
+pub fn search(
+    symbols: Vec<lsp::SymbolInformation>,
+    query: &str,
+) -> Vec<lsp::SymbolInformation> {
+    let mut matches: Vec<(i32, lsp::SymbolInformation)> = symbols
+        .into_iter()
+        .filter_map(|symbol| {
+            crate::fuzzy::score(&symbol.name, query)
+                .map(|score| (score, symbol))
+        })
+        .collect();
+    // The sort is stable, so symbols with the same score stay in the order
+    // they were found in.
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches
+        .into_iter()
+        .take(MAX_SYMBOLS)
+        .map(|(_, symbol)| symbol)
+        .collect()
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use flux::ast::walk;
 use lspower::lsp;
 
@@ -7,6 +9,47 @@ pub struct NodeFinderNode<'a> {
     pub parent: Option<Box<NodeFinderNode<'a>>>,
 }
 
+impl<'a> NodeFinderNode<'a> {
+    /// The selection ranges of the node and its parents, for growing a
+    /// selection a node at a time, e.g. from an identifier to its member
+    /// expression, call, pipeline and statement. A parent with the same range
+    /// as its child, like the statement of an expression, is skipped.
+    pub fn selection_range(&self) -> Option<lsp::SelectionRange> {
+        let mut ranges: Vec<lsp::Range> = vec![];
+        let mut node = Some(self);
+        while let Some(current) = node {
+            let range: lsp::Range =
+                current.node.base().location.clone().into();
+            let grows = match ranges.last() {
+                Some(last) => {
+                    range != *last
+                        && crate::lsp::position_in_range(
+                            &last.start,
+                            &range,
+                        )
+                        && crate::lsp::position_in_range(
+                            &last.end, &range,
+                        )
+                }
+                None => true,
+            };
+            if grows {
+                ranges.push(range);
+            }
+            node = current.parent.as_deref();
+        }
+
+        let mut selection: Option<lsp::SelectionRange> = None;
+        for range in ranges.into_iter().rev() {
+            selection = Some(lsp::SelectionRange {
+                range,
+                parent: selection.map(Box::new),
+            });
+        }
+        selection
+    }
+}
+
 #[derive(Clone)]
 pub struct NodeFinderVisitor<'a> {
     pub node: Option<NodeFinderNode<'a>>,
@@ -62,8 +105,126 @@ impl From<&flux::ast::Package> for PackageInfo {
     }
 }
 
+/// The `//` comments directly above a variable or builtin, e.g.
+///
+/// ```flux
+/// // Convert a temperature to Fahrenheit.
+/// // @param c The temperature in Celsius.
+/// toFahrenheit = (c) => c * 9.0 / 5.0 + 32.0
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocComment {
+    pub description: String,
+    /// The `@param name description` lines, in the order they are written.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl DocComment {
+    /// Parse the comments attached to a statement, or None if there are none.
+    fn parse(comments: &[flux::ast::Comment]) -> Option<Self> {
+        let mut doc = DocComment::default();
+        let mut description: Vec<&str> = vec![];
+        for comment in comments {
+            let line = comment.text.trim_end();
+            let line = line.strip_prefix("//").unwrap_or(line);
+            let line = line.strip_prefix(' ').unwrap_or(line);
+            if let Some(param) = line.strip_prefix("@param") {
+                let mut words = param.trim_start().splitn(2, ' ');
+                if let Some(name) =
+                    words.next().filter(|name| !name.is_empty())
+                {
+                    doc.parameters.push((
+                        name.into(),
+                        words.next().unwrap_or("").trim().into(),
+                    ));
+                }
+            } else if let Some((_, text)) = doc.parameters.last_mut()
+            {
+                // Lines after a parameter continue its description.
+                if !line.trim().is_empty() {
+                    if !text.is_empty() {
+                        text.push(' ');
+                    }
+                    text.push_str(line.trim());
+                }
+            } else {
+                description.push(line);
+            }
+        }
+        doc.description = description.join("\n").trim().into();
+        if doc.description.is_empty() && doc.parameters.is_empty() {
+            None
+        } else {
+            Some(doc)
+        }
+    }
+
+    /// The description of a parameter.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, description)| description.as_str())
+    }
+
+    /// Render the doc comment as markdown.
+    pub fn markdown(&self) -> String {
+        let mut markdown = self.description.clone();
+        if !self.parameters.is_empty() {
+            if !markdown.is_empty() {
+                markdown.push_str("\n\n");
+            }
+            markdown.push_str("**Parameters**\n");
+            for (name, description) in &self.parameters {
+                markdown.push_str(&format!(
+                    "\n- `{}`: {}",
+                    name, description
+                ));
+            }
+        }
+        markdown
+    }
+}
+
+/// Collect the doc comments of the variables and builtins at the top level of
+/// the files of a package, by name.
+#[derive(Default)]
+pub struct DocCommentVisitor {
+    pub docs: HashMap<String, DocComment>,
+}
+
+impl<'a> walk::Visitor<'a> for DocCommentVisitor {
+    fn visit(&mut self, node: walk::Node<'a>) -> bool {
+        // The parser attaches comments to the first token of a statement, which is
+        // the identifier of a variable and the keyword of a builtin. Statements
+        // are not walked into, so the variables of functions are left out.
+        let (name, comments) = match node {
+            walk::Node::Package(_) | walk::Node::File(_) => {
+                return true
+            }
+            walk::Node::VariableAssgn(assign) => (
+                &assign.id.name,
+                [&assign.base.comments, &assign.id.base.comments],
+            ),
+            walk::Node::BuiltinStmt(builtin) => (
+                &builtin.id.name,
+                [&builtin.base.comments, &builtin.id.base.comments],
+            ),
+            _ => return false,
+        };
+        let comments: Vec<flux::ast::Comment> = comments
+            .iter()
+            .flat_map(|comments| comments.iter().cloned())
+            .collect();
+        if let Some(doc) = DocComment::parse(&comments) {
+            self.docs.insert(name.clone(), doc);
+        }
+        false
+    }
+}
+
 macro_rules! semantic_tokens {
-    ($($name: ident => $lsp_name: ident),* $(,)?) => {
+    ($($name: ident => $lsp_name: expr),* $(,)?) => {
         // C-like enumerations can be casted to 0,1,2 etc in order which is exactly what the
         // LSP protocal needs for the mapping
         #[derive(Debug)]
@@ -77,7 +238,7 @@ macro_rules! semantic_tokens {
         impl SemanticToken {
             pub(crate) const LSP_MAPPING: &'static [lsp::SemanticTokenType] = &[
                 $(
-                lsp::SemanticTokenType::$lsp_name,
+                $lsp_name,
                 )*
             ];
         }
@@ -88,162 +249,676 @@ macro_rules! semantic_tokens {
     }
 }
 
+macro_rules! semantic_token_modifiers {
+    ($($name: ident => $lsp_name: expr),* $(,)?) => {
+        // Modifiers are sent as a bitset, where the bit of a modifier is its
+        // index in the legend.
+        #[derive(Debug)]
+        #[allow(non_camel_case_types)]
+        pub(crate) enum SemanticTokenModifier {
+            $(
+            $name,
+            )*
+        }
+
+        impl SemanticTokenModifier {
+            pub(crate) const LSP_MAPPING: &'static [lsp::SemanticTokenModifier] = &[
+                $(
+                $lsp_name,
+                )*
+            ];
+        }
+
+        $(
+        pub(crate) const $name: u32 = 1 << SemanticTokenModifier::$name as u32;
+        )*
+    }
+}
+
 // Constructs an integer <=> string mapping according in accordance to
 // https://microsoft.github.io/language-server-protocol/specifications/specification-3-17/#semanticTokensLegend
 semantic_tokens! {
-    SEMANTIC_TOKEN_KEYWORD => KEYWORD,
-    SEMANTIC_TOKEN_NUMBER => NUMBER,
-    SEMANTIC_TOKEN_STRING => STRING,
+    SEMANTIC_TOKEN_KEYWORD => lsp::SemanticTokenType::KEYWORD,
+    SEMANTIC_TOKEN_NUMBER => lsp::SemanticTokenType::NUMBER,
+    SEMANTIC_TOKEN_STRING => lsp::SemanticTokenType::STRING,
+    SEMANTIC_TOKEN_OPERATOR => lsp::SemanticTokenType::OPERATOR,
+    SEMANTIC_TOKEN_FUNCTION => lsp::SemanticTokenType::FUNCTION,
+    SEMANTIC_TOKEN_PARAMETER => lsp::SemanticTokenType::PARAMETER,
+    SEMANTIC_TOKEN_VARIABLE => lsp::SemanticTokenType::VARIABLE,
+    SEMANTIC_TOKEN_PROPERTY => lsp::SemanticTokenType::PROPERTY,
+    SEMANTIC_TOKEN_REGEXP => lsp::SemanticTokenType::REGEXP,
+    // Durations aren't one of the token types of the spec.
+    SEMANTIC_TOKEN_DURATION => lsp::SemanticTokenType::new("duration"),
+    SEMANTIC_TOKEN_COMMENT => lsp::SemanticTokenType::COMMENT,
+    SEMANTIC_TOKEN_NAMESPACE => lsp::SemanticTokenType::NAMESPACE,
+    SEMANTIC_TOKEN_TYPE => lsp::SemanticTokenType::TYPE,
 }
 
-#[derive(Clone, Default)]
-pub struct SemanticTokenVisitor {
-    pub tokens: Vec<lsp::SemanticToken>,
+semantic_token_modifiers! {
+    SEMANTIC_TOKEN_MODIFIER_DECLARATION => lsp::SemanticTokenModifier::DECLARATION,
+    SEMANTIC_TOKEN_MODIFIER_READONLY => lsp::SemanticTokenModifier::READONLY,
+    SEMANTIC_TOKEN_MODIFIER_DEPRECATED => lsp::SemanticTokenModifier::DEPRECATED,
+    SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY => lsp::SemanticTokenModifier::DEFAULT_LIBRARY,
+}
+
+/// Collect the semantic tokens of a file, with absolute positions. The tokens
+/// are encoded for a response with `crate::lsp::encode_semantic_tokens`.
+pub struct SemanticTokenVisitor<'a> {
+    /// The source of the file, for the tokens that aren't nodes, e.g.
+    /// operators and comments.
+    source: String,
+    tokens: Vec<lsp::SemanticToken>,
+    /// The enclosing nodes of the current node.
+    path: Vec<walk::Node<'a>>,
+    /// The paths of the imported packages, by the name they are used with.
+    imports: HashMap<String, String>,
+    /// The token type and modifiers of the variables declared so far, by name,
+    /// in the file and then in each enclosing function.
+    declarations: Vec<HashMap<String, (u32, u32)>>,
+    /// The parameters of the enclosing functions.
+    parameters: Vec<Vec<String>>,
+    /// The ranges of string and regexp literals, which can contain `//`.
+    literals: Vec<lsp::Range>,
+}
+
+impl<'a> SemanticTokenVisitor<'a> {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.into(),
+            tokens: vec![],
+            path: vec![],
+            imports: HashMap::new(),
+            declarations: vec![HashMap::new()],
+            parameters: vec![],
+            literals: vec![],
+        }
+    }
+
+    /// The tokens of the file, ordered by position, including its comments.
+    pub fn into_tokens(mut self) -> Vec<lsp::SemanticToken> {
+        // Comments aren't nodes, so they are found in the source, outside of
+        // the literals.
+        let starts: Vec<usize> = self
+            .source
+            .match_indices("//")
+            .map(|(index, _)| index)
+            .collect();
+        let mut comment_end = 0;
+        for index in starts {
+            let position =
+                crate::lsp::position_of(&self.source, index);
+            if index < comment_end
+                || self.literals.iter().any(|range| {
+                    range.start <= position && position < range.end
+                })
+            {
+                continue;
+            }
+            comment_end = self.source[index..]
+                .find('\n')
+                .map_or(self.source.len(), |end| index + end);
+            let length = self.source[index..comment_end]
+                .trim_end()
+                .chars()
+                .count() as u32;
+            self.push(position, length, SEMANTIC_TOKEN_COMMENT, 0);
+        }
+
+        self.tokens.sort_by_key(|token| {
+            (token.delta_line, token.delta_start)
+        });
+        self.tokens.dedup_by_key(|token| {
+            (token.delta_line, token.delta_start)
+        });
+        self.tokens
+    }
+
+    fn push(
+        &mut self,
+        start: lsp::Position,
+        length: u32,
+        token_type: u32,
+        modifiers: u32,
+    ) {
+        self.tokens.push(lsp::SemanticToken {
+            delta_line: start.line,
+            delta_start: start.character,
+            length,
+            token_type,
+            token_modifiers_bitset: modifiers,
+        });
+    }
+
+    /// Push a token for a node. Tokens can't span lines, so nodes that do
+    /// don't get one.
+    fn push_node(
+        &mut self,
+        base: &flux::ast::BaseNode,
+        token_type: u32,
+        modifiers: u32,
+    ) {
+        let range: lsp::Range = base.location.clone().into();
+        if range.start.line == range.end.line {
+            self.push(
+                range.start,
+                range.end.character - range.start.character,
+                token_type,
+                modifiers,
+            );
+        }
+    }
+
+    /// Push a token for the text at the start of a node, e.g. its keyword.
+    fn push_prefix(
+        &mut self,
+        base: &flux::ast::BaseNode,
+        text: &str,
+        token_type: u32,
+    ) {
+        self.push(
+            base.location.start.into(),
+            text.chars().count() as u32,
+            token_type,
+            0,
+        );
+    }
+
+    /// Push a token for text that isn't a node, e.g. an operator, found in the
+    /// source between the end of one node and the start of another.
+    fn push_between(
+        &mut self,
+        text: &str,
+        before: &flux::ast::BaseNode,
+        after: &flux::ast::BaseNode,
+        token_type: u32,
+    ) {
+        let from = crate::lsp::offset_of(
+            &self.source,
+            &before.location.end.into(),
+        );
+        let to = crate::lsp::offset_of(
+            &self.source,
+            &after.location.start.into(),
+        );
+        if let Some(index) = from.zip(to).and_then(|(from, to)| {
+            Some(from + self.source.get(from..to)?.find(text)?)
+        }) {
+            self.push(
+                crate::lsp::position_of(&self.source, index),
+                text.chars().count() as u32,
+                token_type,
+                0,
+            );
+        }
+    }
+
+    fn push_literal(&mut self, base: &flux::ast::BaseNode) {
+        self.literals.push(base.location.clone().into());
+    }
+
+    /// The node `n` levels above the current node.
+    fn ancestor(&self, n: usize) -> Option<walk::Node<'a>> {
+        self.path.iter().rev().nth(n).copied()
+    }
+
+    /// The token type and modifiers of an identifier, from the node it is in.
+    fn identifier(
+        &self,
+        ident: &'a flux::ast::Identifier,
+    ) -> Option<(u32, u32)> {
+        let is_key = |key: &flux::ast::PropertyKey| matches!(key, flux::ast::PropertyKey::Identifier(key) if std::ptr::eq(key, ident));
+        Some(match self.ancestor(1)? {
+            walk::Node::PackageClause(_)
+            | walk::Node::ImportDeclaration(_) => (
+                SEMANTIC_TOKEN_NAMESPACE,
+                SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+            ),
+            walk::Node::VariableAssgn(assign)
+                if std::ptr::eq(&assign.id, ident) =>
+            {
+                if let flux::ast::Expression::Function(_) =
+                    assign.init
+                {
+                    (
+                        SEMANTIC_TOKEN_FUNCTION,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+                    )
+                } else if let Some(walk::Node::OptionStmt(_)) =
+                    self.ancestor(2)
+                {
+                    // Options are the variables that can be set again.
+                    (
+                        SEMANTIC_TOKEN_VARIABLE,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+                    )
+                } else {
+                    (
+                        SEMANTIC_TOKEN_VARIABLE,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION
+                            | SEMANTIC_TOKEN_MODIFIER_READONLY,
+                    )
+                }
+            }
+            walk::Node::BuiltinStmt(builtin)
+                if std::ptr::eq(&builtin.id, ident) =>
+            {
+                if let flux::ast::MonoType::Function(_) =
+                    builtin.ty.monotype
+                {
+                    (
+                        SEMANTIC_TOKEN_FUNCTION,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+                    )
+                } else {
+                    (
+                        SEMANTIC_TOKEN_VARIABLE,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION
+                            | SEMANTIC_TOKEN_MODIFIER_READONLY,
+                    )
+                }
+            }
+            walk::Node::Property(property)
+                if is_key(&property.key) =>
+            {
+                match (self.ancestor(2), self.ancestor(3)) {
+                    (Some(walk::Node::FunctionExpr(_)), _) => (
+                        SEMANTIC_TOKEN_PARAMETER,
+                        SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+                    ),
+                    // The keys of the arguments of a call are the names of
+                    // its parameters.
+                    (
+                        Some(walk::Node::ObjectExpr(_)),
+                        Some(walk::Node::CallExpr(_)),
+                    ) => (SEMANTIC_TOKEN_PARAMETER, 0),
+                    _ => (SEMANTIC_TOKEN_PROPERTY, 0),
+                }
+            }
+            walk::Node::MemberExpr(member)
+                if is_key(&member.property) =>
+            {
+                self.member(member, &ident.name)
+            }
+            walk::Node::MonoType(_)
+            | walk::Node::TypeExpression(_)
+            | walk::Node::TypeConstraint(_) => {
+                (SEMANTIC_TOKEN_TYPE, 0)
+            }
+            walk::Node::PropertyType(_) => {
+                (SEMANTIC_TOKEN_PROPERTY, 0)
+            }
+            walk::Node::ParameterType(_) => {
+                (SEMANTIC_TOKEN_PARAMETER, 0)
+            }
+            walk::Node::CallExpr(call) => match &call.callee {
+                flux::ast::Expression::Identifier(callee)
+                    if std::ptr::eq(callee, ident) =>
+                {
+                    self.reference(&ident.name)
+                        .unwrap_or((SEMANTIC_TOKEN_FUNCTION, 0))
+                }
+                _ => self
+                    .reference(&ident.name)
+                    .unwrap_or((SEMANTIC_TOKEN_VARIABLE, 0)),
+            },
+            _ => self
+                .reference(&ident.name)
+                .unwrap_or((SEMANTIC_TOKEN_VARIABLE, 0)),
+        })
+    }
+
+    /// The token type and modifiers of a name that refers to a parameter,
+    /// variable, package or builtin, or None if it is undefined. The innermost
+    /// function that binds the name wins.
+    fn reference(&self, name: &str) -> Option<(u32, u32)> {
+        for (depth, declarations) in
+            self.declarations.iter().enumerate().rev()
+        {
+            if let Some(declaration) = declarations.get(name) {
+                return Some(*declaration);
+            }
+            // The parameters of a function are in the scope of its body.
+            if depth > 0
+                && self.parameters[depth - 1]
+                    .iter()
+                    .any(|p| p == name)
+            {
+                return Some((SEMANTIC_TOKEN_PARAMETER, 0));
+            }
+        }
+        if self.imports.contains_key(name) {
+            return Some((SEMANTIC_TOKEN_NAMESPACE, 0));
+        }
+        crate::lang::UNIVERSE.member(name).map(|typ| match typ {
+            flux::semantic::types::MonoType::Fun(_) => (
+                SEMANTIC_TOKEN_FUNCTION,
+                SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY,
+            ),
+            _ => (
+                SEMANTIC_TOKEN_VARIABLE,
+                SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY
+                    | SEMANTIC_TOKEN_MODIFIER_READONLY,
+            ),
+        })
+    }
+
+    /// The token type and modifiers of the property of a member expression,
+    /// which is a member of a package when the object is an import.
+    fn member(
+        &self,
+        member: &flux::ast::MemberExpr,
+        name: &str,
+    ) -> (u32, u32) {
+        let path = match &member.object {
+            flux::ast::Expression::Identifier(object) => {
+                self.imports.get(&object.name)
+            }
+            _ => None,
+        };
+        let typ = path.and_then(|path| {
+            crate::lang::STDLIB.package(path)?.member(name)
+        });
+        match (path, typ) {
+            (
+                Some(path),
+                Some(flux::semantic::types::MonoType::Fun(_)),
+            ) => {
+                let mut modifiers =
+                    SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY;
+                if crate::lang::deprecated_function(path, name)
+                    .is_some()
+                {
+                    modifiers |= SEMANTIC_TOKEN_MODIFIER_DEPRECATED;
+                }
+                (SEMANTIC_TOKEN_FUNCTION, modifiers)
+            }
+            (Some(_), Some(_)) => (
+                SEMANTIC_TOKEN_VARIABLE,
+                SEMANTIC_TOKEN_MODIFIER_DEFAULT_LIBRARY
+                    | SEMANTIC_TOKEN_MODIFIER_READONLY,
+            ),
+            _ => (SEMANTIC_TOKEN_PROPERTY, 0),
+        }
+    }
 }
 
-impl<'a> walk::Visitor<'a> for SemanticTokenVisitor {
+impl<'a> walk::Visitor<'a> for SemanticTokenVisitor<'a> {
     fn visit(&mut self, node: walk::Node<'a>) -> bool {
+        self.path.push(node);
         match node {
             walk::Node::PackageClause(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = 7; // Length of "package"
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_KEYWORD,
-                    token_modifiers_bitset: 0,
-                });
-
-                // Identifier itself is just too broad. Manually handle
-                // the case for the string identifier.
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line: node.name.base.location.start.line,
-                    delta_start: node.name.base.location.start.column,
-                    length: node.name.base.location.end.column
-                        - node.name.base.location.start.column,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                });
+                self.push_prefix(
+                    &node.base,
+                    "package",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
             }
             walk::Node::ImportDeclaration(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = 6; // Length of "import"
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_KEYWORD,
-                    token_modifiers_bitset: 0,
-                });
+                self.push_prefix(
+                    &node.base,
+                    "import",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+                let name = match &node.alias {
+                    Some(alias) => alias.name.clone(),
+                    None => node
+                        .path
+                        .value
+                        .split('/')
+                        .last()
+                        .unwrap_or_default()
+                        .into(),
+                };
+                self.imports.insert(name, node.path.value.clone());
+            }
+            walk::Node::OptionStmt(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "option",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+            }
+            walk::Node::ReturnStmt(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "return",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+            }
+            // These statements are internal to flux developement.
+            walk::Node::TestCaseStmt(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "testcase",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+            }
+            walk::Node::BuiltinStmt(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "builtin",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+            }
+            walk::Node::Identifier(ident) => {
+                if let Some((token_type, modifiers)) =
+                    self.identifier(ident)
+                {
+                    if modifiers & SEMANTIC_TOKEN_MODIFIER_DECLARATION
+                        != 0
+                        && token_type != SEMANTIC_TOKEN_PARAMETER
+                        && token_type != SEMANTIC_TOKEN_NAMESPACE
+                    {
+                        if let Some(declarations) =
+                            self.declarations.last_mut()
+                        {
+                            declarations.insert(
+                                ident.name.clone(),
+                                (
+                                    token_type,
+                                    modifiers
+                                        & !SEMANTIC_TOKEN_MODIFIER_DECLARATION,
+                                ),
+                            );
+                        }
+                    }
+                    self.push_node(
+                        &ident.base,
+                        token_type,
+                        modifiers,
+                    );
+                }
             }
             walk::Node::IntegerLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                });
+                self.push_node(&node.base, SEMANTIC_TOKEN_NUMBER, 0);
             }
             walk::Node::FloatLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                });
+                self.push_node(&node.base, SEMANTIC_TOKEN_NUMBER, 0);
             }
-            walk::Node::StringLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_STRING,
-                    token_modifiers_bitset: 0,
-                });
+            walk::Node::UintLit(node) => {
+                self.push_node(&node.base, SEMANTIC_TOKEN_NUMBER, 0);
+            }
+            walk::Node::DateTimeLit(node) => {
+                self.push_node(&node.base, SEMANTIC_TOKEN_NUMBER, 0);
             }
             walk::Node::DurationLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                });
+                self.push_node(
+                    &node.base,
+                    SEMANTIC_TOKEN_DURATION,
+                    0,
+                );
             }
-            walk::Node::UintLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                });
+            walk::Node::StringLit(node) => {
+                self.push_literal(&node.base);
+                self.push_node(&node.base, SEMANTIC_TOKEN_STRING, 0);
             }
-            walk::Node::DateTimeLit(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = node.base.location.end.column
-                    - node.base.location.start.column;
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_NUMBER,
-                    token_modifiers_bitset: 0,
-                });
+            walk::Node::RegexpLit(node) => {
+                self.push_literal(&node.base);
+                self.push_node(&node.base, SEMANTIC_TOKEN_REGEXP, 0);
             }
-            // These statements are internal to flux developement.
-            walk::Node::TestCaseStmt(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = 7; // Length of "builtin"
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_KEYWORD,
-                    token_modifiers_bitset: 0,
-                });
+            // A string with `${}` interpolation is a string expression. Only the
+            // quotes and text parts are string tokens, so the interpolated
+            // expressions get their own tokens.
+            walk::Node::StringExpr(node) => {
+                self.push_literal(&node.base);
+                self.push_prefix(
+                    &node.base,
+                    "\"",
+                    SEMANTIC_TOKEN_STRING,
+                );
             }
-            walk::Node::BuiltinStmt(node) => {
-                let delta_line = node.base.location.start.line;
-                let delta_start = node.base.location.start.column;
-                let length = 8; // Length of "testcase"
-                self.tokens.push(lsp::SemanticToken {
-                    delta_line,
-                    delta_start,
-                    length,
-                    token_type: SEMANTIC_TOKEN_KEYWORD,
-                    token_modifiers_bitset: 0,
-                });
+            walk::Node::TextPart(node) => {
+                self.push_node(&node.base, SEMANTIC_TOKEN_STRING, 0);
+            }
+            walk::Node::InterpolatedPart(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "${",
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::FunctionExpr(node) => {
+                self.declarations.push(HashMap::new());
+                self.parameters.push(
+                    node.params
+                        .iter()
+                        .filter_map(|param| match &param.key {
+                            flux::ast::PropertyKey::Identifier(
+                                key,
+                            ) => Some(key.name.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                );
+                let body = match &node.body {
+                    flux::ast::FunctionBody::Block(block) => {
+                        &block.base
+                    }
+                    flux::ast::FunctionBody::Expr(expr) => {
+                        expr.base()
+                    }
+                };
+                let before = node
+                    .params
+                    .last()
+                    .map_or(&node.base, |param| &param.base);
+                self.push_between(
+                    "=>",
+                    before,
+                    body,
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::PipeLit(node) => {
+                self.push_node(
+                    &node.base,
+                    SEMANTIC_TOKEN_OPERATOR,
+                    0,
+                );
+            }
+            walk::Node::PipeExpr(node) => {
+                self.push_between(
+                    "|>",
+                    node.argument.base(),
+                    &node.call.base,
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::BinaryExpr(node) => {
+                self.push_between(
+                    &node.operator.to_string(),
+                    node.left.base(),
+                    node.right.base(),
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::LogicalExpr(node) => {
+                self.push_between(
+                    &node.operator.to_string(),
+                    node.left.base(),
+                    node.right.base(),
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::UnaryExpr(node) => {
+                self.push_prefix(
+                    &node.base,
+                    &node.operator.to_string(),
+                    SEMANTIC_TOKEN_OPERATOR,
+                );
+            }
+            walk::Node::ConditionalExpr(node) => {
+                self.push_prefix(
+                    &node.base,
+                    "if",
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+                self.push_between(
+                    "then",
+                    node.test.base(),
+                    node.consequent.base(),
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+                self.push_between(
+                    "else",
+                    node.consequent.base(),
+                    node.alternate.base(),
+                    SEMANTIC_TOKEN_KEYWORD,
+                );
+            }
+            walk::Node::ObjectExpr(node) => {
+                if let (Some(with), Some(property)) =
+                    (&node.with, node.properties.first())
+                {
+                    self.push_between(
+                        "with",
+                        &with.source.base,
+                        &property.base,
+                        SEMANTIC_TOKEN_KEYWORD,
+                    );
+                }
             }
             _ => {}
         }
         true
     }
+
+    // The closing delimiters of a string expression come after the tokens of
+    // the nodes inside it.
+    fn done(&mut self, node: walk::Node<'a>) {
+        self.path.pop();
+        match node {
+            walk::Node::FunctionExpr(_) => {
+                self.declarations.pop();
+                self.parameters.pop();
+            }
+            walk::Node::InterpolatedPart(node) => {
+                let end: lsp::Position =
+                    node.base.location.end.into();
+                self.push(
+                    lsp::Position {
+                        line: end.line,
+                        character: end.character - 1,
+                    },
+                    1, // Length of "}"
+                    SEMANTIC_TOKEN_OPERATOR,
+                    0,
+                );
+            }
+            walk::Node::StringExpr(node) => {
+                let end: lsp::Position =
+                    node.base.location.end.into();
+                self.push(
+                    lsp::Position {
+                        line: end.line,
+                        character: end.character - 1,
+                    },
+                    1, // Length of the closing quote
+                    SEMANTIC_TOKEN_STRING,
+                    0,
+                );
+            }
+            _ => {}
+        }
+    }
 }
@@ -0,0 +1,95 @@
+use flux::ast::Operator;
+use flux::semantic::nodes::{CallExpr, Expression, StringLit};
+use flux::semantic::walk::{self, Node, Visitor};
+
+/// The name of the function being called, e.g. `from` in both `from()` and
+/// `influxdb.from()`.
+fn callee_name(call: &CallExpr) -> Option<&str> {
+    match &call.callee {
+        Expression::Identifier(ident) => Some(&ident.name),
+        Expression::Member(member) => Some(&member.property),
+        _ => None,
+    }
+}
+
+/// Return true if a call is part of a pipeline whose source is `buckets()`.
+fn is_buckets_pipeline(call: &CallExpr) -> bool {
+    let mut call = call;
+    while let Some(Expression::Call(source)) = &call.pipe {
+        call = source;
+    }
+    call.pipe.is_none() && callee_name(call) == Some("buckets")
+}
+
+/// Find the string literals compared with the `name` column, e.g. the `"my-bucket"`
+/// in `filter(fn: (r) => r.name == "my-bucket")`.
+#[derive(Default)]
+struct NameComparisonVisitor<'a> {
+    literals: Vec<&'a StringLit>,
+}
+
+impl<'a> Visitor<'a> for NameComparisonVisitor<'a> {
+    fn visit(&mut self, node: Node<'a>) -> bool {
+        if let Node::BinaryExpr(binary) = node {
+            if matches!(
+                binary.operator,
+                Operator::EqualOperator | Operator::NotEqualOperator
+            ) {
+                match (&binary.left, &binary.right) {
+                    (
+                        Expression::Member(member),
+                        Expression::StringLit(lit),
+                    )
+                    | (
+                        Expression::StringLit(lit),
+                        Expression::Member(member),
+                    ) if member.property == "name" => {
+                        self.literals.push(lit)
+                    }
+                    _ => (),
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Find the string literals that name a bucket.
+///
+/// These are the `bucket` arguments of `from` and `to`, and the names compared
+/// against the results of `buckets()`.
+#[derive(Default)]
+pub struct BucketLiteralVisitor<'a> {
+    pub literals: Vec<&'a StringLit>,
+}
+
+impl<'a> Visitor<'a> for BucketLiteralVisitor<'a> {
+    fn visit(&mut self, node: Node<'a>) -> bool {
+        if let Node::CallExpr(call) = node {
+            if matches!(callee_name(call), Some("from") | Some("to"))
+            {
+                for argument in &call.arguments {
+                    match &argument.value {
+                        Expression::StringLit(lit)
+                            if argument.key.name == "bucket" =>
+                        {
+                            self.literals.push(lit)
+                        }
+                        _ => (),
+                    }
+                }
+            } else if is_buckets_pipeline(call) {
+                for argument in &call.arguments {
+                    let mut visitor =
+                        NameComparisonVisitor::default();
+                    walk::walk(
+                        &mut visitor,
+                        Node::Property(argument),
+                    );
+                    self.literals.extend(visitor.literals);
+                }
+            }
+        }
+        true
+    }
+}
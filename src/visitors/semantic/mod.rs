@@ -1,14 +1,16 @@
 use flux::semantic::{
-    nodes::{Expression, Symbol},
+    nodes::{Block, CallExpr, Expression, Symbol},
     walk::{self, Node, Visitor},
 };
 use lspower::lsp;
 
+mod buckets;
 mod completion;
 mod functions;
 mod lint;
 mod symbols;
 
+pub use buckets::BucketLiteralVisitor;
 pub use completion::{
     FunctionFinderVisitor, ObjectFunctionFinderVisitor,
 };
@@ -16,7 +18,7 @@ pub use lint::{
     ContribDiagnosticVisitor, ExperimentalDiagnosticVisitor,
     InfluxDBIdentifierDiagnosticVisitor,
 };
-pub use symbols::SymbolsVisitor;
+pub use symbols::{document_symbols, pipeline_name_of};
 
 fn contains_position(node: Node<'_>, pos: lsp::Position) -> bool {
     if let Node::Package(_) = node {
@@ -79,6 +81,37 @@ impl<'a> NodeFinderVisitor<'a> {
     }
 }
 
+/// Whether a name is bound by one of the functions in the path to a node, as a
+/// parameter or a variable of its body, rather than at the top level of a file.
+pub fn is_bound_in_function(path: &[Node<'_>], name: &str) -> bool {
+    path.iter().any(|node| {
+        let func = match node {
+            Node::FunctionExpr(func) => func,
+            _ => return false,
+        };
+        if func
+            .params
+            .iter()
+            .any(|param| param.key.name.as_str() == name)
+        {
+            return true;
+        }
+        let mut block = &func.body;
+        loop {
+            match block {
+                Block::Variable(assign, next) => {
+                    if assign.id.name.as_str() == name {
+                        return true;
+                    }
+                    block = next;
+                }
+                Block::Expr(_, next) => block = next,
+                Block::Return(_) => return false,
+            }
+        }
+    })
+}
+
 pub struct IdentFinderVisitor<'a> {
     pub name: Symbol,
     pub identifiers: Vec<walk::Node<'a>>,
@@ -163,6 +196,25 @@ impl<'a> DefinitionFinderVisitor<'a> {
     }
 }
 
+/// Finds the calls of functions by their name, e.g. `f(x: 1)`, rather than
+/// calls of members like `strings.trim(v: s)`.
+#[derive(Default)]
+pub struct CallFinderVisitor<'a> {
+    pub calls: Vec<&'a CallExpr>,
+}
+
+impl<'a> Visitor<'a> for CallFinderVisitor<'a> {
+    fn visit(&mut self, node: Node<'a>) -> bool {
+        if let Node::CallExpr(call) = node {
+            if let Expression::Identifier(_) = call.callee {
+                self.calls.push(call);
+            }
+        }
+
+        true
+    }
+}
+
 #[derive(Default)]
 pub struct FoldFinderVisitor<'a> {
     pub nodes: Vec<Node<'a>>,
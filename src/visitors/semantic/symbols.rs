@@ -1,302 +1,280 @@
 #![allow(deprecated)]
 
-use flux::semantic::nodes::{self, Expression};
-use flux::semantic::walk::{Node, Visitor};
+use flux::semantic::nodes::{
+    self, Assignment, Block, Expression, Statement,
+};
+use flux::semantic::types::MonoType;
 use lspower::lsp;
 
-fn parse_variable_assignment(
-    uri: lsp::Url,
-    node: Node,
-    va: &nodes::VariableAssgn,
-) -> Vec<lsp::SymbolInformation> {
-    if let Expression::Function(f) = va.init.clone() {
-        vec![lsp::SymbolInformation {
-            kind: lsp::SymbolKind::FUNCTION,
-            name: va.id.name.to_string(),
-            location: lsp::Location {
-                uri: uri.clone(),
-                range: node.loc().clone().into(),
-            },
-            tags: None,
-            deprecated: None,
-            container_name: None,
-        }]
-        .into_iter()
-        .chain(f.params.into_iter().map(|param| {
-            lsp::SymbolInformation {
-                kind: lsp::SymbolKind::VARIABLE,
-                name: param.key.name.to_string(),
-                location: lsp::Location {
-                    uri: uri.clone(),
-                    range: param.loc.into(),
-                },
-                tags: None,
-                deprecated: None,
-                container_name: None,
-            }
-        }))
-        .collect()
-    } else {
-        vec![lsp::SymbolInformation {
-            kind: lsp::SymbolKind::VARIABLE,
-            name: va.id.name.to_string(),
-            location: lsp::Location {
-                uri,
-                range: node.loc().clone().into(),
-            },
-            tags: None,
-            deprecated: None,
-            container_name: None,
-        }]
+fn symbol(
+    name: String,
+    detail: Option<String>,
+    kind: lsp::SymbolKind,
+    range: lsp::Range,
+    selection_range: lsp::Range,
+    children: Vec<lsp::DocumentSymbol>,
+) -> lsp::DocumentSymbol {
+    lsp::DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children: if children.is_empty() {
+            None
+        } else {
+            Some(children)
+        },
     }
 }
 
-fn parse_call_expression(
-    uri: lsp::Url,
-    c: &nodes::CallExpr,
-) -> Vec<lsp::SymbolInformation> {
-    let initial_symbols =
-        if let Expression::Identifier(ident) = c.callee.clone() {
-            vec![lsp::SymbolInformation {
-                kind: lsp::SymbolKind::FUNCTION,
-                name: ident.name.to_string(),
-                location: lsp::Location {
-                    uri: uri.clone(),
-                    range: c.loc.clone().into(),
-                },
-                tags: None,
-                deprecated: None,
-                container_name: None,
-            }]
-        } else {
-            vec![]
-        };
-
-    initial_symbols
-        .into_iter()
-        .chain(c.arguments.clone().into_iter().map(|arg| {
-            if let Expression::Function(_) = arg.value {
-                lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::FUNCTION,
-                    name: arg.key.name.to_string(),
-                    location: lsp::Location {
-                        uri: uri.clone(),
-                        range: arg.loc.into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                }
-            } else {
-                lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::VARIABLE,
-                    name: arg.key.name.to_string(),
-                    location: lsp::Location {
-                        uri: uri.clone(),
-                        range: arg.loc.into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                }
-            }
-        }))
-        .collect()
+/// The calls of a pipeline, from its source to its last stage, and the
+/// expression the pipeline starts from when it isn't a call, e.g. a variable.
+fn pipeline_stages(
+    call: &nodes::CallExpr,
+) -> (Vec<&nodes::CallExpr>, Option<&Expression>) {
+    match &call.pipe {
+        Some(Expression::Call(pipe)) => {
+            let (mut stages, source) = pipeline_stages(pipe);
+            stages.push(call);
+            (stages, source)
+        }
+        pipe => (vec![call], pipe.as_ref()),
+    }
 }
 
-fn parse_binary_expression(
-    uri: lsp::Url,
-    be: &nodes::BinaryExpr,
-) -> Vec<lsp::SymbolInformation> {
-    #[allow(clippy::expect_used)]
-    vec![
-        if let Expression::Identifier(ident) = be.left.clone() {
-            Some(lsp::SymbolInformation {
-                kind: lsp::SymbolKind::VARIABLE,
-                name: ident.name.to_string(),
-                location: lsp::Location {
-                    uri: uri.clone(),
-                    range: ident.loc.into(),
-                },
-                tags: None,
-                deprecated: None,
-                container_name: None,
-            })
-        } else {
-            None
-        },
-        if let Expression::Identifier(ident) = be.right.clone() {
-            Some(lsp::SymbolInformation {
-                kind: lsp::SymbolKind::VARIABLE,
-                name: ident.name.to_string(),
-                location: lsp::Location {
-                    uri,
-                    range: ident.loc.into(),
-                },
-                tags: None,
-                deprecated: None,
-                container_name: None,
-            })
-        } else {
-            None
+fn callee_name(call: &nodes::CallExpr) -> Option<String> {
+    match &call.callee {
+        Expression::Identifier(ident) => Some(ident.name.to_string()),
+        Expression::Member(member) => match &member.object {
+            Expression::Identifier(object) => {
+                Some(format!("{}.{}", object.name, member.property))
+            }
+            _ => Some(member.property.to_string()),
         },
-    ]
-    .into_iter()
-    .filter(|item| !item.is_none())
-    .map(|item| item.expect("Previous filter call failed"))
-    .collect()
+        _ => None,
+    }
 }
 
-pub struct SymbolsVisitor<'a> {
-    pub symbols: Vec<lsp::SymbolInformation>,
-    pub uri: lsp::Url,
-    pub path: Vec<Node<'a>>,
+fn string_argument(
+    call: &nodes::CallExpr,
+    name: &str,
+) -> Option<String> {
+    call.arguments
+        .iter()
+        .find(|argument| argument.key.name.as_str() == name)
+        .and_then(|argument| match &argument.value {
+            Expression::StringLit(lit) => Some(lit.value.clone()),
+            _ => None,
+        })
 }
 
-impl<'a> SymbolsVisitor<'a> {
-    pub fn new(uri: lsp::Url) -> SymbolsVisitor<'a> {
-        SymbolsVisitor {
-            path: vec![],
-            symbols: vec![],
-            uri,
-        }
+/// The name of a pipeline: the name of the table it yields, or else the bucket
+/// it reads, or else the variable it starts from.
+fn pipeline_name(
+    stages: &[&nodes::CallExpr],
+    source: Option<&Expression>,
+) -> String {
+    let stage = |name: &str| {
+        stages
+            .iter()
+            .rev()
+            .find(|call| callee_name(call).as_deref() == Some(name))
+    };
+    if let Some(call) = stage("yield") {
+        // Unnamed results are yielded as `_result`.
+        return string_argument(call, "name")
+            .unwrap_or_else(|| "_result".into());
+    }
+    if let Some(bucket) =
+        stage("from").and_then(|call| string_argument(call, "bucket"))
+    {
+        return bucket;
+    }
+    match source {
+        Some(Expression::Identifier(ident)) => ident.name.to_string(),
+        _ => "pipeline".into(),
     }
 }
 
-impl<'a> SymbolsVisitor<'a> {}
-
-impl<'a> Visitor<'a> for SymbolsVisitor<'a> {
-    fn done(&mut self, _: Node<'a>) {
-        self.path.pop();
+/// The stages of a pipeline and the expression it starts from, or None if the
+/// expression isn't a pipeline.
+fn pipeline(
+    expr: &Expression,
+) -> Option<(Vec<&nodes::CallExpr>, Option<&Expression>)> {
+    match expr {
+        Expression::Call(call) if call.pipe.is_some() => {
+            Some(pipeline_stages(call))
+        }
+        _ => None,
     }
+}
 
-    fn visit(&mut self, node: Node<'a>) -> bool {
-        let uri = self.uri.clone();
+/// The name of a pipeline in the outline, or None if the expression isn't a
+/// pipeline.
+pub fn pipeline_name_of(expr: &Expression) -> Option<String> {
+    let (stages, source) = pipeline(expr)?;
+    Some(pipeline_name(&stages, source))
+}
 
-        self.path.push(node);
+fn stage_symbols(
+    stages: &[&nodes::CallExpr],
+) -> Vec<lsp::DocumentSymbol> {
+    stages
+        .iter()
+        .filter_map(|call| {
+            Some(symbol(
+                callee_name(call)?,
+                call.loc.source.clone(),
+                lsp::SymbolKind::FUNCTION,
+                call.loc.clone().into(),
+                call.callee.loc().clone().into(),
+                vec![],
+            ))
+        })
+        .collect()
+}
 
-        match node {
-            Node::VariableAssgn(va) => {
-                let list = parse_variable_assignment(uri, node, va);
+fn function_symbols(
+    func: &nodes::FunctionExpr,
+) -> Vec<lsp::DocumentSymbol> {
+    let params = func.params.iter().map(|param| {
+        symbol(
+            param.key.name.to_string(),
+            func.typ
+                .parameter(param.key.name.as_str())
+                .map(|typ| typ.to_string()),
+            lsp::SymbolKind::VARIABLE,
+            param.loc.clone().into(),
+            param.key.loc.clone().into(),
+            vec![],
+        )
+    });
 
-                for si in list {
-                    self.symbols.push(si);
-                }
+    // The variables and functions declared in the body of the function.
+    let mut declarations = vec![];
+    let mut block = &func.body;
+    loop {
+        match block {
+            Block::Variable(assign, next) => {
+                declarations.push(variable_symbol(assign));
+                block = next;
             }
-            Node::CallExpr(c) => {
-                let list = parse_call_expression(uri, c);
+            Block::Expr(_, next) => block = next,
+            Block::Return(_) => break,
+        }
+    }
+    params.chain(declarations).collect()
+}
 
-                for si in list {
-                    self.symbols.push(si);
-                }
-            }
-            Node::BinaryExpr(be) => {
-                let list = parse_binary_expression(uri, be);
+fn variable_symbol(
+    assign: &nodes::VariableAssgn,
+) -> lsp::DocumentSymbol {
+    let name = assign.id.name.to_string();
+    let range = assign.loc.clone().into();
+    let selection_range = assign.id.loc.clone().into();
+    if let Expression::Function(func) = &assign.init {
+        return symbol(
+            name,
+            Some(func.typ.to_string()),
+            lsp::SymbolKind::FUNCTION,
+            range,
+            selection_range,
+            function_symbols(func),
+        );
+    }
+    let children = match pipeline(&assign.init) {
+        Some((stages, _)) => stage_symbols(&stages),
+        None => vec![],
+    };
+    symbol(
+        name,
+        Some(assign.init.type_of().to_string()),
+        lsp::SymbolKind::VARIABLE,
+        range,
+        selection_range,
+        children,
+    )
+}
 
-                for si in list {
-                    self.symbols.push(si);
-                }
-            }
-            Node::MemberExpr(me) => {
-                if let Some(source) = me.loc.source.clone() {
-                    self.symbols.push(lsp::SymbolInformation {
-                        kind: lsp::SymbolKind::OBJECT,
-                        name: source,
-                        location: lsp::Location {
-                            uri,
-                            range: me.loc.clone().into(),
-                        },
-                        tags: None,
-                        deprecated: None,
-                        container_name: None,
-                    });
-                }
-            }
-            Node::FloatLit(num) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::NUMBER,
-                    name: num.value.to_string(),
-                    location: lsp::Location {
-                        uri,
-                        range: num.loc.clone().into(),
+fn statement_symbol(stmt: &Statement) -> Option<lsp::DocumentSymbol> {
+    match stmt {
+        Statement::Variable(assign) => Some(variable_symbol(assign)),
+        Statement::Option(opt) => {
+            let (name, selection_range) = match &opt.assignment {
+                Assignment::Variable(assign) => (
+                    assign.id.name.to_string(),
+                    assign.id.loc.clone().into(),
+                ),
+                Assignment::Member(assign) => (
+                    match &assign.member.object {
+                        Expression::Identifier(object) => format!(
+                            "{}.{}",
+                            object.name, assign.member.property
+                        ),
+                        _ => assign.member.property.to_string(),
                     },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                });
-                return false;
-            }
-            Node::IntegerLit(num) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::NUMBER,
-                    name: num.value.to_string(),
-                    location: lsp::Location {
-                        uri,
-                        range: num.loc.clone().into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                });
-                return false;
-            }
-            Node::DateTimeLit(d) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::CONSTANT,
-                    name: d.value.to_string(),
-                    location: lsp::Location {
-                        uri,
-                        range: d.loc.clone().into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                });
-                return false;
-            }
-            Node::BooleanLit(b) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::BOOLEAN,
-                    name: b.value.to_string(),
-                    location: lsp::Location {
-                        uri,
-                        range: b.loc.clone().into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                });
-                return false;
-            }
-            Node::StringLit(s) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::STRING,
-                    name: s.value.clone(),
-                    location: lsp::Location {
-                        uri,
-                        range: s.loc.clone().into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                });
-                return false;
-            }
-            Node::ArrayExpr(a) => {
-                self.symbols.push(lsp::SymbolInformation {
-                    kind: lsp::SymbolKind::ARRAY,
-                    name: String::from("[]"),
-                    location: lsp::Location {
-                        uri,
-                        range: a.loc.clone().into(),
-                    },
-                    tags: None,
-                    deprecated: None,
-                    container_name: None,
-                })
-            }
-            _ => (),
+                    assign.member.loc.clone().into(),
+                ),
+            };
+            Some(symbol(
+                name,
+                Some("option".into()),
+                lsp::SymbolKind::PROPERTY,
+                opt.loc.clone().into(),
+                selection_range,
+                vec![],
+            ))
+        }
+        Statement::Builtin(builtin) => Some(symbol(
+            builtin.id.name.to_string(),
+            Some(builtin.typ_expr.to_string()),
+            match builtin.typ_expr.expr {
+                MonoType::Fun(_) => lsp::SymbolKind::FUNCTION,
+                _ => lsp::SymbolKind::VARIABLE,
+            },
+            builtin.loc.clone().into(),
+            builtin.id.loc.clone().into(),
+            vec![],
+        )),
+        Statement::Expr(stmt) => {
+            let (stages, source) = pipeline(&stmt.expression)?;
+            let range: lsp::Range = stmt.loc.clone().into();
+            Some(symbol(
+                pipeline_name(&stages, source),
+                Some("pipeline".into()),
+                lsp::SymbolKind::EVENT,
+                range,
+                range,
+                stage_symbols(&stages),
+            ))
         }
-        true
+        _ => None,
     }
 }
+
+/// The outline of a file: its imports, options, variables, functions with
+/// their parameters, and pipelines with their stages.
+pub fn document_symbols(
+    file: &nodes::File,
+) -> Vec<lsp::DocumentSymbol> {
+    file.imports
+        .iter()
+        .map(|import| {
+            symbol(
+                import.path.value.clone(),
+                import
+                    .alias
+                    .as_ref()
+                    .map(|alias| alias.name.to_string()),
+                lsp::SymbolKind::MODULE,
+                import.loc.clone().into(),
+                import.path.loc.clone().into(),
+                vec![],
+            )
+        })
+        .chain(file.body.iter().filter_map(statement_symbol))
+        .collect()
+}
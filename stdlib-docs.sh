@@ -0,0 +1,26 @@
+#!/bin/bash
+# Regenerate data/stdlib-docs.json, the stdlib documentation bundled into the
+# server, with the `fluxdoc` tool of the flux version pinned in Cargo.toml.
+# Run it whenever the flux dependency is updated.
+set -e
+
+FLUX_TAG=$(sed -n -E 's/^flux = .*tag *= *"([^"]+)".*/\1/p' Cargo.toml)
+if [[ -z "$FLUX_TAG" ]]; then
+    echo "Could not find the flux tag in Cargo.toml."
+    exit 1
+fi
+
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+git clone --quiet --depth 1 --branch "$FLUX_TAG" \
+    https://github.com/influxdata/flux.git "$WORK_DIR/flux"
+(
+    cd "$WORK_DIR/flux/libflux"
+    cargo run --quiet --release --bin fluxdoc -- \
+        dump --dir ../stdlib --output "$WORK_DIR/docs.json"
+)
+
+cp "$WORK_DIR/docs.json" data/stdlib-docs.json
+
+echo "Updated data/stdlib-docs.json from flux $FLUX_TAG"